@@ -50,7 +50,10 @@ fn main() {
 
     let cfg  = b.build().unwrap();
     let node = Node::new(&cfg).unwrap();
-    let _ = node.start();
+    if let Err(e) = node.start() {
+        eprintln!("{e}");
+        return;
+    }
 
     thread::sleep(Duration::from_secs(60*100));
     node.stop();
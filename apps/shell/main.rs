@@ -31,9 +31,16 @@ async fn main() {
     }
 
     let node = Node::new(&cfg).unwrap();
-    let _ = node.start();
+    if let Err(e) = node.start() {
+        eprintln!("{e}");
+        return;
+    }
 
-    thread::sleep(Duration::from_secs(1));
+    if let Err(e) = node.wait_ready(Duration::from_secs(30)).await {
+        eprintln!("{e}");
+        node.stop();
+        return;
+    }
 
     let target: Id = "HZXXs9LTfNQjrDKvvexRhuMk8TTJhYCfrHwaj3jUzuhZ".try_into().unwrap();
     println!("Attemp finding node with id: {} ...", target);
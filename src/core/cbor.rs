@@ -36,18 +36,14 @@ impl<'a> ciborium_io::Read for Reader<'a> {
     type Error = std::io::Error;
 
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
-        if self.data.len() < self.pos {
+        let remaining_len = self.data.len().saturating_sub(self.pos);
+        if remaining_len < buf.len() {
+            self.pos = self.data.len();
             return Err(Self::Error::from(std::io::ErrorKind::UnexpectedEof));
         }
 
-        let remaining_len = self.data.len() - self.pos;
-        if remaining_len >= buf.len() {
-            buf.copy_from_slice(&self.data[self.pos..self.pos + buf.len()]);
-            self.pos += buf.len();
-        } else {
-            buf.copy_from_slice(&self.data[self.pos..]);
-            self.pos = self.data.len();
-        }
+        buf.copy_from_slice(&self.data[self.pos..self.pos + buf.len()]);
+        self.pos += buf.len();
         Ok(())
     }
 }
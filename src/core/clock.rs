@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex, Weak};
+use std::cell::RefCell;
+use std::borrow::Borrow;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+
+// Source of the current time for the maintenance work of a node, such as
+// the scheduler, token rotation, bucket refreshing and storage expiry.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    // Register the notifier of a running node, to be signaled whenever the
+    // time is moved other than by its passage. Nothing to do for the clocks
+    // following the real time.
+    fn watch(&self, _notifier: Weak<Notify>) {}
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// Virtual clock which stays still until being advanced explicitly.
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+    watchers: Mutex<Vec<Weak<Notify>>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::now())
+    }
+
+    pub fn starting_at(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        self.changed();
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+        self.changed();
+    }
+
+    // Wake up the nodes running on this clock, dropping the stopped ones.
+    fn changed(&self) {
+        self.watchers.lock().unwrap().retain(|v| match v.upgrade() {
+            Some(notifier) => {
+                notifier.notify_one();
+                true
+            },
+            None => false,
+        });
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    fn watch(&self, notifier: Weak<Notify>) {
+        self.watchers.lock().unwrap().push(notifier);
+    }
+}
+
+// The clock of the node being run on the current thread, which is only
+// entered for as long as the node is polled, as several nodes might take
+// turns on the same thread.
+thread_local! {
+    static CLOCK: RefCell<Option<Arc<dyn Clock>>> = const { RefCell::new(None) };
+}
+
+// Enter the given clock on the current thread, returning the one entered
+// before to be restored later.
+pub(crate) fn enter(clock: Option<Arc<dyn Clock>>) -> Option<Arc<dyn Clock>> {
+    CLOCK.with(|v| v.replace(clock))
+}
+
+#[cfg(test)]
+pub(crate) fn set(clock: Arc<dyn Clock>) {
+    enter(Some(clock));
+}
+
+#[cfg(test)]
+pub(crate) fn reset() {
+    enter(None);
+}
+
+pub(crate) fn now() -> SystemTime {
+    CLOCK.with(|v| match v.borrow().as_ref() {
+        Some(clock) => clock.now(),
+        None => SystemTime::now(),
+    })
+}
+
+pub(crate) fn elapsed<T: Borrow<SystemTime>>(since: T) -> Duration {
+    now().duration_since(*since.borrow()).unwrap_or_default()
+}
+
+pub(crate) fn elapsed_until<T: Borrow<SystemTime>>(until: T) -> Duration {
+    until.borrow().duration_since(now()).unwrap_or_default()
+}
@@ -1,6 +1,9 @@
 use std::net::SocketAddr;
 use log::LevelFilter;
-use crate::core::node_info::NodeInfo;
+use crate::core::{
+    constants,
+    node_info::NodeInfo,
+};
 
 pub trait ActiveProxyConfig: Send + Sync {
     fn server_peerid(&self) -> &str;
@@ -19,6 +22,25 @@ pub trait Config: Send + Sync {
     fn storage_path(&self) -> &str;
     fn bootstrap_nodes(&self) -> &[NodeInfo];
 
+    fn max_datagram_size(&self) -> usize {
+        constants::MAX_DATAGRAM_SIZE
+    }
+
+    // Limits of the routing table entries from the same IPv4 /24 or
+    // IPv6 /64 network, per bucket and for the whole table.
+    fn bucket_subnet_limit(&self) -> usize {
+        constants::BUCKET_SUBNET_LIMIT
+    }
+    fn table_subnet_limit(&self) -> usize {
+        constants::TABLE_SUBNET_LIMIT
+    }
+
+    // Takes the nodes on the loopback addresses into the routing table,
+    // which only makes sense for the nodes run on the same host in tests.
+    fn loopback_allowed(&self) -> bool {
+        false
+    }
+
     fn log_level(&self) -> LevelFilter;
     fn log_file(&self) -> Option<&str>;
 
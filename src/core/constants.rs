@@ -1,6 +1,13 @@
 pub(crate) const DEFAULT_DHT_PORT: u16 = 39001;
 
 pub(crate) const MAX_ENTRIES_PER_BUCKET: usize = 8;
+// Replacement candidates kept for a full bucket.
+pub(crate) const MAX_CACHE_ENTRIES_PER_BUCKET: usize = 8;
+
+// Entries from the same IPv4 /24 or IPv6 /64 network allowed in a bucket,
+// and in the whole routing table, replacement candidates included.
+pub(crate) const BUCKET_SUBNET_LIMIT: usize = 2;
+pub(crate) const TABLE_SUBNET_LIMIT: usize = 10;
 
 // Refresh interval for a bucket in milliseconds
 pub(crate) const BUCKET_REFRESH_INTERVAL: u128 = 15 * 60 * 1000;
@@ -29,16 +36,46 @@ pub(crate) const RANDOM_PING_INTERVAL: u64 = 10 * 1000;         // 10 seconds
 pub(crate) const RPC_SERVER_REACHABILITY_TIMEOUT: u128 = 60 * 1000;
 
 pub(crate) const BOOTSTRAP_IF_LESS_THAN_X_PEERS:usize = 30;
+// Minimum number of routing table entries for a bootstrapped DHT to be ready.
+pub(crate) const READY_IF_AT_LEAST_X_PEERS:usize = 1;
 pub(crate) const SELF_LOOKUP_INTERVAL:u128 = 30 * 60 * 1000;   // 30 minutes
 pub(crate) const ROUTING_TABLE_PERSIST_INTERVAL: u64 = 10 * 60 * 1000;   // 10 minutes
 // pub(crate) const BOOTSTRAP_MIN_INTERVAL: u128 = 4 * 60 * 1000;
 
 
 pub(crate) const RPC_CALL_TIMEOUT_MAX: u64 = 10 * 1000;
+pub(crate) const RPC_CALL_TIMEOUT_MIN: u64 = 500;
+// Weight of a new round-trip time sample in the moving averages, and the
+// standard deviations above the mean waited for before a call times out.
+pub(crate) const RTT_SAMPLE_GAIN: f64 = 0.125;
+pub(crate) const RTT_TIMEOUT_DEVIATIONS: f64 = 4.0;
+
+// Maximum number of requests in flight for a lookup task by default.
+pub(crate) const LOOKUP_CONCURRENCY: usize = 10;
 
 pub(crate) const EXPIRED_CHECK_INTERVAL: u64 = 60 * 1000;
 
+// Crypto contexts of peers not heard from for this long are dropped.
+pub(crate) const CRYPTO_CONTEXT_IDLE_TIMEOUT: u128 = 10 * 60 * 1000;
+// Lifetime of the session keys, and how long the previous ones are
+// still accepted after a rotation.
+pub(crate) const SESSION_KEY_LIFETIME: u128 = 30 * 60 * 1000;
+pub(crate) const SESSION_KEY_GRACE_PERIOD: u128 = 2 * 60 * 1000;
+// Session packets fall back to the handshake format if the peer
+// has not been heard from for this long.
+pub(crate) const SESSION_ACK_TIMEOUT: u128 = 60 * 1000;
+
+// Packets sent longer ago, or later, than this are taken as replays,
+// which also bounds the clock skew tolerated between nodes.
+pub(crate) const REPLAY_WINDOW: u128 = 5 * 60 * 1000;
+// Transaction ids kept per remote node to detect replays within the window.
+pub(crate) const REPLAY_CACHE_SIZE: usize = 1024;
+
 pub(crate) const STORAGE_EXPIRE_INTERVAL: u64 = 5 * 60 * 1000;
 
+// The largest UDP payload over IPv4, and the smallest MTU of IPv6.
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 65507;
+pub(crate) const MIN_DATAGRAM_SIZE: usize = 1280;
+
 pub(crate) const MAX_PEER_AGE: u128 = 120 * 60 * 1000;
 pub(crate) const MAX_VALUE_AGE: u128 = 120 * 60 * 1000;
@@ -1,6 +1,6 @@
-use std::rc::Rc;
+use std::mem;
 use std::collections::HashMap;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use crate::{
     as_millis,
@@ -13,96 +13,337 @@ use crate::{
         Nonce,
     }
 };
+use crate::core::{
+    clock,
+    constants,
+    version,
+};
+
+// Packet formats, following the sender id at the head of each packet:
+//  legacy:    box(static keys, fixed nonce)[message]
+//  handshake: 0x01 | nonce | box(static keys, nonce)
+//                  [session key | acked key | timestamp | message]
+//  session:   0x02 | sender session key | receiver session key | nonce
+//                  | box(session keys, nonce)[timestamp | message]
+//
+// The legacy format is only sent to peers not known to read the other two,
+// and only taken from the ones not heard from in them yet. The handshake
+// carries our session key, authenticated with the static keys, along with
+// the peer's session key we have learned. Once both sides have learned
+// each other's session key, the session format is used, which is forward
+// secret as session keys get rotated. The timestamp, in milliseconds since
+// the epoch, lets the receiver reject replayed packets.
+pub(crate) const PACKET_HANDSHAKE: u8 = 0x01;
+pub(crate) const PACKET_SESSION: u8 = 0x02;
+
+const HANDSHAKE_HEADER_BYTES: usize = 1 + Nonce::BYTES;
+const SESSION_HEADER_BYTES: usize = 1 + 2 * PublicKey::BYTES + Nonce::BYTES;
+const TIMESTAMP_BYTES: usize = 8;
+
+fn put_timestamp(buf: &mut Vec<u8>) {
+    let millis = clock::now().duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    buf.extend_from_slice(&millis.to_le_bytes());
+}
+
+// Split the timestamp off the head of the decrypted data.
+fn take_timestamp(mut plain: Vec<u8>) -> Result<(Vec<u8>, Option<SystemTime>), Error> {
+    if plain.len() < TIMESTAMP_BYTES {
+        return Err(Error::Crypto("Missing packet timestamp".to_string()));
+    }
+    let msg = plain.split_off(TIMESTAMP_BYTES);
+    let millis = u64::from_le_bytes(plain.as_slice().try_into().unwrap());
+    let timestamp = SystemTime::UNIX_EPOCH.checked_add(Duration::from_millis(millis));
+    match timestamp {
+        Some(_) => Ok((msg, timestamp)),
+        None => Err(Error::Crypto("Invalid packet timestamp".to_string())),
+    }
+}
+
+// What a packet read would change about its sender. It's only applied,
+// with `accept`, once the packet has passed the replay check, otherwise a
+// replayed handshake would roll the session back to an old key.
+pub(crate) enum Received {
+    Legacy,
+    // The peer's session key and the box made of it if new, and whether
+    // the peer has learned our current session key.
+    Handshake(PublicKey, Option<CryptoBox>, bool),
+    // Whether it's sent with our current session key.
+    Session(bool),
+}
 
-pub(crate) const EXPIRED_CHECK_INTERVAL: u64 = 60 * 1000;
 pub(crate) struct CryptoCache {
     keypair: KeyPair,
-    cache: HashMap<Id, Rc<Entry>>,
+    session: KeyPair,
+    previous: Option<KeyPair>,
+    session_created: SystemTime,
+    cache: HashMap<Id, Entry>,
 }
 
 impl CryptoCache {
     pub(crate) fn new(keypair: KeyPair) -> CryptoCache {
         Self {
             keypair,
+            session: KeyPair::random(),
+            previous: None,
+            session_created: clock::now(),
             cache: HashMap::new(),
         }
     }
 
-    pub(crate) fn get(&mut self, key: &Id) -> Rc<Entry> {
-        let entry = self.cache.get(key);
-        if entry.is_none() {
-            let _new = Rc::new(Entry::new(self.load(key)));
-            self.cache.insert(key.clone(), _new.clone());
-            return _new
-        }
-        entry.unwrap().clone()
+    fn entry<'a>(cache: &'a mut HashMap<Id, Entry>, keypair: &KeyPair, key: &Id) -> &'a mut Entry {
+        cache.entry(key.clone()).or_insert_with(|| Entry::new(key, keypair))
     }
 
     pub(crate) fn expire(&mut self) {
-        let mut to_remove = vec![];
-        self.cache.iter_mut().for_each(|(id, entry)| {
-            if entry.expired() {
-                to_remove.push(id.clone());
-            }
-        });
+        self.cache.retain(|_, entry| !entry.expired());
 
-        to_remove.iter().for_each(|id| {
-            self.cache.remove(id);
+        let age = as_millis!(self.session_created);
+        if age >= constants::SESSION_KEY_LIFETIME {
+            self.rotate();
+        } else if age >= constants::SESSION_KEY_GRACE_PERIOD {
+            self.previous = None;
+        }
+    }
+
+    // Replace the session keys, peers learn about the new ones from
+    // the next handshake sent to them.
+    fn rotate(&mut self) {
+        let previous = mem::replace(&mut self.session, KeyPair::random());
+        self.previous = Some(previous);
+        self.session_created = clock::now();
+
+        let session = &self.session;
+        self.cache.values_mut().for_each(|entry| {
+            entry.acked = false;
+            entry.session_box = entry.session_pk.as_ref().and_then(|pk|
+                CryptoBox::try_from((pk, session.private_key())).ok()
+            );
         });
     }
 
-    fn load(&self, key: &Id) -> Box<CryptoContext> {
-        Box::new(CryptoContext::new(
-            &key.to_encryption_key(),
-            &self.keypair
-        ))
+    // Peers are only sent the versioned formats once they are known to
+    // read them, which the version in their messages tells. A peer is never
+    // taken back to the legacy format, as the legacy packets claiming so
+    // might be replayed ones.
+    pub(crate) fn learn_version(&mut self, sender: &Id, ver: i32) {
+        let entry = Self::entry(&mut self.cache, &self.keypair, sender);
+        if version::supports_versioned_packet(ver) {
+            entry.versioned = true;
+        }
     }
-}
 
-pub(crate) struct Entry(Box<CryptoContext>, SystemTime);
-impl Entry {
-    fn new(value: Box<CryptoContext>) -> Self {
-        Entry(value, SystemTime::now())
+    // Apply what the packet read from the peer tells, once it's known not
+    // to be replayed.
+    pub(crate) fn accept(&mut self, sender: &Id, received: Received) {
+        let entry = Self::entry(&mut self.cache, &self.keypair, sender);
+        match received {
+            Received::Legacy => {},
+            Received::Handshake(peer_pk, session_box, acked) => {
+                entry.versioned = true;
+                entry.upgraded = true;
+                entry.acked = acked;
+                if session_box.is_some() {
+                    entry.session_box = session_box;
+                    entry.session_pk = Some(peer_pk);
+                }
+            },
+            Received::Session(current) => {
+                entry.versioned = true;
+                entry.upgraded = true;
+                if current {
+                    entry.acked = true;
+                }
+            },
+        }
+        entry.last_received = clock::now();
     }
 
-    fn expired(&self) -> bool {
-        as_millis!(&self.1) >= EXPIRED_CHECK_INTERVAL as u128
+    pub(crate) fn encrypt_into(&mut self,
+        recipient: &Id,
+        plain: &[u8]
+    ) -> Result<Vec<u8>, Error> {
+        let session = self.session.public_key().clone();
+        let entry = Self::entry(&mut self.cache, &self.keypair, recipient);
+
+        if !entry.versioned {
+            return entry.static_box.encrypt_into(plain, &entry.legacy_nonce);
+        }
+
+        if entry.acked && as_millis!(entry.last_received) >= constants::SESSION_ACK_TIMEOUT {
+            entry.acked = false;
+        }
+
+        let nonce = Nonce::random();
+        let mut buf = Vec::new();
+        match (entry.acked, entry.session_box.as_ref(), entry.session_pk.as_ref()) {
+            (true, Some(session_box), Some(session_pk)) => {
+                let mut payload = Vec::with_capacity(TIMESTAMP_BYTES + plain.len());
+                put_timestamp(&mut payload);
+                payload.extend_from_slice(plain);
+
+                buf.reserve(SESSION_HEADER_BYTES + payload.len() + CryptoBox::MAC_BYTES);
+                buf.push(PACKET_SESSION);
+                buf.extend_from_slice(session.as_bytes());
+                buf.extend_from_slice(session_pk.as_bytes());
+                buf.extend_from_slice(nonce.as_bytes());
+                buf.extend_from_slice(&session_box.encrypt_into(&payload, &nonce)?);
+            },
+            _ => {
+                let mut payload = Vec::with_capacity(2 * PublicKey::BYTES + TIMESTAMP_BYTES + plain.len());
+                payload.extend_from_slice(session.as_bytes());
+                match entry.session_pk.as_ref() {
+                    Some(pk) => payload.extend_from_slice(pk.as_bytes()),
+                    None => payload.extend_from_slice(&[0u8; PublicKey::BYTES]),
+                }
+                put_timestamp(&mut payload);
+                payload.extend_from_slice(plain);
+
+                buf.reserve(HANDSHAKE_HEADER_BYTES + payload.len() + CryptoBox::MAC_BYTES);
+                buf.push(PACKET_HANDSHAKE);
+                buf.extend_from_slice(nonce.as_bytes());
+                buf.extend_from_slice(&entry.static_box.encrypt_into(&payload, &nonce)?);
+            }
+        }
+        Ok(buf)
     }
 
-    pub(crate) fn ctx(&self) -> &Box<CryptoContext> {
-        &self.0
+    // Returns the message along with the time it was sent at, which the
+    // legacy format doesn't tell, and what the packet would change about
+    // the sender, which is left to `accept`.
+    pub(crate) fn decrypt_into(&mut self,
+        sender: &Id,
+        data: &[u8]
+    ) -> Result<(Vec<u8>, Option<SystemTime>, Received), Error> {
+        // A legacy packet may start with a format byte by chance, so
+        // it's taken as legacy if it can't be read as the other formats.
+        let result = match data.first() {
+            Some(&PACKET_HANDSHAKE) => self.decrypt_handshake(sender, data),
+            Some(&PACKET_SESSION) => self.decrypt_session(sender, data),
+            _ => Err(Error::Crypto("Not a versioned packet".to_string())),
+        };
+        if result.is_ok() {
+            return result;
+        }
+
+        // Legacy packets carry no timestamp, so the ones from a peer that
+        // has sent the versioned formats can only be taken as replays. Until
+        // then, the peer keeps sending the legacy format as long as none of
+        // our handshakes gets through to it. Even after its restart, the peer
+        // gets a handshake from us once it has not been heard from for a
+        // while, and learns the formats again.
+        let entry = Self::entry(&mut self.cache, &self.keypair, sender);
+        if entry.upgraded {
+            return Err(result.err().unwrap());
+        }
+        let plain = entry.static_box.decrypt_into(data, &entry.legacy_nonce)?;
+        Ok((plain, None, Received::Legacy))
+    }
+
+    fn decrypt_handshake(&mut self,
+        sender: &Id,
+        data: &[u8]
+    ) -> Result<(Vec<u8>, Option<SystemTime>, Received), Error> {
+        if data.len() < HANDSHAKE_HEADER_BYTES + CryptoBox::MAC_BYTES + 2 * PublicKey::BYTES + TIMESTAMP_BYTES {
+            return Err(Error::Crypto("Truncated handshake packet".to_string()));
+        }
+
+        let session = self.session.public_key().clone();
+        let entry = Self::entry(&mut self.cache, &self.keypair, sender);
+        let nonce = Nonce::try_from(&data[1..HANDSHAKE_HEADER_BYTES])?;
+        let mut payload = entry.static_box.decrypt_into(&data[HANDSHAKE_HEADER_BYTES..], &nonce)?;
+        let peer_pk = PublicKey::try_from(&payload[..PublicKey::BYTES])?;
+        let acked = &payload[PublicKey::BYTES..2 * PublicKey::BYTES] == session.as_bytes();
+        let session_box = match entry.session_pk.as_ref() != Some(&peer_pk) {
+            true => Some(CryptoBox::try_from((&peer_pk, self.session.private_key()))?),
+            false => None,
+        };
+
+        let (plain, timestamp) = take_timestamp(payload.split_off(2 * PublicKey::BYTES))?;
+        Ok((plain, timestamp, Received::Handshake(peer_pk, session_box, acked)))
+    }
+
+    fn decrypt_session(&mut self,
+        sender: &Id,
+        data: &[u8]
+    ) -> Result<(Vec<u8>, Option<SystemTime>, Received), Error> {
+        if data.len() < SESSION_HEADER_BYTES + CryptoBox::MAC_BYTES {
+            return Err(Error::Crypto("Truncated session packet".to_string()));
+        }
+
+        let peer_pk = &data[1..1 + PublicKey::BYTES];
+        let own_pk = &data[1 + PublicKey::BYTES..1 + 2 * PublicKey::BYTES];
+        let nonce = Nonce::try_from(&data[1 + 2 * PublicKey::BYTES..SESSION_HEADER_BYTES])?;
+        let cipher = &data[SESSION_HEADER_BYTES..];
+
+        // The peer's session key must have been learned from a handshake.
+        let entry = match self.cache.get(sender) {
+            Some(v) => v,
+            None => return Err(Error::Crypto(format!("No session with {}", sender))),
+        };
+        let session_pk = match entry.session_pk.as_ref() {
+            Some(pk) if pk.as_bytes() == peer_pk => pk,
+            _ => return Err(Error::Crypto(format!("Unknown session key of {}", sender))),
+        };
+
+        let current = own_pk == self.session.public_key().as_bytes();
+        let plain = if current {
+            match entry.session_box.as_ref() {
+                Some(session_box) => session_box.decrypt_into(cipher, &nonce)?,
+                None => return Err(Error::Crypto(format!("No session with {}", sender))),
+            }
+        } else {
+            // Sent before the peer has learned our new session key.
+            match self.previous.as_ref() {
+                Some(prev) if own_pk == prev.public_key().as_bytes() => {
+                    CryptoBox::try_from((session_pk, prev.private_key()))?
+                        .decrypt_into(cipher, &nonce)?
+                },
+                _ => return Err(Error::Crypto("Expired session key".to_string())),
+            }
+        };
+
+        let (plain, timestamp) = take_timestamp(plain)?;
+        Ok((plain, timestamp, Received::Session(current)))
     }
 }
 
-pub(crate) struct CryptoContext {
-    box_: CryptoBox,
-    nonce: Nonce,
+struct Entry {
+    static_box: CryptoBox,
+    legacy_nonce: Nonce,
+
+    versioned: bool,
+    // Whether the peer has sent us the versioned formats.
+    upgraded: bool,
+    session_pk: Option<PublicKey>,
+    session_box: Option<CryptoBox>,
+    // Whether the peer has learned our current session key.
+    acked: bool,
+
+    last_received: SystemTime,
 }
 
-impl CryptoContext {
-    fn new(pk: &PublicKey, keypair: &KeyPair) -> CryptoContext {
+impl Entry {
+    fn new(id: &Id, keypair: &KeyPair) -> Self {
+        let pk = id.to_encryption_key();
         let receiver = Id::try_from(pk.as_bytes()).unwrap();
         let sender   = Id::try_from(keypair.public_key().as_bytes()).unwrap();
         let distance = Id::distance(&sender, &receiver);
 
         Self {
-            box_: CryptoBox::try_from((pk, keypair.private_key())).unwrap(),
-            nonce: Nonce::try_from(&distance.as_bytes()[0..Nonce::BYTES]).unwrap(),
+            static_box: CryptoBox::try_from((&pk, keypair.private_key())).unwrap(),
+            legacy_nonce: Nonce::try_from(&distance.as_bytes()[0..Nonce::BYTES]).unwrap(),
+            versioned: false,
+            upgraded: false,
+            session_pk: None,
+            session_box: None,
+            acked: false,
+            last_received: clock::now(),
         }
     }
 
-    pub(crate) fn encrypt_into(&self, plain: &[u8]) -> Result<Vec<u8>, Error> {
-        self.box_.encrypt_into(plain, &self.nonce)
-    }
-
-    pub(crate) fn decrypt_into(&self, cipher: &[u8]) -> Result<Vec<u8>, Error> {
-        self.box_.decrypt_into(cipher, &self.nonce)
-    }
-}
-
-impl Drop for CryptoContext {
-    fn drop(&mut self) {
-        self.box_.clear();
-        self.nonce.clear();
+    fn expired(&self) -> bool {
+        as_millis!(self.last_received) >= constants::CRYPTO_CONTEXT_IDLE_TIMEOUT
     }
 }
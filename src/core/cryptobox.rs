@@ -461,6 +461,9 @@ impl CryptoBox {
         plain: &mut [u8],
         nonce: &Nonce
     ) -> Result<usize> {
+        if cipher.len() < CryptoBox::MAC_BYTES {
+            return Err(Error::Argument("The cipher data is too short.".to_string()));
+        }
         let expected_len = cipher.len() - CryptoBox::MAC_BYTES;
         if plain.len() < expected_len {
             return Err(Error::Argument(format!("The input buffer is insufficient.")));
@@ -486,7 +489,7 @@ impl CryptoBox {
         cipher: &[u8],
         nonce: &Nonce
     ) -> Result<Vec<u8>> {
-        let mut plain = vec![0u8; cipher.len() - CryptoBox::MAC_BYTES];
+        let mut plain = vec![0u8; cipher.len().saturating_sub(CryptoBox::MAC_BYTES)];
         self.decrypt(cipher, plain.as_mut(), nonce).map(|_| plain)
     }
 }
@@ -540,6 +543,9 @@ pub fn decrypt(cipher: &[u8],
     pk: &PublicKey,
     sk: &PrivateKey,
 ) -> Result<usize> {
+    if cipher.len() < CryptoBox::MAC_BYTES {
+        return Err(Error::Argument("The cipher data is too short.".to_string()));
+    }
     let expected_len = cipher.len() - CryptoBox::MAC_BYTES;
     if plain.len() < expected_len {
         return Err(Error::Argument(format!("The input buffer is insufficient.")));
@@ -567,6 +573,6 @@ pub fn decrypt_into(cipher: &[u8],
     pk: &PublicKey,
     sk: &PrivateKey
 ) -> Result<Vec<u8>> {
-    let mut plain = vec![0u8; cipher.len() - CryptoBox::MAC_BYTES];
+    let mut plain = vec![0u8; cipher.len().saturating_sub(CryptoBox::MAC_BYTES)];
     decrypt(cipher, plain.as_mut(), nonce, pk, sk).map(|_| plain)
 }
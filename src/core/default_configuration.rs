@@ -58,6 +58,9 @@ struct Cfg {
     port: u16,
     dataDir: String,
     logger: Option<Logger>,
+    maxDatagramSize: Option<usize>,
+    bucketSubnetLimit: Option<usize>,
+    tableSubnetLimit: Option<usize>,
     bootstraps: Vec<CfgNode>,
     activeproxy: Option<ActiveProxyItem>
 }
@@ -73,6 +76,10 @@ pub struct Builder<'a> {
 
     port:           u16,
     data_dir:       String,
+    max_datagram_size: usize,
+    bucket_subnet_limit: usize,
+    table_subnet_limit: usize,
+    loopback_allowed: bool,
 
     log_level:      LevelFilter,
     log_file:       Option<String>,
@@ -92,6 +99,10 @@ impl<'a> Builder<'a> {
             ipv6_addr:      None,
             port:           constants::DEFAULT_DHT_PORT,
             data_dir:       env::var("HOME").unwrap_or_else(|_| String::from(".")),
+            max_datagram_size: constants::MAX_DATAGRAM_SIZE,
+            bucket_subnet_limit: constants::BUCKET_SUBNET_LIMIT,
+            table_subnet_limit: constants::TABLE_SUBNET_LIMIT,
+            loopback_allowed: false,
             log_level:      LevelFilter::Info,
             log_file:       None,
             activeproxy:    None,
@@ -137,6 +148,33 @@ impl<'a> Builder<'a> {
         self
     }
 
+    pub fn with_max_datagram_size(&mut self, size: usize) -> &mut Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    pub fn with_subnet_limits(&mut self, bucket: usize, table: usize) -> &mut Self {
+        self.bucket_subnet_limit = bucket;
+        self.table_subnet_limit = table;
+        self
+    }
+
+    pub fn with_loopback_allowed(&mut self) -> &mut Self {
+        self.loopback_allowed = true;
+        self
+    }
+
+    // LevelFilter::Off leaves the node without a logger of its own.
+    pub fn with_log_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.log_level = level;
+        self
+    }
+
+    pub fn with_log_file(&mut self, input: &str) -> &mut Self {
+        self.log_file = Some(input.to_string());
+        self
+    }
+
     pub fn add_bootstrap_node(&mut self, node: &NodeInfo) -> &mut Self {
         self.bootstrap_nodes.push(node.clone());
         self
@@ -187,6 +225,16 @@ impl<'a> Builder<'a> {
             )
         }
 
+        if let Some(size) = cfg.maxDatagramSize {
+            self.max_datagram_size = size;
+        }
+        if let Some(limit) = cfg.bucketSubnetLimit {
+            self.bucket_subnet_limit = limit;
+        }
+        if let Some(limit) = cfg.tableSubnetLimit {
+            self.table_subnet_limit = limit;
+        }
+
         if let Some(logger) = cfg.logger {
             self.log_level = logger::convert_loglevel(&logger.level);
             self.log_file = logger.logFile;
@@ -211,6 +259,25 @@ impl<'a> Builder<'a> {
             self.ipv6_addr = Some(local_addr(false)?);
         }
 
+        if self.max_datagram_size < constants::MIN_DATAGRAM_SIZE ||
+            self.max_datagram_size > constants::MAX_DATAGRAM_SIZE {
+            return Err(Error::Argument(format!(
+                "Invalid max datagram size {}, expected between {} and {}.",
+                self.max_datagram_size,
+                constants::MIN_DATAGRAM_SIZE,
+                constants::MAX_DATAGRAM_SIZE
+            )));
+        }
+
+        if self.bucket_subnet_limit == 0 ||
+            self.table_subnet_limit < self.bucket_subnet_limit {
+            return Err(Error::Argument(format!(
+                "Invalid subnet limits {} per bucket and {} per table, expected the former being at least 1 and not above the latter.",
+                self.bucket_subnet_limit,
+                self.table_subnet_limit
+            )));
+        }
+
         if self.ipv4_addr.is_none() && self.ipv6_addr.is_none() {
             return Err(Error::Argument(format!(
                 "No valid IPv4 or IPv6 address was specified."
@@ -268,6 +335,10 @@ pub(crate) struct DefaultConfiguration {
     addr6: Option<SocketAddr>,
 
     port: u16,
+    max_datagram_size: usize,
+    bucket_subnet_limit: usize,
+    table_subnet_limit: usize,
+    loopback_allowed: bool,
 
     log_level: LevelFilter,
     log_file: Option<String>,
@@ -297,6 +368,10 @@ impl DefaultConfiguration {
             addr4,
             addr6,
             port: b.port,
+            max_datagram_size: b.max_datagram_size,
+            bucket_subnet_limit: b.bucket_subnet_limit,
+            table_subnet_limit: b.table_subnet_limit,
+            loopback_allowed: b.loopback_allowed,
             log_level: b.log_level,
             log_file: b.log_file.clone(),
             storage_path: b.data_dir.to_string(),
@@ -327,6 +402,22 @@ impl Config for DefaultConfiguration {
         &self.bootstrap_nodes
     }
 
+    fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
+
+    fn bucket_subnet_limit(&self) -> usize {
+        self.bucket_subnet_limit
+    }
+
+    fn table_subnet_limit(&self) -> usize {
+        self.table_subnet_limit
+    }
+
+    fn loopback_allowed(&self) -> bool {
+        self.loopback_allowed
+    }
+
     fn log_level(&self) -> LevelFilter {
         self.log_level
     }
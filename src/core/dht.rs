@@ -1,5 +1,7 @@
 
+use std::fmt;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::cell::RefCell;
 use std::net::SocketAddr;
 use std::any::{Any, TypeId};
@@ -18,10 +20,12 @@ use crate::{
     NodeInfo,
     PeerInfo,
     Value,
-    Error
+    Error,
+    error::ErrorCode,
 };
 
 use crate::core::{
+    clock,
     is_any_unicast,
     is_bogon,
     constants,
@@ -29,12 +33,15 @@ use crate::core::{
     rpccall,
     rpccall::RpcCall,
     server::Server,
+    scheduler::Scheduler,
+    event::{EventBus, NodeEvent},
     token_manager::TokenManager,
     data_storage::DataStorage,
-    lookup_option::LookupOption,
+    lookup_option::{LookupOption, LookupOptions},
     routing_table::RoutingTable,
     kclosest_nodes::KClosestNodes,
     kbucket_entry::KBucketEntry,
+    rtt_estimator::RttEstimator,
 };
 
 use crate::core::msg::{
@@ -44,8 +51,9 @@ use crate::core::msg::{
 };
 
 use crate::core::task::{
-    task::{State, Task},
+    task::{State, Task, TaskId},
     lookup_task::LookupTask,
+    announce_task::AnnounceTask,
     node_lookup::NodeLookupTask,
     peer_lookup::PeerLookupTask,
     task_manager::TaskManager,
@@ -55,6 +63,25 @@ use crate::core::task::{
     ping_refresh::PingOption,
 };
 
+// Outcome of the latest bootstrap round.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum BootstrapState {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl fmt::Display for BootstrapState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            BootstrapState::Pending => "pending",
+            BootstrapState::Completed => "completed",
+            BootstrapState::Failed => "failed",
+        };
+        write!(f, "{}", str)
+    }
+}
+
 pub(crate) struct DHT {
     id: Rc<Id>,
     ni: Rc<NodeInfo>,
@@ -65,8 +92,11 @@ pub(crate) struct DHT {
     bootstrap_needed: bool,
     bootstrap_nodes : Vec<Rc<NodeInfo>>,
     bootstrap_time  : Rc<RefCell<SystemTime>>,
+    bootstrap_state : Rc<RefCell<BootstrapState>>,
 
     known_nodes: HashMap<SocketAddr, Id>,
+    rtt: RttEstimator,      // over the responses from all the nodes.
+    loopback_allowed: bool,
 
     rt:     Rc<RefCell<RoutingTable>>,
     taskman:Rc<RefCell<TaskManager>>,
@@ -74,6 +104,7 @@ pub(crate) struct DHT {
     server: Option<Rc<RefCell<Server>>>,
     tokman: Option<Rc<RefCell<TokenManager>>>,
     storage:Option<Rc<RefCell<dyn DataStorage>>>,
+    events: Option<Arc<EventBus>>,
     cloned: Option<Rc<RefCell<DHT>>>,
 }
 
@@ -89,8 +120,11 @@ impl DHT {
             bootstrap_nodes: Vec::new(),
             bootstrap_needed: false,
             bootstrap_time: Rc::new(RefCell::new(SystemTime::UNIX_EPOCH)),
+            bootstrap_state: Rc::new(RefCell::new(BootstrapState::Pending)),
 
             known_nodes: HashMap::new(),
+            rtt: RttEstimator::new(),
+            loopback_allowed: false,
 
             rt:     Rc::new(RefCell::new(RoutingTable::new(nodeid.clone()))),
             taskman:Rc::new(RefCell::new(TaskManager::new())),
@@ -98,6 +132,7 @@ impl DHT {
             server: None,
             storage:None,
             tokman: None,
+            events: None,
             cloned: None,
         }
     }
@@ -109,15 +144,37 @@ impl DHT {
         if type_id == TypeId::of::<Rc<RefCell<DHT>>>() {
             self.cloned = Some(field_any.downcast::<Rc<RefCell<DHT>>>().unwrap().deref().clone());
         } else if type_id == TypeId::of::<Rc<RefCell<Server>>>() {
-            self.server = Some(field_any.downcast::<Rc<RefCell<Server>>>().unwrap().deref().clone());
+            let server = field_any.downcast::<Rc<RefCell<Server>>>().unwrap().deref().clone();
+            self.rt.borrow_mut().set_stats(server.borrow().stats());
+            self.server = Some(server);
         } else if type_id == TypeId::of::<Rc<RefCell<dyn DataStorage>>>() {
             self.storage = Some(field_any.downcast::<Rc<RefCell<dyn DataStorage>>>().unwrap().deref().clone());
         } else if type_id == TypeId::of::<Rc<RefCell<TokenManager>>>() {
             self.tokman = Some(field_any.downcast::<Rc<RefCell<TokenManager>>>().unwrap().deref().clone());
+        } else if type_id == TypeId::of::<Arc<EventBus>>() {
+            let events = field_any.downcast::<Arc<EventBus>>().unwrap().deref().clone();
+            self.rt.borrow_mut().set_events(events.clone());
+            self.events = Some(events);
         }
         self
     }
 
+    pub(crate) fn set_loopback_allowed(&mut self, allowed: bool) {
+        self.loopback_allowed = allowed;
+    }
+
+    // Whether the address is kept out of the routing table and lookups.
+    pub(crate) fn is_bogon(&self, addr: &SocketAddr) -> bool {
+        if self.loopback_allowed && addr.ip().is_loopback() {
+            return false;
+        }
+        if cfg!(feature = "devp") {
+            !is_any_unicast(&addr.ip())
+        } else {
+            is_bogon(addr)
+        }
+    }
+
     pub(crate) fn enable_persistence(&mut self, path: String) {
         self.store_path = Some(path);
     }
@@ -146,6 +203,19 @@ impl DHT {
         self.rt.clone()
     }
 
+    // Timeout of the calls to the node, from the round-trip times measured
+    // on it, or on all the nodes if it has never answered yet.
+    pub(crate) fn rpc_timeout(&self, id: &Id) -> Duration {
+        self.rt.borrow().bucket_entry(id)
+            .filter(|v| v.borrow().rtt().samples() > 0)
+            .map(|v| v.borrow().rtt().timeout())
+            .unwrap_or_else(|| self.rtt.timeout())
+    }
+
+    pub(crate) fn round_trip_time(&self, id: &Id) -> Option<Duration> {
+        self.rt.borrow().bucket_entry(id).and_then(|v| v.borrow().rtt().mean())
+    }
+
     pub(crate) fn taskman(&self) -> Rc<RefCell<TaskManager>> {
         self.taskman.clone()
     }
@@ -162,6 +232,16 @@ impl DHT {
         unwrap!(self.storage)
     }
 
+    fn publish(&self, event: NodeEvent) {
+        if let Some(events) = self.events.as_ref() {
+            events.publish(event);
+        }
+    }
+
+    pub(crate) fn bootstrap_state(&self) -> BootstrapState {
+        *self.bootstrap_state.borrow()
+    }
+
     pub(crate) fn add_bootstrap_node(&mut self, node: Rc<NodeInfo>) {
         self.bootstrap_nodes.push(node)
     }
@@ -180,6 +260,7 @@ impl DHT {
 
         let bootstr_map = Rc::new(RefCell::new(HashMap::new()));
         let count = Rc::new(RefCell::new(0));
+        let responsed = Rc::new(RefCell::new(0));
 
         for item in bootstr_nodes.iter() {
             let req = Rc::new(RefCell::new({
@@ -188,7 +269,8 @@ impl DHT {
 
                 msg.set_remote(item.id(), item.socket_addr());
                 msg.with_target(Rc::new(Id::random()));
-                msg.with_want4(true);
+                msg.with_want4(self.network() == Network::IPv4);
+                msg.with_want6(self.network() == Network::IPv6);
                 msg as Box<dyn Msg>
             }));
 
@@ -201,13 +283,20 @@ impl DHT {
             let bootstr_sz = bootstr_nodes.len();
             let cloned_map = bootstr_map.clone();
             let cloned_cnt = count.clone();
+            let cloned_rsp = responsed.clone();
+            let cloned_events = self.events.clone();
+            let cloned_state = self.bootstrap_state.clone();
+            let network = self.network();
             let cloned_dht = self.dht();
             let cloned_bootstrap_time = self.bootstrap_time.clone();
 
+            // The bootstrap nodes are usually busy taking the newcomers, which
+            // are given the full timeout rather than the measured one.
             call.borrow_mut().set_cloned(call.clone());
+            call.borrow_mut().set_timeout(Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX));
             call.borrow_mut().set_state_changed_fn(move |_call, _, _cur| {
                 match _cur {
-                    rpccall::State::Responsed => {},
+                    rpccall::State::Responsed => *cloned_rsp.borrow_mut() += 1,
                     rpccall::State::Err => {},
                     rpccall::State::Timeout => {},
                     _ => return,
@@ -238,7 +327,15 @@ impl DHT {
 
                 *cloned_cnt.borrow_mut() += 1;
                 if *cloned_cnt.borrow() == bootstr_sz {
-                    *cloned_bootstrap_time.borrow_mut() = SystemTime::now();
+                    let (state, event) = match *cloned_rsp.borrow() > 0 {
+                        true  => (BootstrapState::Completed, NodeEvent::BootstrapCompleted(network)),
+                        false => (BootstrapState::Failed, NodeEvent::BootstrapFailed(network)),
+                    };
+                    *cloned_state.borrow_mut() = state;
+                    if let Some(events) = cloned_events.as_ref() {
+                        events.publish(event);
+                    }
+                    *cloned_bootstrap_time.borrow_mut() = clock::now();
                     cloned_dht.borrow().fill_home_bucket(
                         cloned_map.borrow().values().cloned().collect()
                     );
@@ -283,7 +380,7 @@ impl DHT {
 
         if self.bootstrap_needed ||
             self.rt.borrow().size_of_entries() < constants::BOOTSTRAP_IF_LESS_THAN_X_PEERS ||
-            as_millis!(self.bootstrap_time.borrow()) > constants::SELF_LOOKUP_INTERVAL {
+            as_millis!(*self.bootstrap_time.borrow()) > constants::SELF_LOOKUP_INTERVAL {
 
             // Regularly search for our ID to update the routing table
             self.bootstrap_needed = false;
@@ -293,7 +390,7 @@ impl DHT {
         if as_millis!(self.last_saved) > constants::ROUTING_TABLE_PERSIST_INTERVAL as u128 {
             info!("Persisting routing table ....");
             self.rt.borrow_mut().save(self.store_path.as_ref().unwrap().as_str());
-            self.last_saved = SystemTime::now();
+            self.last_saved = clock::now();
         }
     }
 
@@ -352,6 +449,17 @@ impl DHT {
             taskman.borrow_mut().dequeue();
         }, 500, constants::DHT_UPDATE_INTERVAL);
 
+        // Dequeue newly added tasks right away instead of waiting for the
+        // next regular round.
+        let taskman   = self.taskman.clone();
+        let cloned_scheduler = scheduler.clone();
+        self.taskman.borrow_mut().set_wakeup_fn(move || {
+            let taskman = taskman.clone();
+            cloned_scheduler.borrow_mut().add_oneshot(move || {
+                taskman.borrow_mut().dequeue();
+            }, 0);
+        });
+
         // fix the first time to persist the routing table: 2 min
         self.last_saved = self.last_saved.checked_sub(
             Duration::from_millis(constants::ROUTING_TABLE_PERSIST_INTERVAL + (120 * 1000))
@@ -394,17 +502,13 @@ impl DHT {
         if let Some(path) = self.store_path.take() {
             self.rt.borrow_mut().save(&path);
         }
+        self.taskman.borrow_mut().set_wakeup_fn(|| {});
         self.taskman.borrow_mut().cancel_all();
     }
 
     pub(crate) fn on_message(&mut self, msg: Rc<RefCell<Box<dyn Msg>>>) {
         let from  = msg.borrow().origin().clone();
-        let bogon = if cfg!(feature = "devp") {
-            !is_any_unicast(&from.ip())
-        } else {
-            is_bogon(&from)
-        };
-        if bogon {
+        if self.is_bogon(&from) {
             info!("Received a message from bogon address {}, ignored the potential
                   routing table operation", from);
             return;
@@ -430,6 +534,9 @@ impl DHT {
             if !call.borrow().matches_addr() {
                 return;
             }
+            if let Some(rtt) = call.borrow().rtt() {
+                self.rtt.update(rtt);
+            }
         }
 
         let mut found = false;
@@ -480,7 +587,13 @@ impl DHT {
             if let Some(call) = call {
                 entry.signal_response();
                 entry.merge_request_time(call.borrow().sent_time().clone());
-            } else if !found {
+                if let Some(rtt) = call.borrow().rtt() {
+                    entry.signal_round_trip(rtt);
+                }
+            } else if !found && self.rt.borrow().accepts(from_id) {
+                // Verify the unknown node before taking it, unless there is no
+                // room for it, not even as a candidate, otherwise both sides
+                // would keep pinging back.
                 let call = {
                     use crate::core::msg::ping_req as req;
                     let msg = Box::new(req::Message::new());
@@ -509,7 +622,7 @@ impl DHT {
             Method::StoreValue  => self.on_store_value(borrowed_deref),
             Method::FindPeer    => self.on_find_peers(borrowed_deref),
             Method::AnnouncePeer=> self.on_announce_peer(borrowed_deref),
-            Method::Unknown     => self.send_err(borrowed_deref, 203, "Invalid request method")
+            Method::Unknown     => self.send_err(borrowed_deref, ErrorCode::MethodUnknown, "Invalid request method")
         }
     }
 
@@ -530,7 +643,7 @@ impl DHT {
         );
     }
 
-    fn send_err(&mut self, msg: &Box<dyn Msg>, code: i32, str: &str) {
+    fn send_err(&mut self, msg: &Box<dyn Msg>, code: ErrorCode, str: &str) {
         let msg = Rc::new(RefCell::new({
             use crate::core::msg::error_msg::Message as Message;
             let mut err = Box::new(Message::new(msg.method(), msg.txid()));
@@ -701,13 +814,13 @@ impl DHT {
 
         if !valid {
             warn!("Received a store value request with invalid token from {}", req.origin());
-            self.send_err(msg, 203, "Invalid token for store value request");
+            self.send_err(msg, ErrorCode::Protocol, "Invalid token for store value request");
             return;
         }
 
         if !value.is_valid() {
             warn!("Received a store value request failed on verification from {}", req.origin());
-            self.send_err(msg, 203, "Invalid value");
+            self.send_err(msg, ErrorCode::Protocol, "Invalid value");
             return;
         }
 
@@ -715,6 +828,7 @@ impl DHT {
             .put_value(&value, Some(0), Some(true), None)
             .map_err(|e| error!("{}",e))
             .unwrap();
+        self.publish(NodeEvent::ValueStored(value.as_ref().clone()));
 
         let rsp = Rc::new(RefCell::new({
             let mut msg = Box::new(rsp::Message::new());
@@ -750,7 +864,9 @@ impl DHT {
                 msg.populate_peers(peers.into_iter().collect());
             }
 
-            if req.want4() && use_ipv4 && found_peers {
+            // The closest nodes are only given when there are no peers to
+            // give, for the requester to carry on the lookup with.
+            if req.want4() && use_ipv4 && !found_peers {
                 msg.populate_closest_nodes4({
                     let mut kns = KClosestNodes::new(
                         req.target(),
@@ -763,7 +879,7 @@ impl DHT {
                 });
             }
 
-            if req.want6() && !use_ipv4 && found_peers {
+            if req.want6() && !use_ipv4 && !found_peers {
                 msg.populate_closest_nodes6({
                     let mut kns = KClosestNodes::new(
                         req.target(),
@@ -812,13 +928,13 @@ impl DHT {
 
         if !valid {
             warn!("Received an announce peer request with invalid token from {}", req.origin());
-            self.send_err(msg, 203,"Invalid token for ANNOUNCE PEER request");
+            self.send_err(msg, ErrorCode::Protocol, "Invalid token for ANNOUNCE PEER request");
             return;
         }
 
         if !peer.is_valid() {
             warn!("Received an announce peer request, but verification failed from {}", req.origin());
-            self.send_err(msg, 203, "The peer is invalid peer");
+            self.send_err(msg, ErrorCode::Protocol, "The peer is invalid peer");
             return;
         }
 
@@ -829,6 +945,7 @@ impl DHT {
             .put_peer(&peer, Some(true), None)
             .map_err(|e| error!("{}", e))
             .unwrap();
+        self.publish(NodeEvent::PeerAnnounced(peer.as_ref().clone()));
 
         let rsp = Rc::new(RefCell::new({
             let mut msg = Box::new(rsp::Message::new());
@@ -846,6 +963,9 @@ impl DHT {
             !self.server().borrow().is_reachable() {
             return;
         }
+        // Only the estimator of the node timing out backs off, the one over
+        // all the nodes is left to the samples, as a few dead nodes would
+        // otherwise hold up the calls to all the others never measured.
         self.rt.borrow_mut().on_timeout(call.target_id());
     }
 
@@ -857,9 +977,10 @@ impl DHT {
 
     pub(crate) fn find_node<F>(&self,
         target: &Id,
-        option: LookupOption,
+        options: &LookupOptions,
         complete_fn: Rc<RefCell<F>>
-    ) where F: FnMut(Option<NodeInfo>) + 'static {
+    ) -> TaskId where F: FnMut(Option<NodeInfo>) + 'static {
+        let option = options.option();
         let result = Rc::new(RefCell::new({
             self.rt.borrow()
                 .bucket_entry(&target)
@@ -872,6 +993,7 @@ impl DHT {
                 self.dht()
             ));
             task_.set_name("LookupNode");
+            task_.with_options(options);
 
             let cloned = result.clone();
             task_.set_result_fn(move |_task, _ni| {
@@ -892,20 +1014,22 @@ impl DHT {
             task_ as Box<dyn Task>
         }));
 
-        self.taskman.borrow_mut().add(task);
+        self.add_task(task, options)
     }
 
     pub(crate) fn find_value<F>(&self,
         value_id: Rc<Id>,
-        option: LookupOption,
+        options: &LookupOptions,
         complete_fn: Rc<RefCell<F>>
-    ) where F: FnMut(Option<Value>) + 'static {
+    ) -> TaskId where F: FnMut(Option<Value>) + 'static {
+        let option = options.option();
         let result = Rc::new(RefCell::new(None as Option<Value>));
         let task = Rc::new(RefCell::new({
             let cloned = result.clone();
             let mut task_ = Box::new(ValueLookupTask::new(self.dht(), value_id));
             task_.set_name("LookupValue");
             task_.with_expected_seq(-1);
+            task_.with_options(options);
             task_.set_result_fn(move |_task, _value| {
                 if let Some(_v) = _value.as_ref() {
                     if cloned.borrow().is_some() {
@@ -932,24 +1056,29 @@ impl DHT {
             task_ as Box<dyn Task>
         }));
 
-        self.taskman.borrow_mut().add(task);
+        self.add_task(task, options)
     }
 
     pub(crate) fn store_value<F>(&self,
         value: &Value,
+        options: &LookupOptions,
         complete_fn: Rc<RefCell<F>>
-    ) where F: FnMut(Option<Vec<NodeInfo>>) + 'static {
+    ) where F: FnMut(Result<Vec<NodeInfo>, Error>) + 'static {
         let mut task = Box::new(NodeLookupTask::new(
             Rc::new(value.id()),
             self.dht()
         ));
         task.set_name("LookupNode");
         task.set_want_token(true);
+        task.with_options(options);
 
         let dht = self.dht();
         let taskman = self.taskman.clone();
         let v = Rc::new(value.clone());
         let complete_fn = complete_fn.clone();
+        let cloned_options = options.clone();
+        let scheduler = self.server().borrow().scheduler();
+        let started = clock::now();
         task.add_listener(Box::new(move |_task| {
             if _task.state() != State::Finished {
                 return;
@@ -963,7 +1092,7 @@ impl DHT {
             if closest_set.borrow().size() == 0 {
                 // This should never happen
                 warn!("!!! Value announce task not started because the node lookup task got the empty closest nodes.");
-                complete_fn.borrow_mut()(Option::default());
+                complete_fn.borrow_mut()(Ok(Vec::new()));
                 return;
             }
 
@@ -975,37 +1104,55 @@ impl DHT {
                     v.clone()
                 ));
                 nested.set_name("ValueAnnounce");
-                nested.add_listener(Box::new(move |_| {
+                nested.with_options(&cloned_options);
+                nested.add_listener(Box::new(move |_nested| {
+                    let rejected = match _nested.as_any().downcast_ref::<ValueAnnounceTask>() {
+                        Some(downcasted) => downcasted.result().err(),
+                        None => None,
+                    };
+                    if let Some(err) = rejected {
+                        complete_fn.borrow_mut()(Err(err));
+                        return;
+                    }
+
                     let mut result = Vec::new();
                     for item in closest_set.borrow().entries().iter() {
                         result.push(item.borrow().ni().deref().clone());
                     }
-                    complete_fn.borrow_mut()(Some(result));
+                    complete_fn.borrow_mut()(Ok(result));
                 }));
                 nested.set_name("Nested ValueAnnounce");
                 nested as Box<dyn Task>
             }));
 
+            // The announcement gets what is left of the deadline once the
+            // closest nodes are found.
+            if let Some(deadline) = cloned_options.deadline() {
+                let elapsed = clock::now().duration_since(started).unwrap_or_default();
+                arm_deadline(&scheduler, &announce, deadline.saturating_sub(elapsed));
+            }
+
             _task.set_nested(announce.clone());
             taskman.borrow_mut().add(announce);
         }));
 
-        self.taskman.borrow_mut().add(
-            Rc::new(RefCell::new(task))
-        );
+        self.add_task(Rc::new(RefCell::new(task)), options);
     }
 
-    pub(crate) fn find_peer<F>(&self,
+    pub(crate) fn find_peer<P, F>(&self,
         peer_id: Rc<Id>,
         expected: usize,
-        option: LookupOption,
+        options: &LookupOptions,
+        found_fn: Rc<RefCell<P>>,
         complete_fn: Rc<RefCell<F>>
-    ) where F: FnMut(Vec<PeerInfo>) + 'static {
+    ) -> TaskId where P: FnMut(&PeerInfo) + 'static, F: FnMut(Vec<PeerInfo>) + 'static {
+        let option = options.option();
         let peers = Rc::new(RefCell::new(Vec::new()));
         let dedup = Rc::new(RefCell::new(HashSet::new()));
         let cloned_peers = peers.clone();
         let mut task = Box::new(PeerLookupTask::new(self.dht(), peer_id));
         task.set_name("lookupPeer");
+        task.with_options(options);
         task.set_result_fn(move |_task, mut _peers| {
             while let Some(item) = _peers.pop() {
                 let hash = {
@@ -1014,6 +1161,7 @@ impl DHT {
                     s.finish()
                 };
                 if dedup.borrow_mut().insert(hash) {
+                    found_fn.borrow_mut()(&item);
                     cloned_peers.borrow_mut().push(item);
                 }
             }
@@ -1032,26 +1180,29 @@ impl DHT {
             complete_fn.borrow_mut()(moved_value);
         }));
 
-        self.taskman.borrow_mut().add(
-            Rc::new(RefCell::new(task))
-        );
+        self.add_task(Rc::new(RefCell::new(task)), options)
     }
 
     pub(crate) fn announce_peer<F>(&self,
         peer: &PeerInfo,
+        options: &LookupOptions,
         complete_fn: Rc<RefCell<F>>
-    ) where F: FnMut(Option<Vec<NodeInfo>>) + 'static {
+    ) where F: FnMut(Result<Vec<NodeInfo>, Error>) + 'static {
         let mut task = Box::new(NodeLookupTask::new(
             Rc::new(peer.id().clone()),
             self.dht()
         ));
         task.set_name("LookupNode");
         task.set_want_token(true);
+        task.with_options(options);
 
         let dht = self.dht();
         let taskman = self.taskman.clone();
         let p = Rc::new(peer.clone());
         let complete_fn = complete_fn.clone();
+        let cloned_options = options.clone();
+        let scheduler = self.server().borrow().scheduler();
+        let started = clock::now();
         task.add_listener(Box::new(move |_task| {
             if _task.state() != State::Finished {
                 return;
@@ -1066,7 +1217,7 @@ impl DHT {
             if closest_set.borrow().size() == 0 {
                 // This should never happen
                 warn!("!!! Peer announce task not started because the node lookup task got the empty closest nodes.");
-                complete_fn.borrow_mut()(Option::default());
+                complete_fn.borrow_mut()(Ok(Vec::new()));
                 return;
             }
 
@@ -1074,23 +1225,67 @@ impl DHT {
             let announce = Rc::new(RefCell::new({
                 let mut nested = Box::new(PeerAnnounceTask::new(dht.clone(), closest_set.clone(), p.clone()));
                 nested.set_name("PeerAnnounce");
-                nested.add_listener(Box::new(move |_| {
+                nested.with_options(&cloned_options);
+                nested.add_listener(Box::new(move |_nested| {
+                    let rejected = match _nested.as_any().downcast_ref::<PeerAnnounceTask>() {
+                        Some(downcasted) => downcasted.result().err(),
+                        None => None,
+                    };
+                    if let Some(err) = rejected {
+                        complete_fn.borrow_mut()(Err(err));
+                        return;
+                    }
+
                     let mut result = Vec::new();
                     for item in closest_set.borrow().entries().iter() {
                         result.push(item.borrow().ni().deref().clone());
                     }
-                    complete_fn.borrow_mut()(Some(result));
+                    complete_fn.borrow_mut()(Ok(result));
                 }));
                 nested.set_name("Nested PeerAnnounce");
                 nested as Box<dyn Task>
             }));
 
+            // The announcement gets what is left of the deadline once the
+            // closest nodes are found.
+            if let Some(deadline) = cloned_options.deadline() {
+                let elapsed = clock::now().duration_since(started).unwrap_or_default();
+                arm_deadline(&scheduler, &announce, deadline.saturating_sub(elapsed));
+            }
+
             _task.set_nested(announce.clone());
             taskman.borrow_mut().add(announce);
         }));
 
-        self.taskman.borrow_mut().add(
-            Rc::new(RefCell::new(task))
-        )
+        self.add_task(Rc::new(RefCell::new(task)), options);
+    }
+
+    // Queue the task of a lookup operation. The task gets finished with the
+    // results found so far once the deadline is reached. For the store and
+    // announce operations, the deadline covers the whole operation: the
+    // nested announcement only gets the time left after the lookup of the
+    // closest nodes, and is finished with what has been accepted by then.
+    fn add_task(&self, task: Rc<RefCell<Box<dyn Task>>>, options: &LookupOptions) -> TaskId {
+        if let Some(deadline) = options.deadline() {
+            arm_deadline(&self.server().borrow().scheduler(), &task, deadline);
+        }
+
+        let taskid = task.borrow().taskid();
+        self.taskman.borrow_mut().add(task);
+        taskid
     }
 }
+
+fn arm_deadline(scheduler: &Rc<RefCell<Scheduler>>, task: &Rc<RefCell<Box<dyn Task>>>, deadline: Duration) {
+    let cloned = Rc::downgrade(task);
+    scheduler.borrow_mut().add_oneshot(move || {
+        let Some(task) = cloned.upgrade() else {
+            return;
+        };
+        if task.borrow().is_finished() {
+            return;
+        }
+        debug!("Task#{} reached the deadline", task.borrow().taskid());
+        task.borrow_mut().finish();
+    }, deadline.as_millis() as u64);
+}
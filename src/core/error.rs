@@ -3,7 +3,57 @@ use std::io;
 use std::net;
 use std::result;
 
-#[derive(Debug)]
+use crate::Id;
+
+// Codes carried by the error messages sent back by remote nodes. The
+// rejected requests are answered with Protocol, told apart by the message.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorCode {
+    Generic,
+    Server,
+    Protocol,
+    MethodUnknown,
+    Unknown(i32),
+}
+
+impl ErrorCode {
+    pub fn code(&self) -> i32 {
+        match self {
+            ErrorCode::Generic          => 201,
+            ErrorCode::Server           => 202,
+            ErrorCode::Protocol         => 203,
+            ErrorCode::MethodUnknown    => 204,
+            ErrorCode::Unknown(code)    => *code,
+        }
+    }
+}
+
+impl From<i32> for ErrorCode {
+    fn from(code: i32) -> Self {
+        match code {
+            201 => ErrorCode::Generic,
+            202 => ErrorCode::Server,
+            203 => ErrorCode::Protocol,
+            204 => ErrorCode::MethodUnknown,
+            _   => ErrorCode::Unknown(code),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorCode::Generic          => "Generic",
+            ErrorCode::Server           => "Server",
+            ErrorCode::Protocol         => "Protocol",
+            ErrorCode::MethodUnknown    => "MethodUnknown",
+            ErrorCode::Unknown(_)       => "Unknown",
+        };
+        write!(f, "{}({})", name, self.code())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Error {
     Io(String),
     Network(String),
@@ -13,6 +63,12 @@ pub enum Error {
     Crypto(String),
     Db(String),
     Permission(String),
+    // The request was rejected by a remote node.
+    Remote {
+        code: ErrorCode,
+        node: Id,
+        message: String,
+    },
 }
 
 impl fmt::Display for Error {
@@ -26,6 +82,9 @@ impl fmt::Display for Error {
             Error::Crypto(msg)      => write!(f, "{}", msg),
             Error::Db(msg)          => write!(f, "{}", msg),
             Error::Permission(msg)  => write!(f, "{}", msg),
+            Error::Remote { code, node, message } => {
+                write!(f, "Remote error {} from {}: {}", code, node, message)
+            },
         }
     }
 }
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use futures_core::Stream;
+use tokio::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
+
+use crate::{
+    Network,
+    NodeInfo,
+    NodeStatus,
+    PeerInfo,
+    Value,
+};
+
+// Events emitted by a running node, which can be observed by the
+// stream acquired with Node::subscribe().
+#[derive(Clone, Debug)]
+pub enum NodeEvent {
+    StatusChanged(NodeStatus),
+    BootstrapCompleted(Network),
+    BootstrapFailed(Network),
+    ReachabilityChanged(bool),
+    EntryAdded(NodeInfo),
+    EntryRemoved(NodeInfo),
+    EntryReplaced {
+        old: NodeInfo,
+        new: NodeInfo,
+    },
+    ValueStored(Value),
+    PeerAnnounced(PeerInfo),
+}
+
+impl fmt::Display for NodeEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeEvent::StatusChanged(v) => write!(f, "status changed: {}", v),
+            NodeEvent::BootstrapCompleted(v) => write!(f, "bootstrap completed: {}", v),
+            NodeEvent::BootstrapFailed(v) => write!(f, "bootstrap failed: {}", v),
+            NodeEvent::ReachabilityChanged(v) => write!(f, "reachability changed: {}", v),
+            NodeEvent::EntryAdded(v) => write!(f, "entry added: {}", v),
+            NodeEvent::EntryRemoved(v) => write!(f, "entry removed: {}", v),
+            NodeEvent::EntryReplaced { old, new } => write!(f, "entry replaced: {} -> {}", old, new),
+            NodeEvent::ValueStored(v) => write!(f, "value stored: {}", v.id()),
+            NodeEvent::PeerAnnounced(v) => write!(f, "peer announced: {}", v.id()),
+        }
+    }
+}
+
+// Fan-out of node events to all subscribers. Shared between the Node handle
+// and the working thread, subscribers dropping their streams are pruned on
+// the next publish.
+pub(crate) struct EventBus {
+    subscribers: Mutex<Vec<UnboundedSender<NodeEvent>>>,
+}
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> EventStream {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.subscribers.lock().unwrap().push(tx);
+        EventStream { rx }
+    }
+
+    pub(crate) fn publish(&self, event: NodeEvent) {
+        self.subscribers.lock().unwrap().retain(|tx| {
+            tx.send(event.clone()).is_ok()
+        });
+    }
+}
+
+// Stream of the events published after the subscription was made. The last
+// event of a stopped node is StatusChanged(Stopped), while the stream itself
+// only ends once the event bus is dropped, which takes the node to be stopped
+// and the Node it was acquired from, along with all of its clones, dropped.
+pub struct EventStream {
+    rx: UnboundedReceiver<NodeEvent>,
+}
+
+impl EventStream {
+    pub async fn next(&mut self) -> Option<NodeEvent> {
+        self.rx.recv().await
+    }
+
+    pub fn try_next(&mut self) -> Option<NodeEvent> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = NodeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
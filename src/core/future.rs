@@ -2,21 +2,31 @@ use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll, Waker};
 use std::future::Future;
+use futures_core::Stream;
+use tokio::sync::mpsc::{UnboundedSender, UnboundedReceiver};
 
 use crate::{
     Id,
     LookupOption,
+    LookupOptions,
     NodeInfo,
     PeerInfo,
     Value,
     JointResult,
+    Network,
+    Stats,
+    RoutingTableSnapshot,
     error::Result,
 };
 
+use crate::core::task::task::TaskId;
+use crate::core::dht::BootstrapState;
+
 pub(crate) struct CmdData<T> {
     result: Option<Result<T>>,
     waker : Option<Waker>,
-    completed: bool
+    completed: bool,
+    tasks : Vec<(Network, TaskId)>,   // tasks running on behalf of the command.
 }
 
 impl<T> CmdData<T> {
@@ -25,6 +35,7 @@ impl<T> CmdData<T> {
             result: None,
             waker : None,
             completed: false,
+            tasks : Vec::new(),
         }
     }
 }
@@ -40,9 +51,15 @@ pub(crate) trait Cmd {
     }
 
     fn complete(&mut self, result: Result<Self::CmdResult>) {
+        // The first completion wins, and it could happen even before
+        // the future has been polled.
+        if self.data().completed {
+            return;
+        }
+
+        self.data_mut().result = Some(result);
+        self.data_mut().completed = true;
         if let Some(waker) = self.data_mut().waker.take() {
-            self.data_mut().result = Some(result);
-            self.data_mut().completed = true;
             waker.wake();
         }
     }
@@ -51,24 +68,37 @@ pub(crate) trait Cmd {
         self.data().completed
     }
 
-    fn set_waker(&mut self, waker: Waker) {
+    fn add_task(&mut self, network: Network, taskid: TaskId) {
+        self.data_mut().tasks.push((network, taskid))
+    }
+
+    fn tasks(&self) -> &[(Network, TaskId)] {
+        &self.data().tasks
+    }
+
+    // Register the waker unless the command has been completed, both being
+    // checked under the same lock so that no wakeup would be missed.
+    fn set_waker(&mut self, waker: Waker) -> bool {
+        if self.data().completed {
+            return true;
+        }
         self.data_mut().waker = Some(waker);
-        self.data_mut().completed = false;
+        false
     }
 }
 
 pub(crate) struct FindNodeCmd {
     data: CmdData<JointResult<NodeInfo>>,
     target: Id,
-    option: LookupOption,
+    options: LookupOptions,
 }
 
 impl FindNodeCmd {
-    pub(crate) fn new(target: &Id, option: &LookupOption) -> Self {
+    pub(crate) fn new(target: &Id, options: &LookupOptions) -> Self {
         Self {
             data: CmdData::new(),
             target: target.clone(),
-            option: option.clone(),
+            options: options.clone(),
         }
     }
 
@@ -76,8 +106,12 @@ impl FindNodeCmd {
         &self.target
     }
 
-    pub(crate) fn option(&self) -> &LookupOption {
-        &self.option
+    pub(crate) fn option(&self) -> LookupOption {
+        self.options.option()
+    }
+
+    pub(crate) fn options(&self) -> &LookupOptions {
+        &self.options
     }
 }
 
@@ -95,15 +129,15 @@ impl Cmd for FindNodeCmd {
 pub(crate) struct FindValueCmd {
     data: CmdData<Option<Value>>,
     value_id: Id,
-    option: LookupOption,
+    options: LookupOptions,
 }
 
 impl FindValueCmd {
-    pub(crate) fn new(value_id: &Id, option: &LookupOption) -> Self {
+    pub(crate) fn new(value_id: &Id, options: &LookupOptions) -> Self {
         Self {
             data: CmdData::new(),
             value_id: value_id.clone(),
-            option: option.clone(),
+            options: options.clone(),
         }
     }
 
@@ -111,8 +145,12 @@ impl FindValueCmd {
         &self.value_id
     }
 
-    pub(crate) fn option(&self) -> &LookupOption {
-        &self.option
+    pub(crate) fn option(&self) -> LookupOption {
+        self.options.option()
+    }
+
+    pub(crate) fn options(&self) -> &LookupOptions {
+        &self.options
     }
 }
 
@@ -131,16 +169,30 @@ pub(crate) struct FindPeerCmd {
     data: CmdData<Vec<PeerInfo>>,
     peer_id: Id,
     expected_num: usize,
-    option: LookupOption
+    options: LookupOptions,
+    stream: Option<UnboundedSender<PeerInfo>>,
 }
 
 impl FindPeerCmd {
-    pub(crate) fn new(peer_id: &Id, expected_num: usize, option: &LookupOption) -> Self {
+    pub(crate) fn new(peer_id: &Id, expected_num: usize, options: &LookupOptions) -> Self {
         Self {
             data: CmdData::new(),
             peer_id: peer_id.clone(),
             expected_num,
-            option: option.clone(),
+            options: options.clone(),
+            stream: None,
+        }
+    }
+
+    pub(crate) fn with_stream(mut self, tx: UnboundedSender<PeerInfo>) -> Self {
+        self.stream = Some(tx);
+        self
+    }
+
+    // Deliver the peer found to the stream, if there is one.
+    pub(crate) fn stream_peer(&self, peer: &PeerInfo) {
+        if let Some(tx) = self.stream.as_ref() {
+            _ = tx.send(peer.clone());
         }
     }
 
@@ -152,8 +204,12 @@ impl FindPeerCmd {
         self.expected_num
     }
 
-    pub(crate) fn option(&self) -> &LookupOption {
-        &self.option
+    pub(crate) fn option(&self) -> LookupOption {
+        self.options.option()
+    }
+
+    pub(crate) fn options(&self) -> &LookupOptions {
+        &self.options
     }
 }
 
@@ -171,15 +227,17 @@ impl Cmd for FindPeerCmd {
 pub(crate) struct StoreValueCmd {
     data: CmdData<()>,
     value: Value,
-    persistent: bool
+    persistent: bool,
+    options: LookupOptions,
 }
 
 impl StoreValueCmd {
-    pub(crate) fn new(value: &Value, persistent: bool) -> Self {
+    pub(crate) fn new(value: &Value, persistent: bool, options: &LookupOptions) -> Self {
         Self {
             data: CmdData::new(),
             value: value.clone(),
             persistent: persistent,
+            options: options.clone(),
         }
     }
 
@@ -190,6 +248,10 @@ impl StoreValueCmd {
     pub(crate) fn persistent(&self) -> bool {
         self.persistent
     }
+
+    pub(crate) fn options(&self) -> &LookupOptions {
+        &self.options
+    }
 }
 
 impl Cmd for StoreValueCmd {
@@ -207,14 +269,16 @@ pub(crate) struct AnnouncePeerCmd {
     data: CmdData<()>,
     peer: PeerInfo,
     persistent: bool,
+    options: LookupOptions,
 }
 
 impl AnnouncePeerCmd {
-    pub(crate) fn new(peer: &PeerInfo, persistent: bool) -> Self {
+    pub(crate) fn new(peer: &PeerInfo, persistent: bool, options: &LookupOptions) -> Self {
         Self {
             data: CmdData::new(),
             peer: peer.clone(),
             persistent,
+            options: options.clone(),
         }
     }
 
@@ -225,6 +289,10 @@ impl AnnouncePeerCmd {
     pub(crate) fn persistent(&self) -> bool {
         self.persistent
     }
+
+    pub(crate) fn options(&self) -> &LookupOptions {
+        &self.options
+    }
 }
 
 impl Cmd for AnnouncePeerCmd {
@@ -400,6 +468,97 @@ impl Cmd for GetPeerIdsCmd {
     }
 }
 
+pub(crate) struct GetStatsCmd {
+    data: CmdData<Stats>,
+}
+
+impl GetStatsCmd {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: CmdData::new(),
+        }
+    }
+}
+
+impl Cmd for GetStatsCmd {
+    type CmdResult = Stats;
+
+    fn data(&self) -> &CmdData<Self::CmdResult> {
+        &self.data
+    }
+    fn data_mut(&mut self) -> &mut CmdData<Self::CmdResult> {
+        &mut self.data
+    }
+}
+
+pub(crate) struct GetRoutingTableCmd {
+    data: CmdData<RoutingTableSnapshot>,
+    network: Network,
+}
+
+impl GetRoutingTableCmd {
+    pub(crate) fn new(network: Network) -> Self {
+        Self {
+            data: CmdData::new(),
+            network,
+        }
+    }
+
+    pub(crate) fn network(&self) -> Network {
+        self.network
+    }
+}
+
+impl Cmd for GetRoutingTableCmd {
+    type CmdResult = RoutingTableSnapshot;
+
+    fn data(&self) -> &CmdData<Self::CmdResult> {
+        &self.data
+    }
+    fn data_mut(&mut self) -> &mut CmdData<Self::CmdResult> {
+        &mut self.data
+    }
+}
+
+// Whether the node is ready, along with the outcome of the latest bootstrap
+// round on each network.
+pub(crate) struct Readiness {
+    pub(crate) ready: bool,
+    pub(crate) bootstrap: Vec<(Network, BootstrapState)>,
+}
+
+impl Readiness {
+    pub(crate) fn describe_bootstrap(&self) -> String {
+        self.bootstrap.iter()
+            .map(|(network, state)| format!("{} {}", network, state))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub(crate) struct CheckReadyCmd {
+    data: CmdData<Readiness>,
+}
+
+impl CheckReadyCmd {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: CmdData::new(),
+        }
+    }
+}
+
+impl Cmd for CheckReadyCmd {
+    type CmdResult = Readiness;
+
+    fn data(&self) -> &CmdData<Self::CmdResult> {
+        &self.data
+    }
+    fn data_mut(&mut self) -> &mut CmdData<Self::CmdResult> {
+        &mut self.data
+    }
+}
+
 #[derive(Clone)]
 pub(crate) enum Command {
     FindNode(Arc<Mutex<FindNodeCmd>>),
@@ -413,6 +572,10 @@ pub(crate) enum Command {
     GetPeer(Arc<Mutex<GetPeerCmd>>),
     RemovePeer(Arc<Mutex<RemovePeerCmd>>),
     GetPeerIds(Arc<Mutex<GetPeerIdsCmd>>),
+    GetStats(Arc<Mutex<GetStatsCmd>>),
+    GetRoutingTable(Arc<Mutex<GetRoutingTableCmd>>),
+    CheckReady(Arc<Mutex<CheckReadyCmd>>),
+    Cancel(Box<Command>),
 }
 
 impl Command {
@@ -429,10 +592,23 @@ impl Command {
             Command::GetPeer(c)     => c.lock().unwrap().is_completed(),
             Command::RemovePeer(c)  => c.lock().unwrap().is_completed(),
             Command::GetPeerIds(c) => c.lock().unwrap().is_completed(),
+            Command::GetStats(c)    => c.lock().unwrap().is_completed(),
+            Command::GetRoutingTable(c) => c.lock().unwrap().is_completed(),
+            Command::CheckReady(c)  => c.lock().unwrap().is_completed(),
+            Command::Cancel(_)      => true,
         }
     }
 
-    fn set_waker(&mut self, waker: Waker) {
+    pub(crate) fn tasks(&self) -> Vec<(Network, TaskId)> {
+        match self {
+            Command::FindNode(c)    => c.lock().unwrap().tasks().to_vec(),
+            Command::FindValue(c)   => c.lock().unwrap().tasks().to_vec(),
+            Command::FindPeer(c)    => c.lock().unwrap().tasks().to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn set_waker(&mut self, waker: Waker) -> bool {
         match self {
             Command::FindNode(s)    => s.lock().unwrap().set_waker(waker),
             Command::FindValue(s)   => s.lock().unwrap().set_waker(waker),
@@ -445,17 +621,44 @@ impl Command {
             Command::GetPeer(c)     => c.lock().unwrap().set_waker(waker),
             Command::RemovePeer(c)  => c.lock().unwrap().set_waker(waker),
             Command::GetPeerIds(c)  => c.lock().unwrap().set_waker(waker),
+            Command::GetStats(c)    => c.lock().unwrap().set_waker(waker),
+            Command::GetRoutingTable(c) => c.lock().unwrap().set_waker(waker),
+            Command::CheckReady(c)  => c.lock().unwrap().set_waker(waker),
+            Command::Cancel(_)      => true,
         }
     }
 }
 
 pub(crate) struct CmdFuture {
     command: Command,
+    cancel_fn: Option<Box<dyn FnOnce(Command) + Send>>,
 }
 
 impl CmdFuture {
     pub(crate) fn new(command: Command) -> Self {
-        Self { command }
+        Self {
+            command,
+            cancel_fn: None
+        }
+    }
+
+    // The cancel function is invoked with the command if the future gets
+    // dropped before the command has been completed.
+    pub(crate) fn with_cancel_fn<F>(mut self, f: F) -> Self
+    where F: FnOnce(Command) + Send + 'static {
+        self.cancel_fn = Some(Box::new(f));
+        self
+    }
+}
+
+impl Drop for CmdFuture {
+    fn drop(&mut self) {
+        if self.command.is_completed() {
+            return;
+        }
+        if let Some(cancel_fn) = self.cancel_fn.take() {
+            cancel_fn(self.command.clone());
+        }
     }
 }
 
@@ -463,11 +666,42 @@ impl Future for CmdFuture {
     type Output = Result<()>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if self.command.is_completed() {
-            Poll::Ready(Ok(()))
-        } else {
-            self.command.set_waker(cx.waker().clone());
-            Poll::Pending
+        match self.command.set_waker(cx.waker().clone()) {
+            true => Poll::Ready(Ok(())),
+            false => Poll::Pending
+        }
+    }
+}
+
+// Stream of the peers found by a lookup, each of which is yielded as soon as
+// it arrives. The stream ends when the lookup finishes, and dropping the
+// stream before that cancels the lookup.
+pub struct PeerStream {
+    rx: UnboundedReceiver<PeerInfo>,
+    future: CmdFuture,
+}
+
+impl PeerStream {
+    pub(crate) fn new(rx: UnboundedReceiver<PeerInfo>, future: CmdFuture) -> Self {
+        Self { rx, future }
+    }
+
+    pub async fn next(&mut self) -> Option<PeerInfo> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for PeerStream {
+    type Item = PeerInfo;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(peer) = self.rx.poll_recv(cx) {
+            return Poll::Ready(peer);
+        }
+        // All peers are delivered before the lookup gets completed.
+        match Pin::new(&mut self.future).poll(cx) {
+            Poll::Ready(_) => Poll::Ready(self.rx.try_recv().ok()),
+            Poll::Pending => Poll::Pending,
         }
     }
 }
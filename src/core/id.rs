@@ -36,7 +36,7 @@ impl Id {
 
     pub(crate) fn from_cbor(input: &Value) -> Option<Self> {
         let bytes = input.as_bytes()?;
-        Some(Id(bytes.as_slice().try_into().unwrap()))
+        Some(Id(bytes.as_slice().try_into().ok()?))
     }
 
     pub fn try_from_hexstr(input: &str) -> Result<Self> {
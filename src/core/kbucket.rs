@@ -1,7 +1,9 @@
 use std::fmt;
+use std::net::IpAddr;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::time::SystemTime;
+use std::collections::VecDeque;
 
 use rbtree::RBTree;
 use libsodium_sys::randombytes_uniform;
@@ -14,6 +16,8 @@ use crate::{
 };
 
 use crate::core::{
+    subnet_of,
+    clock,
     constants,
     node_info::Reachable,
     kbucket_entry::KBucketEntry
@@ -25,6 +29,10 @@ use crate::core::{
  * The list is sorted by time last seen : The first element is the least
  * recently seen, the last the most recently seen.
  *
+ * Contacts turned away while the bucket is full are kept in a bounded
+ * replacement cache, ordered the same way, from which the place of an
+ * entry is refilled once the entry has been removed.
+ *
  * CAUTION:
  *   All methods name leading with _ means that method will WRITE the
  *   list, it can only be called inside the routing table's
@@ -35,12 +43,21 @@ use crate::core::{
  *   on the KBucket entries and the cache entries, use for-loop instead.
  */
 
+// Outcome of putting an entry into the bucket.
+pub(crate) enum PutResult {
+    Added,
+    Merged,
+    Replaced(Rc<RefCell<KBucketEntry>>),  // the entry being replaced.
+    Ignored,
+}
+
 pub(crate) struct KBucket {
     prefix: Rc<Prefix>,
     home_bucket: bool,
 
     entries: RBTree<Id, Rc<RefCell<KBucketEntry>>>,
     youngest: Option<Rc<RefCell<KBucketEntry>>>,  // youngest one.
+    cache: VecDeque<Rc<RefCell<KBucketEntry>>>,   // replacement candidates.
 
     last_refreshed: SystemTime,
 }
@@ -52,6 +69,7 @@ impl KBucket {
             home_bucket,
             entries: RBTree::new(),
             youngest: None,
+            cache: VecDeque::new(),
 
             last_refreshed: SystemTime::UNIX_EPOCH,
         }
@@ -98,6 +116,52 @@ impl KBucket {
         self.entries.pop_first().map(|(_,v)|v)
     }
 
+    pub(crate) fn cache_entries(&self) -> Vec<Rc<RefCell<KBucketEntry>>> {
+        self.cache.iter().cloned().collect()
+    }
+
+    pub(crate) fn _take_cache(&mut self) -> Vec<Rc<RefCell<KBucketEntry>>> {
+        self.cache.drain(..).collect()
+    }
+
+    fn candidate(&self) -> Option<usize> {
+        self.cache.iter().rposition(|v| {
+            let v = v.borrow();
+            v.reachable() && !v.needs_replacement()
+        })
+    }
+
+    // Whether the contact is worth verifying to be kept as a candidate: it's
+    // still unverified in the cache, or there is room for it there.
+    pub(crate) fn wants_candidate(&self, id: &Id) -> bool {
+        match self.cache.iter().find(|v| v.borrow().id() == id) {
+            Some(v) => !v.borrow().reachable(),
+            None => self.cache.len() < constants::MAX_CACHE_ENTRIES_PER_BUCKET ||
+                self.cache.iter().any(|v| !v.borrow().reachable()),
+        }
+    }
+
+    // Entries and candidates other than the given node coming from the subnet.
+    pub(crate) fn subnet_entries(&self, subnet: &IpAddr, except: &Id) -> usize {
+        let matches = |v: &Rc<RefCell<KBucketEntry>>| {
+            let v = v.borrow();
+            v.id() != except && subnet_of(v.ni().socket_addr()).as_ref() == Some(subnet)
+        };
+
+        let mut total = 0;
+        for (_,v) in self.entries.iter() {
+            if matches(v) {
+                total += 1;
+            }
+        }
+        for v in self.cache.iter() {
+            if matches(v) {
+                total += 1;
+            }
+        }
+        total
+    }
+
     pub(crate) fn exists(&self, id: &Id) -> bool {
         self.entries.contains_key(id)
     }
@@ -113,14 +177,14 @@ impl KBucket {
     }
 
     pub(crate) fn update_refresh_time(&mut self) {
-        self.last_refreshed = SystemTime::now()
+        self.last_refreshed = clock::now()
     }
 
-    pub(crate) fn _put(&mut self, entry: Rc<RefCell<KBucketEntry>>) {
+    pub(crate) fn _put(&mut self, entry: Rc<RefCell<KBucketEntry>>) -> PutResult {
         if let Some(item) = self.entries.get_mut(entry.borrow().id()) {
             if item.borrow().equals(&entry.borrow()) {
                 item.borrow_mut().merge(entry.clone());
-                return;
+                return PutResult::Merged;
             }
 
             // NodeInfo id and address conflict
@@ -128,52 +192,119 @@ impl KBucket {
             if item.borrow().matches(&entry.borrow()) {
                 info!("New node {} claims same ID or IP as {}, might be impersonation attack or IP change.
                     ignoring until old entry times out", entry.borrow(), item.borrow());
-                return;
+                return PutResult::Ignored;
             }
         }
 
+        let result = self._put_entry(entry.clone());
+        match result {
+            PutResult::Added | PutResult::Replaced(_) => self._remove_cache(entry.borrow().id()),
+            PutResult::Ignored if self.is_full() => self._put_cache(entry),
+            _ => {}
+        }
+        result
+    }
+
+    fn _put_entry(&mut self, entry: Rc<RefCell<KBucketEntry>>) -> PutResult {
         let entry_id = entry.borrow().id().clone();
         if entry.borrow().reachable() {
             // insert to the list if it still has room
             if self.entries.len() < constants::MAX_ENTRIES_PER_BUCKET {
                 self.entries.insert(entry_id, entry.clone());
                 self.youngest = Some(entry);
-                return;
+                return PutResult::Added;
             }
 
             // Try to replace the bad entry
-            if self._replace_bad_entry(entry.clone()) {
-                return;
+            if let Some(replaced) = self._replace_bad_entry(entry.clone()) {
+                return PutResult::Replaced(replaced);
             }
 
             let youngest = match self.youngest.as_ref() {
-                Some(v) => v,
-                None => return
+                Some(v) => v.clone(),
+                None => return PutResult::Ignored
             };
 
-            if entry.borrow().created_time() > youngest.borrow().created_time() {
-                self.entries.replace_or_insert(entry_id, entry.clone());
+            // older entries displace younger ones, entries with hours uptime
+            // are much more valuable than the ones with minutes uptime.
+            if entry.borrow().created_time() < youngest.borrow().created_time() {
+                self.entries.remove(youngest.borrow().id());
+                self.entries.insert(entry_id, entry.clone());
                 self.youngest = Some(entry);
+                return PutResult::Replaced(youngest);
             }
         }
+        PutResult::Ignored
     }
 
-    pub(crate) fn on_timeout(&mut self, id: &Id) {
-        if let Some(item) = self.entries.get(id) {
-            item.borrow_mut().signal_request_timeout();
-
-            // NOTICE: Test only - merge buckets
-            //   remove when the entry needs replacement
-            // _removeIfBad(entry, false);
-            #[cfg(debug_assertions)] {
-                _ = self._remove_bad_entry(item.clone(), false);
+    // Keep the contact as a candidate, the most recently seen one last. The
+    // unverified candidates are dropped first once the cache is over.
+    pub(crate) fn _put_cache(&mut self, entry: Rc<RefCell<KBucketEntry>>) {
+        let pos = self.cache.iter().position(|v| v.borrow().id() == entry.borrow().id());
+        let mut entry = entry;
+        if let Some(item) = pos.and_then(|pos| self.cache.remove(pos)) {
+            let equals = item.borrow().equals(&entry.borrow());
+            if equals {
+                item.borrow_mut().merge(entry);
+                entry = item;
             }
+        }
+
+        self.cache.push_back(entry);
+        if self.cache.len() > constants::MAX_CACHE_ENTRIES_PER_BUCKET {
+            let pos = self.cache.iter()
+                .position(|v| !v.borrow().reachable())
+                .unwrap_or(0);
+            self.cache.remove(pos);
+        }
+    }
+
+    fn _remove_cache(&mut self, id: &Id) {
+        self.cache.retain(|v| v.borrow().id() != id);
+    }
+
+    // Fill the room in the bucket with the most recently seen candidate
+    // that has been verified.
+    pub(crate) fn _promote_candidate(&mut self) -> Option<Rc<RefCell<KBucketEntry>>> {
+        if self.is_full() {
+            return None;
+        }
+
+        let candidate = self.cache.remove(self.candidate()?)?;
+        self.entries.insert(candidate.borrow().id().clone(), candidate.clone());
+        self.youngest = Some(candidate.clone());
+        Some(candidate)
+    }
 
-            // NOTICE: Product
-            //   only removes the entry if it is bad
-            #[cfg(not(debug_assertions))] {
-                self.entries.remove(id);
+    pub(crate) fn on_timeout(&mut self, id: &Id) -> Option<Rc<RefCell<KBucketEntry>>> {
+        if let Some(pos) = self.cache.iter().position(|v| v.borrow().id() == id) {
+            let item = self.cache[pos].clone();
+            item.borrow_mut().signal_request_timeout();
+            if item.borrow().needs_replacement() {
+                self.cache.remove(pos);
             }
+            return None;
+        }
+
+        let item = self.entries.get(id)?.clone();
+        item.borrow_mut().signal_request_timeout();
+
+        // Give up the unresponsive entry if there is a candidate to take
+        // its place.
+        if item.borrow().failed_requests() > constants::KBUCKET_MAX_TIMEOUTS &&
+            self.candidate().is_some() {
+            return self.entries.remove(id);
+        }
+
+        // NOTICE: Test only - merge buckets
+        //   remove when the entry needs replacement
+        // _removeIfBad(entry, false);
+        //
+        // NOTICE: Product
+        //   only removes the entry if it is bad
+        match cfg!(debug_assertions) {
+            true  => self._remove_bad_entry(item, false),
+            false => self.entries.remove(id),
         }
     }
 
@@ -192,16 +323,13 @@ impl KBucket {
         }
     }
 
-    fn _replace_bad_entry(&mut self, new_entry: Rc<RefCell<KBucketEntry>>) -> bool {
-        let mut replaced = false;
-        for (_,v) in self.entries.iter_mut() {
-            if v.borrow().needs_replacement() {
-                v.borrow_mut().merge(new_entry);
-                replaced = true;
-                break;
-            }
-        }
-        replaced
+    fn _replace_bad_entry(&mut self, new_entry: Rc<RefCell<KBucketEntry>>
+    ) -> Option<Rc<RefCell<KBucketEntry>>> {
+        let bad = self.find_any(|v| v.borrow().needs_replacement())?;
+        self.entries.remove(bad.borrow().id());
+        self.entries.insert(new_entry.borrow().id().clone(), new_entry.clone());
+        self.youngest = Some(new_entry);
+        Some(bad)
     }
 
     fn find_any<P>(&self, mut predicate: P) -> Option<Rc<RefCell<KBucketEntry>>>
@@ -227,6 +355,9 @@ impl fmt::Display for KBucket {
         for (_,v) in self.entries.iter() {
             write!(f, " - {}\n", v.borrow())?;
         }
+        if !self.cache.is_empty() {
+            writeln!(f, " cache[{}]", self.cache.len())?;
+        }
         Ok(())
     }
 }
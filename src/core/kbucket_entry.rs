@@ -13,9 +13,11 @@ use crate::{
 };
 
 use crate::core::{
+    clock,
     constants,
     version,
     node_info::Reachable,
+    rtt_estimator::RttEstimator,
 };
 
 /**
@@ -33,6 +35,8 @@ pub(crate) struct KBucketEntry {
 
     reachable: bool,
     failed_requests: i32,
+
+    rtt: RttEstimator,
 }
 
 impl KBucketEntry {
@@ -47,11 +51,12 @@ impl KBucketEntry {
     pub(crate) fn from(ni: NodeInfo) -> Self {
         Self {
             ni: Rc::new(ni),
-            created  : SystemTime::UNIX_EPOCH,
+            created  : clock::now(),
             last_seen: SystemTime::UNIX_EPOCH,
             last_sent: SystemTime::UNIX_EPOCH,
             reachable: false,
             failed_requests: 0,
+            rtt: RttEstimator::new(),
         }
     }
 
@@ -71,7 +76,7 @@ impl KBucketEntry {
             let k = k.as_text()?;
             match k {
                 "id" => entry_id = Some(Id::from_cbor(v)?),
-                "port" => entry_port = v.as_integer()?.try_into().unwrap(),
+                "port" => entry_port = v.as_integer()?.try_into().ok()?,
                 "addr" => {
                     let addr = v.as_bytes()?;
                     if entry_port == 0 {
@@ -79,11 +84,11 @@ impl KBucketEntry {
                     }
                     let addr = SocketAddr::new(match addr.len() {
                         4 => {
-                            let ip: [u8;4] = addr.as_slice().try_into().unwrap();
+                            let ip: [u8;4] = addr.as_slice().try_into().ok()?;
                             IpAddr::V4(Ipv4Addr::from(ip))
                         },
-                        10 => {
-                            let ip: [u8;16] = addr.as_slice().try_into().unwrap();
+                        16 => {
+                            let ip: [u8;16] = addr.as_slice().try_into().ok()?;
                             IpAddr::V6(Ipv6Addr::from(ip))
                         },
                         _ => return None,
@@ -92,33 +97,33 @@ impl KBucketEntry {
                     entry_addr = Some(addr);
                 },
                 "created" => {
-                    let v = v.as_integer()?.try_into().unwrap();
-                    created += Duration::from_secs(v);
+                    let v = v.as_integer()?.try_into().ok()?;
+                    created = created.checked_add(Duration::from_secs(v))?;
                 },
                 "lastSeen" => {
-                    let v = v.as_integer()?.try_into().unwrap();
-                    last_seen += Duration::from_secs(v);
+                    let v = v.as_integer()?.try_into().ok()?;
+                    last_seen = last_seen.checked_add(Duration::from_secs(v))?;
                 },
                 "lastSend" => {
-                    let v = v.as_integer()?.try_into().unwrap();
-                    last_sent += Duration::from_secs(v);
+                    let v = v.as_integer()?.try_into().ok()?;
+                    last_sent = last_sent.checked_add(Duration::from_secs(v))?;
                 },
                 "failedRequests" => {
-                    failed_requests = v.as_integer()?.try_into().unwrap();
+                    failed_requests = v.as_integer()?.try_into().ok()?;
                 },
                 "reachable" => {
-                    reachable = v.as_bool()?.try_into().unwrap();
+                    reachable = v.as_bool()?;
                 },
                 "version" => {
-                    ver = v.as_integer()?.try_into().unwrap();
+                    ver = v.as_integer()?.try_into().ok()?;
                 }
                 _ =>  return None,
             }
         }
 
         let ni = Rc::new(NodeInfo::with_version(
-            entry_id.unwrap(),
-            entry_addr.unwrap(),
+            entry_id?,
+            entry_addr?,
             ver,
         ));
 
@@ -128,7 +133,8 @@ impl KBucketEntry {
             last_seen,
             last_sent,
             reachable,
-            failed_requests
+            failed_requests,
+            rtt: RttEstimator::new(),
         })
     }
 
@@ -144,18 +150,31 @@ impl KBucketEntry {
         &self.created
     }
 
+    pub(crate) fn last_seen(&self) -> &SystemTime {
+        &self.last_seen
+    }
+
     pub(crate) const fn failed_requests(&self) -> i32 {
         self.failed_requests
     }
 
     pub(crate) fn signal_response(&mut self) {
-        self.last_seen = SystemTime::now();
+        self.last_seen = clock::now();
         self.failed_requests = 0;
         self.reachable = true;
     }
 
+    // Should be called with the round-trip time of a request answered.
+    pub(crate) fn signal_round_trip(&mut self, rtt: Duration) {
+        self.rtt.update(rtt);
+    }
+
+    pub(crate) fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
     pub(crate) fn signal_request(&mut self) {
-        self.last_sent = SystemTime::now();
+        self.last_sent = clock::now();
     }
 
     pub(crate) fn merge_request_time(&mut self, request_sent: SystemTime) {
@@ -180,6 +199,7 @@ impl KBucketEntry {
 
     // Should be called to signal that a request to this node has timed out;
     pub(crate) fn signal_request_timeout(&mut self) {
+        self.rtt.backoff();
         if self.failed_requests <= 0 {
             self.failed_requests = 1
         } else {
@@ -212,9 +232,10 @@ impl KBucketEntry {
             return;
         }
 
-        self.created = self.created.max(binding.created);
+        self.created = self.created.min(binding.created);
         self.last_seen = self.last_seen.max(binding.last_seen);
         self.last_sent = self.last_sent.max(binding.last_sent);
+        self.rtt.merge(&binding.rtt);
 
         if binding.reachable() {
             self.set_reachable(true);
@@ -267,15 +288,15 @@ impl KBucketEntry {
             ),
             (
                 Value::Text(String::from("created")),
-                Value::Integer(self.created.elapsed().unwrap().as_secs().into())
+                Value::Integer(clock::elapsed(self.created).as_secs().into())
             ),
             (
                 Value::Text(String::from("lastSeen")),
-                Value::Integer(self.last_seen.elapsed().unwrap().as_secs().into())
+                Value::Integer(clock::elapsed(self.last_seen).as_secs().into())
             ),
             (
                 Value::Text(String::from("lastSend")),
-                Value::Integer(self.last_sent.elapsed().unwrap().as_secs().into())
+                Value::Integer(clock::elapsed(self.last_sent).as_secs().into())
             ),
             (
                 Value::Text(String::from("failedRequests")),
@@ -327,7 +348,7 @@ impl fmt::Display for KBucketEntry {
             as_millis!(&self.created)
         )?;
 
-        if self.last_sent.elapsed().is_ok() {
+        if self.last_sent <= clock::now() {
             write!(f, "; sent:{}", as_millis!(&self.last_sent))?;
         }
         if self.failed_requests > 0 {
@@ -336,6 +357,9 @@ impl fmt::Display for KBucketEntry {
         if self.reachable {
             write!(f, "; reachable")?;
         }
+        if let Some(rtt) = self.rtt.mean() {
+            write!(f, "; rtt: {}", rtt.as_millis())?;
+        }
         if self.ni.version() != 0 {
             write!(f,
                 "; ver: {}",
@@ -8,6 +8,7 @@ use crate::{
 };
 
 use crate::core::{
+    subnet_of,
     kbucket::KBucket,
     kbucket_entry::KBucketEntry,
     routing_table::RoutingTable
@@ -63,6 +64,7 @@ impl KClosestNodes {
         let mut bucket = None;
         let rt = self.rt.clone();
         let binding_rt = rt.borrow();
+        let subnet_limit = binding_rt.bucket_subnet_limit();
 
         for (k,v) in binding_rt.buckets().iter() {
             if k.is_prefix_of(&self.target) {
@@ -72,7 +74,7 @@ impl KClosestNodes {
             idx += 1;
         }
 
-        self.insert_entries(bucket.unwrap());
+        self.insert_entries(bucket.unwrap(), subnet_limit);
 
         let mut low  = idx;
         let mut high = idx;
@@ -95,12 +97,14 @@ impl KClosestNodes {
             } else if low_bucket.is_none() {
                 high += 1;
                 self.insert_entries(
-                    high_bucket.unwrap().1
+                    high_bucket.unwrap().1,
+                    subnet_limit
                 );
             } else if high_bucket.is_none() {
                 low -= 1;
                 self.insert_entries(
-                    low_bucket.unwrap().1
+                    low_bucket.unwrap().1,
+                    subnet_limit
                 );
             } else {
                 let low_bucket = low_bucket.unwrap().1;
@@ -113,17 +117,17 @@ impl KClosestNodes {
                 match ordering {
                     Ordering::Less => {
                         low -= 1;
-                        self.insert_entries(low_bucket);
+                        self.insert_entries(low_bucket, subnet_limit);
                     },
                     Ordering::Greater => {
                         high += 1;
-                        self.insert_entries(high_bucket);
+                        self.insert_entries(high_bucket, subnet_limit);
                     },
                     Ordering::Equal => {
                         low -= 1;
                         high += 1;
-                        self.insert_entries(low_bucket);
-                        self.insert_entries(high_bucket);
+                        self.insert_entries(low_bucket, subnet_limit);
+                        self.insert_entries(high_bucket, subnet_limit);
                     }
                 }
             }
@@ -144,12 +148,28 @@ impl KClosestNodes {
         self.shave();
     }
 
-    fn insert_entries(&mut self, bucket: &Rc<RefCell<KBucket>>) {
-        bucket.borrow().entries().iter().for_each(|item| {
-            if (self.filter)(item) {
+    fn insert_entries(&mut self, bucket: &Rc<RefCell<KBucket>>, subnet_limit: usize) {
+        for item in bucket.borrow().entries().iter() {
+            if (self.filter)(item) && self.admits(item, subnet_limit) {
                 self.entries.push(item.clone())
             }
-        })
+        }
+    }
+
+    // Keep the results from the same subnet within the limit of a bucket.
+    fn admits(&self, item: &Rc<RefCell<KBucketEntry>>, subnet_limit: usize) -> bool {
+        let subnet = match subnet_of(item.borrow().ni().socket_addr()) {
+            Some(v) => v,
+            None => return true,
+        };
+
+        let mut total = 0;
+        for v in self.entries.iter() {
+            if subnet_of(v.borrow().ni().socket_addr()).as_ref() == Some(&subnet) {
+                total += 1;
+            }
+        }
+        total < subnet_limit
     }
 
     fn shave(&mut self) {
@@ -1,4 +1,6 @@
-use std::sync::{Arc, Mutex};
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::io::{self, Write, IoSlice};
 use std::fs::{File, OpenOptions};
 use log::{
@@ -7,14 +9,20 @@ use log::{
     Record
 };
 
-use crate::{
-    unwrap,
-};
+// Each node owns a logger of its own, all of which are served by one
+// dispatcher installed into the log crate. Records are routed to the logger
+// of the node being polled on the current thread, or to the earliest node
+// alive for the threads of the application.
+static DISPATCHER: Dispatcher = Dispatcher;
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+static LOGGERS: Mutex<Vec<(Weak<Logger>, LevelFilter)>> = Mutex::new(Vec::new());
 
-static mut MY_LOGGER: Option<Logger> = None;
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<Logger>>> = const { RefCell::new(None) };
+}
 
-struct  Logger {
-    console_output_enabled: bool,
+pub(crate) struct Logger {
+    console_output_enabled: AtomicBool,
     max_level: LevelFilter,
     fp: Option<Arc<Mutex<File>>>,
 }
@@ -33,13 +41,14 @@ impl log::Log for Logger {
             );
 
             if let Some(fp) = self.fp.as_ref() {
-                _ = fp.lock().unwrap().write_vectored(
+                let mut fp = fp.lock().unwrap();
+                _ = fp.write_vectored(
                     &[IoSlice::new(log.as_bytes())]
                 );
-                _ = fp.lock().unwrap().write(b"\n");
+                _ = fp.write(b"\n");
             }
 
-            if self.console_output_enabled {
+            if self.console_output_enabled.load(Ordering::Relaxed) {
                 println!("{log}");
             }
         }
@@ -52,7 +61,7 @@ impl log::Log for Logger {
 impl Logger {
     pub(crate) fn new(max_level: LevelFilter, logfile: Option<&str>) -> Self {
         let mut logger = Self {
-            console_output_enabled: true,
+            console_output_enabled: AtomicBool::new(true),
             max_level,
             fp: None,
         };
@@ -69,36 +78,77 @@ impl Logger {
         logger
     }
 
-    pub(crate) fn revert_console_output(&mut self) {
-        self.console_output_enabled = !self.console_output_enabled;
+    #[allow(dead_code)]
+    pub(crate) fn revert_console_output(&self) {
+        self.console_output_enabled.fetch_xor(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        update_max_level();
     }
 }
 
-static NULL_LOGGER: NullLogger = NullLogger;
-struct NullLogger;
-impl log::Log for NullLogger {
-    fn enabled(&self, _: &Metadata) -> bool { false }
-    fn log(&self, _: &Record) {}
-    fn flush(&self) {}
+struct Dispatcher;
+impl Dispatcher {
+    fn current(&self) -> Option<Arc<Logger>> {
+        if let Some(logger) = CURRENT.with(|v| v.borrow().clone()) {
+            return Some(logger);
+        }
+        // The logger taken out would be dropped after the lock is released.
+        let loggers = LOGGERS.lock().unwrap();
+        loggers.iter().find_map(|(v, _)| v.upgrade())
+    }
 }
 
-pub(crate) fn setup(max_level: LevelFilter, logfile: Option<&str>) {
-    unsafe {
-        MY_LOGGER = Some(Logger::new(max_level, logfile));
-        _ = log::set_logger(unwrap!(MY_LOGGER));
-        _ = log::set_max_level(unwrap!(MY_LOGGER).max_level);
+impl log::Log for Dispatcher {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.current().is_some_and(|v| v.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(logger) = self.current() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        io::stdout().flush().unwrap();
     }
 }
 
-pub(crate) fn teardown() {
-    _ = log::set_logger(&NULL_LOGGER);
+fn update_max_level() {
+    let mut loggers = LOGGERS.lock().unwrap();
+    loggers.retain(|(v, _)| v.strong_count() > 0);
+    let level = loggers.iter()
+        .map(|(_, level)| *level)
+        .max()
+        .unwrap_or(LevelFilter::Off);
+    log::set_max_level(level);
 }
 
-#[allow(dead_code)]
-pub(crate) fn revert_console_output() {
-    unsafe {
-        MY_LOGGER.as_mut().map(|v| v.revert_console_output());
+// Create the logger for a node. Nothing is created if logging is turned off,
+// or another logger has already been installed by the application, in which
+// case the records from all nodes go there instead.
+pub(crate) fn setup(max_level: LevelFilter, logfile: Option<&str>) -> Option<Arc<Logger>> {
+    if max_level == LevelFilter::Off {
+        return None;
+    }
+    if !*INSTALLED.get_or_init(|| log::set_logger(&DISPATCHER).is_ok()) {
+        return None;
     }
+
+    let logger = Arc::new(Logger::new(max_level, logfile));
+    LOGGERS.lock().unwrap().push((Arc::downgrade(&logger), max_level));
+    update_max_level();
+    Some(logger)
+}
+
+// Route the records on the current thread to the given logger, returning
+// the one entered before to be restored later.
+pub(crate) fn enter(logger: Option<Arc<Logger>>) -> Option<Arc<Logger>> {
+    CURRENT.with(|v| v.replace(logger))
 }
 
 pub(crate) fn convert_loglevel(loglevel: &str) -> LevelFilter {
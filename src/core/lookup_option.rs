@@ -1,4 +1,7 @@
 use std::fmt;
+use std::time::Duration;
+
+use crate::core::constants;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
@@ -19,3 +22,111 @@ impl fmt::Display for LookupOption {
         })
     }
 }
+
+// Per-operation options of the lookups, including the store and announce
+// operations which start with a lookup of the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupOptions {
+    option: LookupOption,
+    deadline: Option<Duration>,
+    rpc_timeout: Option<Duration>,
+    alpha: usize,
+    width: usize,
+    max_hops: Option<usize>,
+    disjoint_paths: usize,
+}
+
+impl LookupOptions {
+    pub fn new(option: LookupOption) -> Self {
+        Self {
+            option,
+            deadline: None,
+            rpc_timeout: None,
+            alpha: constants::LOOKUP_CONCURRENCY,
+            width: constants::MAX_ENTRIES_PER_BUCKET,
+            max_hops: None,
+            disjoint_paths: 1,
+        }
+    }
+
+    // The operation completes with the results found so far once the
+    // deadline is reached.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    // Fixed timeout of the requests, in place of the one derived from the
+    // round-trip times measured on the remote nodes.
+    pub fn with_rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_timeout = Some(timeout);
+        self
+    }
+
+    // Maximum number of requests in flight at the same time.
+    pub fn with_alpha(mut self, alpha: usize) -> Self {
+        self.alpha = alpha.max(1);
+        self
+    }
+
+    // Number of the closest nodes to collect (the k in Kademlia).
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    // Maximum number of rounds the lookup goes towards the target.
+    pub fn with_max_hops(mut self, hops: usize) -> Self {
+        self.max_hops = Some(hops.max(1));
+        self
+    }
+
+    // Number of the disjoint paths the lookup takes, none of the nodes
+    // being queried on more than one of them, with the results merged.
+    // A few adversarial nodes close to the target can hardly mislead all
+    // of the paths (S/Kademlia).
+    pub fn with_disjoint_paths(mut self, paths: usize) -> Self {
+        self.disjoint_paths = paths.max(1);
+        self
+    }
+
+    pub fn option(&self) -> LookupOption {
+        self.option
+    }
+
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    pub fn rpc_timeout(&self) -> Option<Duration> {
+        self.rpc_timeout
+    }
+
+    pub fn alpha(&self) -> usize {
+        self.alpha
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn max_hops(&self) -> Option<usize> {
+        self.max_hops
+    }
+
+    pub fn disjoint_paths(&self) -> usize {
+        self.disjoint_paths
+    }
+}
+
+impl Default for LookupOptions {
+    fn default() -> Self {
+        Self::new(LookupOption::Conservative)
+    }
+}
+
+impl From<LookupOption> for LookupOptions {
+    fn from(option: LookupOption) -> Self {
+        Self::new(option)
+    }
+}
@@ -1,16 +1,17 @@
-mod constants;
-mod crypto_cache;
-mod dht;
+pub(crate) mod dht;
 mod kbucket;
-mod kclosest_nodes;
+pub(crate) mod kclosest_nodes;
 mod server;
 mod rpccall;
-mod task;
-mod scheduler;
+pub(crate) mod task;
 mod sqlite3;
 
 pub(crate) mod msg;
+pub(crate) mod constants;
 pub(crate) mod cbor;
+pub(crate) mod crypto_cache;
+pub(crate) mod replay_window;
+pub(crate) mod rtt_estimator;
 pub(crate) mod logger;
 pub(crate) mod data_storage;
 pub(crate) mod kbucket_entry;
@@ -21,12 +22,15 @@ pub(crate) mod node_runner;
 pub(crate) mod bootstrap_channel;
 pub(crate) mod version;
 pub(crate) mod future;
+pub(crate) mod scheduler;
 
 pub mod id;
+pub mod clock;
 pub mod config;
 pub mod cryptobox;
 pub mod default_configuration;
 pub mod error;
+pub mod event;
 pub mod lookup_option;
 pub mod node_info;
 pub mod node_status;
@@ -36,6 +40,9 @@ pub mod joint_result;
 pub mod network;
 pub mod node;
 pub mod signature;
+pub mod routing_snapshot;
+pub mod stats;
+pub mod transport;
 pub mod value;
 
 #[macro_export]
@@ -121,6 +128,28 @@ pub(crate) fn is_any_unicast(ip: &IpAddr) -> bool {
     is_global_unicast(ip) || is_sitelocal(ip)
 }
 
+// The /24 network of an IPv4 address or the /64 network of an IPv6 address,
+// which the entries taken into the routing table are limited by. Addresses
+// out of the public network are left alone.
+pub(crate) fn subnet_of(addr: &SocketAddr) -> Option<IpAddr> {
+    let ip = addr.ip();
+    if !is_global_unicast(&ip) {
+        return None;
+    }
+    match ip {
+        IpAddr::V4(v4) => {
+            let mut octets = v4.octets();
+            octets[3] = 0;
+            Some(IpAddr::from(octets))
+        },
+        IpAddr::V6(v6) => {
+            let mut octets = v6.octets();
+            octets[8..].fill(0);
+            Some(IpAddr::from(octets))
+        }
+    }
+}
+
 pub(crate) fn is_bogon(addr: &SocketAddr) -> bool {
    !(addr.port() > 0 &&
      addr.port() < 0xFFFF && is_global_unicast(&addr.ip()))
@@ -49,11 +49,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "q" => {
@@ -63,10 +63,10 @@ impl Msg for Message {
                         match k {
                             "t" => self.peerid = Some(Id::from_cbor(v)?),
                             "x" => self.origin = Id::from_cbor(v),
-                            "p" => self.port = Some(v.as_integer()?.try_into().unwrap()),
+                            "p" => self.port = Some(v.as_integer()?.try_into().ok()?),
                             "alt" => self.url = v.as_text().map(|v|v.to_string()),
                             "sig" => self.sig = Some(v.as_bytes()?.clone()),
-                            "tok" => self.token = v.as_integer()?.try_into().unwrap(),
+                            "tok" => self.token = v.as_integer()?.try_into().ok()?,
                             _ => return None,
                         }
                     }
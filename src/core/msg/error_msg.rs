@@ -4,7 +4,7 @@ use ciborium::Value as CVal;
 
 use crate::{
     Error,
-    error::Result
+    error::{ErrorCode, Result}
 };
 use crate::core::version;
 use super::msg::{
@@ -15,7 +15,7 @@ use super::msg::{
 pub(crate) struct Message {
     base_data: MsgData,
     msg: String,
-    code: i32,
+    code: ErrorCode,
 }
 
 impl Msg for Message {
@@ -34,11 +34,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "e" => {
@@ -46,7 +46,10 @@ impl Msg for Message {
                     for (k,v) in map {
                         let k = k.as_text()?;
                         match k {
-                            "c" => self.code = v.as_integer()?.try_into().unwrap(),
+                            "c" => {
+                                let code: i32 = v.as_integer()?.try_into().ok()?;
+                                self.code = ErrorCode::from(code);
+                            },
                             "m" => self.msg = v.as_text()?.to_string(),
                             _ => return None,
                         }
@@ -62,7 +65,7 @@ impl Msg for Message {
         let val = CVal::Map(vec![
             (
                 CVal::Text(String::from("c")),
-                CVal::Integer(self.code.into())
+                CVal::Integer(self.code.code().into())
             ),
             (
                 CVal::Text(String::from("m")),
@@ -89,7 +92,7 @@ impl Message {
     pub(crate) fn new(method: Method, txid: i32) -> Self {
         Message {
             base_data: MsgData::new(Kind::Error, method, txid),
-            code: 0,
+            code: ErrorCode::Unknown(0),
             msg: String::from(""),
         }
     }
@@ -98,7 +101,7 @@ impl Message {
         &self.msg
     }
 
-    pub(crate) fn code(&self) -> i32 {
+    pub(crate) fn code(&self) -> ErrorCode {
         self.code
     }
 
@@ -106,9 +109,18 @@ impl Message {
         self.msg = String::from(str);
     }
 
-    pub(crate) fn with_code(&mut self, code: i32) {
+    pub(crate) fn with_code(&mut self, code: ErrorCode) {
         self.code = code
     }
+
+    // The error to report to the callers of the rejected request.
+    pub(crate) fn to_error(&self) -> Error {
+        Error::Remote {
+            code: self.code,
+            node: self.id().clone(),
+            message: self.msg.clone(),
+        }
+    }
 }
 
 impl TryFrom<CVal> for Box<Message> {
@@ -130,7 +142,7 @@ impl fmt::Display for Message {
             self.kind(),
             self.method(),
             self.txid(),
-            self.code(),
+            self.code().code(),
             self.msg(),
             version::canonical_version(self.ver())
         )?;
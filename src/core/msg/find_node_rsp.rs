@@ -35,6 +35,10 @@ impl Msg for Message {
         &mut self.base_data
     }
 
+    fn trim(&mut self) -> bool {
+        self.trim_nodes()
+    }
+
     fn from_cbor(&mut self, input: &CVal) -> Option<()> {
         let root = input.as_map()?;
         for (k, v) in root {
@@ -42,11 +46,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "r" => {
@@ -74,7 +78,7 @@ impl Msg for Message {
                             },
                             "tok" => {
                                 self.populate_token(
-                                    v.as_integer()?.try_into().unwrap()
+                                    v.as_integer()?.try_into().ok()?
                                 );
                             }
                             _ => return None
@@ -42,11 +42,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "q" => {
@@ -55,7 +55,7 @@ impl Msg for Message {
                         let k = k.as_text()?;
                         match k {
                             "w" => {
-                                let want: i32 = v.as_integer()?.try_into().unwrap();
+                                let want: i32 = v.as_integer()?.try_into().ok()?;
                                 self.with_want4((want & 0x01) != 0);
                                 self.with_want6((want & 0x02) != 0);
                                 self.with_want_token((want & 0x04) != 0);
@@ -70,6 +70,9 @@ impl Msg for Message {
                 _ => return None,
             }
         }
+        if !self.has_target() {
+            return None;
+        }
         Some(())
     }
 
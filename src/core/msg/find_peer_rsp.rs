@@ -43,6 +43,10 @@ impl Msg for Message {
         &mut self.base_data
     }
 
+    fn trim(&mut self) -> bool {
+        self.trim_nodes() || self.peers.pop().is_some()
+    }
+
     fn from_cbor(&mut self, input: &CVal) -> Option<()> {
         let root = input.as_map()?;
         for (k,v) in root {
@@ -50,11 +54,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "r" => {
@@ -82,11 +86,14 @@ impl Msg for Message {
                             },
                             "tok" => {
                                 self.populate_token(
-                                    v.as_integer()?.try_into().unwrap()
+                                    v.as_integer()?.try_into().ok()?
                                 );
                             },
                             "p" => {
                                 let v = v.as_array()?;
+                                if v.is_empty() {
+                                    continue;
+                                }
                                 let peer_id = Id::from_cbor(v.get(0)?)?;
                                 for item in v.iter() {
                                     if item.is_null() {
@@ -100,7 +107,7 @@ impl Msg for Message {
                                             true => None,
                                             false => Id::from_cbor(v.get(1)?)
                                         };
-                                        let port = v.get(2)?.as_integer()?.try_into().unwrap();
+                                        let port = v.get(2)?.as_integer()?.try_into().ok()?;
                                         let alt = v.get(3)?.as_text();
                                         let sig = v.get(4)?.as_bytes()?;
 
@@ -157,11 +164,13 @@ impl Msg for Message {
         });
 
         let mut rsp = LookupResponse::to_cbor(self);
-        if let Some(map) = rsp.as_map_mut() {
-            map.push((
-                CVal::Text(String::from("p")),
-                CVal::Array(array))
-            );
+        if !array.is_empty() {
+            if let Some(map) = rsp.as_map_mut() {
+                map.push((
+                    CVal::Text(String::from("p")),
+                    CVal::Array(array))
+                );
+            }
         }
 
         let mut root = Msg::to_cbor(self);
@@ -44,11 +44,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "q" => {
@@ -57,7 +57,7 @@ impl Msg for Message {
                         let k = k.as_text()?;
                         match k {
                             "w" => {
-                                let want: i32 = v.as_integer()?.try_into().unwrap();
+                                let want: i32 = v.as_integer()?.try_into().ok()?;
                                 self.with_want4((want & 0x01) != 0);
                                 self.with_want6((want & 0x02) != 0);
                                 self.with_want_token((want & 0x04) != 0);
@@ -66,7 +66,7 @@ impl Msg for Message {
                                 self.with_target(Rc::new(Id::from_cbor(v)?));
                             },
                             "seq" => {
-                                let seq = v.as_integer()?.try_into().unwrap();
+                                let seq = v.as_integer()?.try_into().ok()?;
                                 self.seq = if seq > 0 { seq } else {0};
                             }
                             _ => return None,
@@ -76,6 +76,9 @@ impl Msg for Message {
                 _ => return None,
             }
         }
+        if !self.has_target() {
+            return None;
+        }
         Some(())
     }
 
@@ -43,6 +43,10 @@ impl Msg for Message {
         &mut self.base_data
     }
 
+    fn trim(&mut self) -> bool {
+        self.trim_nodes()
+    }
+
     fn from_cbor(&mut self, input: &CVal) -> Option<()> {
         let root = input.as_map()?;
         for (k,v) in root {
@@ -50,11 +54,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "r" => {
@@ -89,28 +93,29 @@ impl Msg for Message {
                             },
                             "tok" => {
                                 self.populate_token(
-                                    v.as_integer()?.try_into().unwrap()
+                                    v.as_integer()?.try_into().ok()?
                                 );
                             },
                             "k" =>   pk  = Some(Id::from_cbor(v)?),    // public_key
                             "rec" => rec = Some(Id::from_cbor(v)?),    // recipient.
-                            "n" => nonce = Some(cryptobox::Nonce::try_from(v.as_bytes()?.as_slice()).unwrap()), // nonce.
+                            "n" => nonce = Some(cryptobox::Nonce::try_from(v.as_bytes()?.as_slice()).ok()?), // nonce.
                             "s" =>   sig = Some(v.as_bytes()?),                 // signature.
-                            "seq" => seq = v.as_integer()?.try_into().unwrap(), // sequence number
+                            "seq" => seq = v.as_integer()?.try_into().ok()?, // sequence number
                             "v" =>  data = Some(v.as_bytes()?.clone()),         // data
                             _ => return None
                         }
                     }
-                    self.value = data.map_or(None, |v|
-                        Some(Rc::new(PackBuilder::new(v)
+                    if let Some(data) = data {
+                        self.value = Some(Rc::new(PackBuilder::new(data)
                             .with_pk(pk)
                             .with_sk(None)
                             .with_rec(rec)
                             .with_nonce(nonce.take())
                             .with_sig(sig.map(|v|v.to_vec()))
                             .with_seq(seq)
-                            .build()
-                    )));
+                            .try_build()?
+                        ));
+                    }
                 },
                 _ => return None,
             }
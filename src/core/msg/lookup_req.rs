@@ -30,6 +30,10 @@ pub(crate) trait Msg {
         unwrap!(self.data().target).clone()
     }
 
+    fn has_target(&self) -> bool {
+        self.data().target.is_some()
+    }
+
     fn want4(&self) -> bool {
         self.data().want4
     }
@@ -47,6 +47,18 @@ pub(crate) trait Msg {
         self.data_mut().token = token
     }
 
+    // Drop the farthest node from the longer list of closest nodes.
+    fn trim_nodes(&mut self) -> bool {
+        let data = self.data_mut();
+        let len4 = data.nodes4.as_ref().map_or(0, |v| v.len());
+        let len6 = data.nodes6.as_ref().map_or(0, |v| v.len());
+        let nodes = match len4 >= len6 {
+            true => data.nodes4.as_mut(),
+            false => data.nodes6.as_mut(),
+        };
+        nodes.is_some_and(|v| v.pop().is_some())
+    }
+
     fn to_cbor(&self) -> CVal {
         let nodes4 = self.nodes4().map_or(vec![], |ns| {
             ns.iter().map(|v| v.to_cbor()).collect()
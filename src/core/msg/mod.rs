@@ -39,28 +39,19 @@ pub(crate) fn deser(buf: &[u8]) -> Result<Rc<RefCell<Box<dyn Msg>>>> {
             return Error::Protocol(format!("Reading cobor error: {}", e))
         )?;
 
-    let mtype = {
-        let map = value.as_map().unwrap();
-        let mut _v = None;
-        for (k,v) in map.iter() {
-            let k = k.as_text().unwrap();
-            if k == "y" {
-                _v = Some(v);
-                break;
-            }
-        }
-        _v.unwrap().as_integer()
-            .unwrap()
-            .try_into()
-            .unwrap()
-    };
+    let mtype: i32 = value.as_map()
+        .and_then(|map| map.iter().find(|(k,_)| k.as_text() == Some("y")))
+        .and_then(|(_,v)| v.as_integer())
+        .and_then(|v| v.try_into().ok())
+        .ok_or_else(|| Error::Protocol("Missing or invalid message type".into()))?;
+
     if !Kind::is_valid(mtype) || !Method::is_valid(mtype) {
         return Err(Error::Protocol(format!(
-            "Invalid message kind {} or method {}", Kind::from(mtype), Method::from(mtype)
+            "Invalid message type {}", mtype
         )));
     }
 
-    let msg = match Kind::from(mtype) {
+    let msg = match Kind::try_from(mtype)? {
         Kind::Error     => value.try_into().map(|v: Box<error_msg::Message>| v as Box<dyn Msg>)?,
         Kind::Request   => match Method::from(mtype) {
             Method::Ping        => value.try_into().map(|v: Box<ping_req::Message>| v as Box<dyn Msg>)?,
@@ -99,3 +90,17 @@ pub(crate) fn serialize(msg: Rc<RefCell<Box<dyn Msg>>>) -> Vec<u8> {
 
     buf
 }
+
+// Serialize the message within the given size, trimming it as needed,
+// or nothing if it can't be made small enough.
+pub(crate) fn serialize_within(msg: Rc<RefCell<Box<dyn Msg>>>, limit: usize) -> Option<Vec<u8>> {
+    loop {
+        let buf = serialize(msg.clone());
+        if buf.len() <= limit {
+            return Some(buf);
+        }
+        if !msg.borrow_mut().trim() {
+            return None;
+        }
+    }
+}
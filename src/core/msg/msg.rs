@@ -13,11 +13,12 @@ use crate::{
     Id
 };
 use crate::core::{
-    rpccall::RpcCall
+    rpccall::RpcCall,
+    error::{Error, Result},
 };
 
-#[derive(PartialEq, Clone, Copy)]
-pub(crate) enum Kind {
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Kind {
     Error = 0,
     Request = 0x20,
     Response = 0x40,
@@ -43,14 +44,15 @@ impl Kind {
     }
 }
 
-impl From<i32> for Kind {
-    fn from(_type: i32) -> Kind {
+impl TryFrom<i32> for Kind {
+    type Error = Error;
+    fn try_from(_type: i32) -> Result<Kind> {
         let kind = _type & Self::MASK;
         match kind {
-            0x00 => Kind::Error,
-            0x20 => Kind::Request,
-            0x40 => Kind::Response,
-            _ => panic!("invalid msg kind: {}", kind)
+            0x00 => Ok(Kind::Error),
+            0x20 => Ok(Kind::Request),
+            0x40 => Ok(Kind::Response),
+            _ => Err(Error::Protocol(format!("Invalid message kind: {}", kind)))
         }
     }
 }
@@ -65,8 +67,8 @@ impl fmt::Display for Kind {
     }
 }
 
-#[derive(PartialEq, Clone, Copy)]
-pub(crate) enum Method {
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Method {
     Unknown = 0x00,
     Ping = 0x01,
     FindNode = 0x02,
@@ -86,7 +88,6 @@ impl Method {
 
 impl From<i32> for Method {
     fn from(_type: i32) -> Self {
-        let method = _type & Self::MASK;
         match _type & Self::MASK {
             0x00 => Method::Unknown,
             0x01 => Method::Ping,
@@ -95,7 +96,7 @@ impl From<i32> for Method {
             0x04 => Method::FindPeer,
             0x05 => Method::StoreValue,
             0x06 => Method::FindValue,
-            _ => panic!("invalid msg method: {}", method)
+            _ => Method::Unknown
         }
     }
 }
@@ -151,8 +152,9 @@ pub(crate) trait Msg: Display {
         self.data().type_
     }
 
+    // Only messages of a valid kind are ever created or decoded.
     fn kind(&self) -> Kind {
-        Kind::from(self.data().type_)
+        Kind::try_from(self.data().type_).unwrap_or(Kind::Error)
     }
 
     fn method(&self) -> Method {
@@ -229,6 +231,12 @@ pub(crate) trait Msg: Display {
         ])
     }
 
+    // Drop the least important part of the message to make it fit into a
+    // datagram, false if there is nothing more to drop.
+    fn trim(&mut self) -> bool {
+        false
+    }
+
     fn from_cbor(&mut self, _: &CVal) -> Option<()>;
     fn as_any(&self) -> &dyn Any;
     fn ser(&self) -> CVal;
@@ -45,11 +45,11 @@ impl Msg for Message {
             match k {
                 "y" => {},
                 "t" => {
-                    let txid = v.as_integer()?.try_into().unwrap();
+                    let txid = v.as_integer()?.try_into().ok()?;
                     self.set_txid(txid);
                 },
                 "v" => {
-                    let ver = v.as_integer()?.try_into().unwrap();
+                    let ver = v.as_integer()?.try_into().ok()?;
                     self.set_ver(ver);
                 },
                 "q" => {
@@ -66,35 +66,31 @@ impl Msg for Message {
                         match k {
                             "k" =>  pkey = Some(Id::from_cbor(v)?),     // publickey
                             "rec" => rec = Some(Id::from_cbor(v)?),     // recipient
-                            "n" => nonce = Some(Nonce::try_from(v.as_bytes()?.as_slice()).unwrap()),  // nonce
+                            "n" => nonce = Some(Nonce::try_from(v.as_bytes()?.as_slice()).ok()?),  // nonce
                             "s" =>   sig = Some(v.as_bytes()?),         // signature.
-                            "seq" => seq = v.as_integer()?.try_into().unwrap(), // sequence number
-                            "cas" => self.expected_seq = v.as_integer()?.try_into().unwrap(),
-                            "tok" => self.token = v.as_integer()?.try_into().unwrap(), // token
+                            "seq" => seq = v.as_integer()?.try_into().ok()?, // sequence number
+                            "cas" => self.expected_seq = v.as_integer()?.try_into().ok()?,
+                            "tok" => self.token = v.as_integer()?.try_into().ok()?, // token
                             "v" =>  data = Some(v.as_bytes()?.clone()), // value
                             _ => return None,
                         }
                     }
 
-                    assert!(data.is_some());
-                    if data.is_none() {
-                        return None;
-                    }
-
                     self.value = Some(Rc::new({
-                        PackBuilder::new(data.unwrap())
+                        PackBuilder::new(data?)
                             .with_pk(pkey)
                             .with_sk(None)
                             .with_rec(rec)
                             .with_nonce(nonce.take())
                             .with_sig(sig.as_ref().map(|v|v.to_vec()))
                             .with_seq(seq)
-                            .build()
+                            .try_build()?
                     }));
                 },
                 _ => return None,
             }
         }
+        self.value.as_ref()?;
         Some(())
     }
 
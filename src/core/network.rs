@@ -1,7 +1,7 @@
 use std::fmt;
 use std::net::SocketAddr;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Network {
     IPv4 = 4,
     IPv6 = 6,
@@ -1,13 +1,18 @@
-use std::ptr;
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::io::Read;
+use std::io::{self, Read};
 use std::net::SocketAddr;
 use std::collections::LinkedList;
 use std::thread::{self, JoinHandle};
 use std::{fs, fs::File, io::Write};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, mpsc};
 use log::{error, info};
+use tokio::sync::Notify;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::oneshot;
+use tokio::task;
+use tokio::sync::mpsc as tokio_mpsc;
+use tokio::time::{self, Duration, Instant};
 
 use crate::{
     create_dirs,
@@ -19,12 +24,15 @@ use crate::{
     NodeInfo,
     NodeStatus,
     PeerInfo,
+    Stats,
+    RoutingTableSnapshot,
     Value,
     Network,
     signature,
     cryptobox,
     cryptobox::Nonce,
     LookupOption,
+    LookupOptions,
     JointResult,
 };
 
@@ -32,12 +40,16 @@ use crate::core::{
     constants,
     logger,
     node_runner,
-    node_runner::NodeRunner,
+    node_runner::{NodeRunner, Context},
     bootstrap_channel::BootstrapChannel,
+    clock::{Clock, SystemClock},
+    transport::{Transport, TransportBinder, UdpTransport},
+    event::{EventBus, EventStream, NodeEvent},
     future::{
         Cmd,
         Command,
         CmdFuture,
+        PeerStream,
         FindNodeCmd,
         FindValueCmd,
         FindPeerCmd,
@@ -49,24 +61,52 @@ use crate::core::{
         GetPeerCmd,
         RemovePeerCmd,
         GetPeerIdsCmd,
+        GetStatsCmd,
+        GetRoutingTableCmd,
+        CheckReadyCmd,
     }
 };
 
+// Working thread, or task on a runtime of the application, running the node.
+enum Worker {
+    Thread(JoinHandle<()>),
+    Task(mpsc::Receiver<()>),       // disconnected once the task is over.
+}
+
+impl Worker {
+    fn join(self) {
+        match self {
+            Worker::Thread(thread) => thread.join().expect("Join thread error"),
+            Worker::Task(done) => _ = done.recv(),
+        }
+    }
+}
+
+// The handle to a node, all clones refer to the same node.
+#[derive(Clone)]
 pub struct Node {
     nodeid: Id,
     port: u16,
 
     bootstr_channel: Arc<Mutex<BootstrapChannel>>,
     command_channel: Arc<Mutex<LinkedList<Command>>>,
+    notifier: Arc<Notify>,             // wake up the working thread on new commands.
+    events: Arc<EventBus>,
 
     signature_keypair : signature::KeyPair,
     encryption_keypair: cryptobox::KeyPair,
 
-    option: Mutex<LookupOption>,
-    status: Mutex<NodeStatus>,
+    option: Arc<Mutex<LookupOption>>,
+    status: Arc<Mutex<NodeStatus>>,
+    transport: Arc<Mutex<TransportBinder>>,
+    clock: Arc<Mutex<Arc<dyn Clock>>>,
+    logger: Option<Arc<logger::Logger>>,
     storage_path: String,
+    max_datagram_size: usize,
+    subnet_limits: (usize, usize),
+    loopback_allowed: bool,
 
-    thread: Mutex<Option<JoinHandle<()>>>,    // working thread.
+    worker: Arc<Mutex<Option<Worker>>>,
     quit: Arc<Mutex<bool>>,            // notification handle to quit from working thread.
 
     addrs: JointResult<SocketAddr>,
@@ -74,7 +114,7 @@ pub struct Node {
 
 impl Node {
     pub fn new(cfg: &Box<dyn Config>) -> Result<Self> {
-        logger::setup(cfg.log_level(), cfg.log_file());
+        let logger = logger::setup(cfg.log_level(), cfg.log_file());
 
         #[cfg(feature = "devp")]
         info!("DHT node running in development mode!!!");
@@ -93,8 +133,8 @@ impl Node {
         let keypair = {
             get_keypair(&path).map_err(|e| {
                 error!("Acquire keypair from {} for DHT node error: {}", path, e);
-                return e;
-            }).ok().unwrap()
+                e
+            })?
         };
 
         let nodeid = {
@@ -102,8 +142,8 @@ impl Node {
             let id_path = path.clone() + "id";
             store_nodeid(&id_path, &id).map_err(|e| {
                 error!("Persisting node Id data error {}", e);
-                return e
-            }).ok().unwrap();
+                e
+            })?;
             info!("Current DHT node Id: {}", id);
             id
         };
@@ -133,80 +173,222 @@ impl Node {
 
             bootstr_channel: Arc::new(Mutex::new(bootstrap_channel)),
             command_channel: Arc::new(Mutex::new(LinkedList::new())),
+            notifier: Arc::new(Notify::new()),
+            events: Arc::new(EventBus::new()),
 
             signature_keypair: keypair.clone(),
             encryption_keypair: cryptobox::KeyPair::try_from(&keypair).unwrap(),
 
-            status: Mutex::new(NodeStatus::Stopped),
-            option: Mutex::new(LookupOption::Conservative),
+            status: Arc::new(Mutex::new(NodeStatus::Stopped)),
+            option: Arc::new(Mutex::new(LookupOption::Conservative)),
+            transport: Arc::new(Mutex::new(UdpTransport::binder())),
+            clock: Arc::new(Mutex::new(Arc::new(SystemClock))),
+            logger,
             storage_path: path,
+            max_datagram_size: cfg.max_datagram_size(),
+            subnet_limits: (cfg.bucket_subnet_limit(), cfg.table_subnet_limit()),
+            loopback_allowed: cfg.loopback_allowed(),
 
-            thread: Mutex::new(None),
+            worker: Arc::new(Mutex::new(None)),
             quit: Arc::new(Mutex::new(false)),
             addrs,
         })
     }
 
-    pub fn start(&self) {
-        let status_ptr: *mut NodeStatus = &mut *(self.status.lock().unwrap());
-        unsafe {
-            if ptr::read_volatile(status_ptr)
-                != NodeStatus::Stopped {
-                return;
-            }
-            ptr::write_volatile(status_ptr,
-                NodeStatus::Initializing
-            );
-        }
+    // Start the working thread and wait until it has opened the storage and
+    // bound the sockets, the node stays stopped if any of them fails. The
+    // calling thread is blocked meanwhile, see start_async for async code.
+    pub fn start(&self) -> Result<()> {
+        self.starting()?;
 
-        info!("DHT node <{}> is starting...", self.nodeid);
+        let (started_tx, started_rx) = mpsc::channel();
+        let thread = self.spawn_thread(move |result| _ = started_tx.send(result));
+        self.started(started_rx.recv().ok(), Worker::Thread(thread))
+    }
+
+    // Same as start, but waits for the working thread without blocking the
+    // runtime of the caller, which can be a current_thread one.
+    pub async fn start_async(&self) -> Result<()> {
+        self.starting()?;
 
-        let path    = self.storage_path.clone();
-        let keypair = self.signature_keypair.clone();
-        let addrs   = self.addrs.clone();
-        let bootstr = self.bootstr_channel.clone();
-        let cmds    = self.command_channel.clone();
-        let quit    = self.quit.clone();
-        let thread  = thread::spawn(move || {
-            let runner = Rc::new(RefCell::new(NodeRunner::new(
-                path,
-                keypair,
-                addrs,
-            )));
-
-            runner.borrow_mut()
-                .set_field(runner.clone())
-                .set_field(bootstr.clone())
-                .set_field(cmds)
-                .cloned();
-
-            node_runner::run_loop(
+        let (started_tx, started_rx) = oneshot::channel();
+        let thread = self.spawn_thread(move |result| _ = started_tx.send(result));
+        self.started(started_rx.await.ok(), Worker::Thread(thread))
+    }
+
+    fn spawn_thread<F>(&self, started: F) -> JoinHandle<()>
+    where F: FnOnce(Result<()>) + Send + 'static {
+        let node = self.clone();
+        thread::spawn(move || {
+            let runner = node.create_runner();
+            node_runner::run_loop(runner, node.quit.clone(), Box::new(started));
+        })
+    }
+
+    // Same as start, but the node runs on the given runtime of the application
+    // rather than a thread of its own. It occupies one thread from the
+    // blocking pool of the runtime until being stopped, and the runtime must
+    // be multi-threaded.
+    pub fn start_on(&self, handle: &Handle) -> Result<()> {
+        // The runtime would never get to drive the node while this thread
+        // waits for it to start.
+        if handle.runtime_flavor() == RuntimeFlavor::CurrentThread {
+            return Err(Error::Argument(format!("DHT node {} can't run on a current_thread runtime", self.nodeid)));
+        }
+        self.starting()?;
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let node = self.clone();
+        let cloned = handle.clone();
+        handle.spawn_blocking(move || {
+            let runner = node.create_runner();
+            cloned.block_on(node_runner::run(
                 runner,
-                quit.clone()
-            );
+                node.quit.clone(),
+                Box::new(move |result| _ = started_tx.send(result))
+            ));
+            drop(done_tx);
         });
 
-        *self.thread.lock().unwrap() = Some(thread);
-        unsafe {
-            ptr::write_volatile(status_ptr,
-                NodeStatus::Running
-            );
+        self.started(started_rx.recv().ok(), Worker::Task(done_rx))
+    }
+
+    // Run the node within the current task until it is stopped, any startup
+    // failure is returned right away. The future is bound to the thread
+    // polling it, so it has to be driven by block_on or within a LocalSet.
+    pub async fn run(&self) -> Result<()> {
+        self.starting()?;
+
+        let failure = Rc::new(RefCell::new(None));
+        let cloned = failure.clone();
+        let node = self.clone();
+        let runner = self.create_runner();
+        node_runner::run(
+            runner,
+            self.quit.clone(),
+            Box::new(move |result| match result {
+                Ok(_) => node.set_status(NodeStatus::Running),
+                Err(e) => *cloned.borrow_mut() = Some(e),
+            })
+        ).await;
+
+        // Stopped by itself instead of by stop().
+        if *self.status.lock().unwrap() != NodeStatus::Stopped {
+            self.set_status(NodeStatus::Stopped);
+        }
+
+        match failure.take() {
+            Some(e) => {
+                error!("DHT node <{}> failed to start: {}", self.nodeid, e);
+                Err(e)
+            },
+            None => Ok(())
         }
     }
 
+    fn starting(&self) -> Result<()> {
+        let mut status = self.status.lock().unwrap();
+        if *status != NodeStatus::Stopped {
+            return Err(Error::State(format!("DHT node {} is already started", self.nodeid)));
+        }
+        *status = NodeStatus::Initializing;
+        drop(status);
+        self.events.publish(NodeEvent::StatusChanged(NodeStatus::Initializing));
+
+        info!("DHT node <{}> is starting...", self.nodeid);
+
+        *self.quit.lock().unwrap() = false;
+        Ok(())
+    }
+
+    fn started(&self, result: Option<Result<()>>, worker: Worker) -> Result<()> {
+        let result = match result {
+            Some(result) => result,
+            None => Err(Error::State(format!("DHT node {} exited unexpectedly on startup", self.nodeid))),
+        };
+
+        if let Err(e) = result {
+            error!("DHT node <{}> failed to start: {}", self.nodeid, e);
+            worker.join();
+            self.set_status(NodeStatus::Stopped);
+            return Err(e);
+        }
+
+        *self.worker.lock().unwrap() = Some(worker);
+        self.set_status(NodeStatus::Running);
+        Ok(())
+    }
+
+    fn set_status(&self, status: NodeStatus) {
+        *self.status.lock().unwrap() = status;
+        self.events.publish(NodeEvent::StatusChanged(status));
+    }
+
+    // Create the runner of the node on the thread where it's going to run,
+    // with the clock and the logger of the node entered meanwhile.
+    fn create_runner(&self) -> Rc<RefCell<NodeRunner>> {
+        let context = Context::new(self.clock.lock().unwrap().clone(), self.logger.clone());
+        let _entered = context.enter();
+
+        let runner = Rc::new(RefCell::new(NodeRunner::new(
+            self.storage_path.clone(),
+            self.signature_keypair.clone(),
+            self.addrs.clone(),
+        )));
+
+        runner.borrow_mut()
+            .set_field(runner.clone())
+            .set_field(self.bootstr_channel.clone())
+            .set_field(self.command_channel.clone())
+            .set_field(self.notifier.clone())
+            .set_field(self.events.clone())
+            .set_field(self.transport.lock().unwrap().clone())
+            .set_field(context.clone())
+            .set_max_datagram_size(self.max_datagram_size);
+        runner.borrow_mut()
+            .set_subnet_limits(self.subnet_limits.0, self.subnet_limits.1)
+            .set_loopback_allowed(self.loopback_allowed);
+        runner
+    }
+
+    // Stop the node and wait for it to finish, except when it's run by the
+    // caller, in which case the run future completes on its own shortly.
+    // The calling thread is blocked until then, see stop_async for async
+    // code.
     pub fn stop(&self) {
-        let status_ptr: *mut NodeStatus = &mut (*self.status.lock().unwrap());
-        unsafe {
-            if ptr::read_volatile(status_ptr)
-                == NodeStatus::Stopped {
-                return;
-            }
-            ptr::write_volatile(
-                status_ptr,
-                NodeStatus::Stopped
-            );
+        if !self.stopping() {
+            return;
         }
 
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            worker.join();
+        }
+        self.stopped();
+    }
+
+    // Same as stop, but waits for the node to finish without blocking the
+    // runtime of the caller.
+    pub async fn stop_async(&self) {
+        if !self.stopping() {
+            return;
+        }
+
+        let worker = self.worker.lock().unwrap().take();
+        if let Some(worker) = worker {
+            _ = task::spawn_blocking(move || worker.join()).await;
+        }
+        self.stopped();
+    }
+
+    fn stopping(&self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if *status == NodeStatus::Stopped {
+            return false;
+        }
+        *status = NodeStatus::Stopped;
+        drop(status);
+
         info!("DHT node <{}> stopping...", self.nodeid);
 
         // Check for abnormal termination in the spawned thread.
@@ -216,31 +398,51 @@ impl Node {
             *quit = true;
         }
         drop(quit);
+        self.notifier.notify_one();
+        true
+    }
 
-        self.thread.lock().unwrap().take().unwrap().join().expect("Join thread error");
-        *self.thread.lock().unwrap() = None;
-
+    fn stopped(&self) {
+        self.events.publish(NodeEvent::StatusChanged(NodeStatus::Stopped));
         info!("DHT node {} stopped", self.nodeid);
-        logger::teardown();
     }
 
     pub fn is_running(&self) -> bool {
-        let status_ptr: *const NodeStatus = &(*self.status.lock().unwrap());
-        unsafe {
-            ptr::read_volatile(status_ptr) == NodeStatus::Running
-        }
+        *self.status.lock().unwrap() == NodeStatus::Running
     }
 
     pub fn bootstrap(&self, node: &NodeInfo) {
         self.bootstr_channel.lock()
             .expect("Locking failure")
             .push(node);
+        self.notifier.notify_one();
     }
 
     pub fn bootstrap_nodes(&self, nodes: &[NodeInfo]) {
         self.bootstr_channel.lock()
             .expect("Locking failure")
             .push_nodes(nodes);
+        self.notifier.notify_one();
+    }
+
+    pub fn subscribe(&self) -> EventStream {
+        self.events.subscribe()
+    }
+
+    fn push_command(&self, cmd: Command) {
+        self.command_channel.lock().unwrap().push_back(cmd);
+        self.notifier.notify_one();
+    }
+
+    // Cancel the tasks of a lookup command once the caller gives up
+    // waiting for its result.
+    fn cancel_fn(&self) -> impl FnOnce(Command) + Send + 'static {
+        let cmds = self.command_channel.clone();
+        let notifier = self.notifier.clone();
+        move |cmd| {
+            cmds.lock().unwrap().push_back(Command::Cancel(Box::new(cmd)));
+            notifier.notify_one();
+        }
     }
 
     pub const fn id(&self) -> &Id {
@@ -255,6 +457,21 @@ impl Node {
         self.id() == id
     }
 
+    // Replace the UDP sockets with another transport, which takes effect
+    // from the next time the node is started.
+    pub fn set_transport<F>(&self, binder: F)
+    where F: Fn(&SocketAddr) -> io::Result<Box<dyn Transport>> + Send + Sync + 'static
+    {
+        *self.transport.lock().unwrap() = Arc::new(binder);
+    }
+
+    // Replace the system clock driving the maintenance work of the node,
+    // which takes effect from the next time the node is started. Moving a
+    // ManualClock wakes the running node up for the jobs fallen due.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.lock().unwrap() = clock;
+    }
+
     pub fn set_lookup_option(&self, option: LookupOption) {
         *self.option.lock().unwrap() = option;
     }
@@ -263,9 +480,21 @@ impl Node {
         self.option.lock().unwrap().clone()
     }
 
+    // The lookup options used by the operations without explicit options.
+    fn lookup_options(&self, option: Option<&LookupOption>) -> LookupOptions {
+        LookupOptions::from(option.copied().unwrap_or(self.lookup_option()))
+    }
+
     pub async fn find_node(&self,
         target: &Id,
         option: Option<&LookupOption>
+    ) -> Result<JointResult<NodeInfo>> {
+        self.find_node_with(target, &self.lookup_options(option)).await
+    }
+
+    pub async fn find_node_with(&self,
+        target: &Id,
+        options: &LookupOptions
     ) -> Result<JointResult<NodeInfo>> {
         if target == &MIN_ID {
             return Err(Error::Argument(format!("Invalid target node id {}", target)));
@@ -274,13 +503,11 @@ impl Node {
             return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
         }
 
-        let default_opt = self.option.lock().unwrap();
-        let opt = option.unwrap_or(&default_opt);
-        let arc = Arc::new(Mutex::new(FindNodeCmd::new(target, opt)));
+        let arc = Arc::new(Mutex::new(FindNodeCmd::new(target, options)));
         let cmd = Command::FindNode(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
-        match CmdFuture::new(cmd).await {
+        self.push_command(cmd.clone());
+        match CmdFuture::new(cmd).with_cancel_fn(self.cancel_fn()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
         }
@@ -289,6 +516,13 @@ impl Node {
     pub async fn find_value(&self,
         value_id: &Id,
         option: Option<&LookupOption>
+    ) -> Result<Option<Value>> {
+        self.find_value_with(value_id, &self.lookup_options(option)).await
+    }
+
+    pub async fn find_value_with(&self,
+        value_id: &Id,
+        options: &LookupOptions
     ) -> Result<Option<Value>> {
         if value_id == &MIN_ID {
             return Err(Error::Argument(format!("Invalid value id {}", value_id)));
@@ -297,13 +531,11 @@ impl Node {
             return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
         }
 
-        let default_opt = self.option.lock().unwrap();
-        let opt = option.unwrap_or(&default_opt);
-        let arc = Arc::new(Mutex::new(FindValueCmd::new(value_id, opt)));
+        let arc = Arc::new(Mutex::new(FindValueCmd::new(value_id, options)));
         let cmd = Command::FindValue(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
-        match CmdFuture::new(cmd).await {
+        self.push_command(cmd.clone());
+        match CmdFuture::new(cmd).with_cancel_fn(self.cancel_fn()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
         }
@@ -313,6 +545,14 @@ impl Node {
         peer_id: &Id,
         expected_seq: Option<usize>,
         option: Option<&LookupOption>
+    ) -> Result<Vec<PeerInfo>> {
+        self.find_peer_with(peer_id, expected_seq, &self.lookup_options(option)).await
+    }
+
+    pub async fn find_peer_with(&self,
+        peer_id: &Id,
+        expected_seq: Option<usize>,
+        options: &LookupOptions
     ) -> Result<Vec<PeerInfo>> {
         if peer_id == &MIN_ID {
             return Err(Error::Argument(format!("Invalid peer id {}", peer_id)));
@@ -321,26 +561,58 @@ impl Node {
             return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
         }
 
-        let default_opt = *self.option.lock().unwrap();
-        let opt = option.unwrap_or(&default_opt);
         let seq = expected_seq.unwrap_or(0);
         let arc = Arc::new(Mutex::new(FindPeerCmd::new(
             peer_id,
             seq,
-            opt
+            options
         )));
         let cmd = Command::FindPeer(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
-        match CmdFuture::new(cmd).await {
+        self.push_command(cmd.clone());
+        match CmdFuture::new(cmd).with_cancel_fn(self.cancel_fn()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
         }
     }
 
+    pub fn find_peer_stream(&self,
+        peer_id: &Id,
+        options: &LookupOptions
+    ) -> Result<PeerStream> {
+        if peer_id == &MIN_ID {
+            return Err(Error::Argument(format!("Invalid peer id {}", peer_id)));
+        }
+        if !self.is_running() {
+            return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
+        }
+
+        let (tx, rx) = tokio_mpsc::unbounded_channel();
+        let arc = Arc::new(Mutex::new(FindPeerCmd::new(
+            peer_id,
+            0,
+            options
+        ).with_stream(tx)));
+        let cmd = Command::FindPeer(arc);
+
+        self.push_command(cmd.clone());
+        Ok(PeerStream::new(
+            rx,
+            CmdFuture::new(cmd).with_cancel_fn(self.cancel_fn())
+        ))
+    }
+
     pub async fn store_value(&self,
         value: &Value,
         persistent: Option<bool>
+    ) -> Result<()> {
+        self.store_value_with(value, persistent, &self.lookup_options(None)).await
+    }
+
+    pub async fn store_value_with(&self,
+        value: &Value,
+        persistent: Option<bool>,
+        options: &LookupOptions
     ) -> Result<()> {
         if !value.is_valid() {
             return Err(Error::Argument(format!("Invalid value")));
@@ -350,10 +622,10 @@ impl Node {
         }
 
         let persistent = persistent.unwrap_or(false);
-        let arc = Arc::new(Mutex::new(StoreValueCmd::new(value, persistent)));
+        let arc = Arc::new(Mutex::new(StoreValueCmd::new(value, persistent, options)));
         let cmd = Command::StoreValue(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -363,6 +635,14 @@ impl Node {
     pub async fn announce_peer(&self,
         peer: &PeerInfo,
         persistent: Option<bool>
+    ) -> Result<()> {
+        self.announce_peer_with(peer, persistent, &self.lookup_options(None)).await
+    }
+
+    pub async fn announce_peer_with(&self,
+        peer: &PeerInfo,
+        persistent: Option<bool>,
+        options: &LookupOptions
     ) -> Result<()> {
         if !peer.is_valid() {
             return Err(Error::Argument(format!("Invalid peer")));
@@ -372,10 +652,10 @@ impl Node {
         }
 
         let persistent = persistent.unwrap_or(false);
-        let arc = Arc::new(Mutex::new(AnnouncePeerCmd::new(peer, persistent)));
+        let arc = Arc::new(Mutex::new(AnnouncePeerCmd::new(peer, persistent, options)));
         let cmd = Command::AnnouncePeer(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -393,7 +673,7 @@ impl Node {
         let arc = Arc::new(Mutex::new(GetValueCmd::new(value_id)));
         let cmd = Command::GetValue(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -411,7 +691,7 @@ impl Node {
         let arc = Arc::new(Mutex::new(RemoveValueCmd::new(value_id)));
         let cmd = Command::RemoveValue(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -426,7 +706,7 @@ impl Node {
         let arc = Arc::new(Mutex::new(GetValueIdsCmd::new()));
         let cmd = Command::GetValueIds(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -444,7 +724,7 @@ impl Node {
         let arc = Arc::new(Mutex::new(GetPeerCmd::new(peer_id)));
         let cmd = Command::GetPeer(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -462,7 +742,7 @@ impl Node {
         let arc = Arc::new(Mutex::new(RemovePeerCmd::new(peer_id)));
         let cmd = Command::RemovePeer(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
@@ -477,13 +757,89 @@ impl Node {
         let arc = Arc::new(Mutex::new(GetPeerIdsCmd::new()));
         let cmd = Command::GetPeerIds(arc.clone());
 
-        self.command_channel.lock().unwrap().push_back(cmd.clone());
+        self.push_command(cmd.clone());
         match CmdFuture::new(cmd.clone()).await {
             Ok(_) => arc.lock().unwrap().result(),
             Err(e) => Err(e)
         }
     }
 
+    pub async fn stats(&self) -> Result<Stats> {
+        if !self.is_running() {
+            return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
+        }
+
+        let arc = Arc::new(Mutex::new(GetStatsCmd::new()));
+        let cmd = Command::GetStats(arc.clone());
+
+        self.push_command(cmd.clone());
+        match CmdFuture::new(cmd.clone()).await {
+            Ok(_) => arc.lock().unwrap().result(),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub async fn routing_table(&self, network: Network) -> Result<RoutingTableSnapshot> {
+        if !self.is_running() {
+            return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
+        }
+
+        let arc = Arc::new(Mutex::new(GetRoutingTableCmd::new(network)));
+        let cmd = Command::GetRoutingTable(arc.clone());
+
+        self.push_command(cmd.clone());
+        match CmdFuture::new(cmd.clone()).await {
+            Ok(_) => arc.lock().unwrap().result(),
+            Err(e) => Err(e)
+        }
+    }
+
+    // Wait until the node has joined the network, which means at least one DHT
+    // has finished bootstrapping and holds the minimum number of entries. Not
+    // being ready in time, the error tells how bootstrapping has gone on each
+    // network.
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<()> {
+        if !self.is_running() {
+            return Err(Error::State(format!("DHT node {} is not running", self.nodeid)))
+        }
+
+        // Subscribe before checking so that no change would be missed.
+        let mut events = self.subscribe();
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let arc = Arc::new(Mutex::new(CheckReadyCmd::new()));
+            let cmd = Command::CheckReady(arc.clone());
+
+            self.push_command(cmd.clone());
+            CmdFuture::new(cmd).await?;
+            let readiness = arc.lock().unwrap().result()?;
+            if readiness.ready {
+                return Ok(());
+            }
+
+            loop {
+                match time::timeout_at(deadline, events.next()).await {
+                    Ok(Some(NodeEvent::BootstrapCompleted(_))) |
+                    Ok(Some(NodeEvent::BootstrapFailed(_))) |
+                    Ok(Some(NodeEvent::EntryAdded(_))) => break,
+                    Ok(Some(NodeEvent::StatusChanged(NodeStatus::Stopped))) | Ok(None) => {
+                        return Err(Error::State(format!("DHT node {} is stopped", self.nodeid)))
+                    },
+                    Ok(Some(_)) => continue,
+                    Err(_) => {
+                        return Err(Error::State(format!(
+                            "DHT node {} is not ready within {} ms, bootstrap {}",
+                            self.nodeid,
+                            timeout.as_millis(),
+                            readiness.describe_bootstrap()
+                        )))
+                    }
+                }
+            }
+        }
+    }
+
     pub fn encrypt_into(&self, recipient: &Id, plain: &[u8]) -> Result<Vec<u8>> {
         let pk = recipient.to_encryption_key();
         let receiver = Id::try_from(pk.as_bytes()).unwrap();
@@ -572,8 +928,8 @@ impl Node {
 
 fn get_keypair(path: &str) -> Result<signature::KeyPair> {
     create_dirs(path).map_err(|e| {
-        return Error::State(format!("Checking persistence error: {}", e));
-    }).ok().unwrap();
+        Error::State(format!("Checking persistence error: {}", e))
+    })?;
 
     let keypath = path.to_string() + "key";
     let keypair;
@@ -584,18 +940,12 @@ fn get_keypair(path: &str) -> Result<signature::KeyPair> {
             if metadata.is_dir() {
                 return Err(Error::State(format!("Bad file path {} for key storage.", keypath)));
             };
-            keypair = load_key(&keypath)
-                .map_err(|e| return e)
-                .ok()
-                .unwrap();
+            keypair = load_key(&keypath)?;
         },
         Err(_) => {
             // otherwise, generate a fresh keypair
             keypair = signature::KeyPair::random();
-            store_key(&keypath, &keypair)
-                .map_err(|e|return e)
-                .ok()
-                .unwrap();
+            store_key(&keypath, &keypair)?;
         }
     };
 
@@ -36,14 +36,14 @@ impl NodeInfo {
         let map = input.as_array()?;
         let id  = Id::from_cbor(map.get(0)?)?;
         let ip  = map.get(1)?.as_bytes()?;
-        let port: u16 = map.get(2)?.as_integer()?.try_into().unwrap();
+        let port: u16 = map.get(2)?.as_integer()?.try_into().ok()?;
         let addr = match ip.len() {
             4 => {
-                let ip: [u8; 4] = ip.as_slice().try_into().unwrap();
+                let ip: [u8; 4] = ip.as_slice().try_into().ok()?;
                 IpAddr::V4(Ipv4Addr::from(ip))
             },
             16 => {
-                let ip: [u8; 16] = ip.as_slice().try_into().unwrap();
+                let ip: [u8; 16] = ip.as_slice().try_into().ok()?;
                 IpAddr::V6(Ipv6Addr::from(ip))
             },
             _ => return None,
@@ -8,7 +8,11 @@ use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::ops::Deref;
+use std::pin::Pin;
+use std::future::Future;
+use std::task::{Context as TaskContext, Poll};
 use log::{debug, info, warn, error};
+use tokio::{runtime, sync::Notify};
 
 use crate::{
     Id,
@@ -18,20 +22,26 @@ use crate::{
     PeerInfo,
     JointResult,
     LookupOption,
+    LookupOptions,
     signature,
     cryptobox::KeyPair,
+    RoutingTableSnapshot,
     Error,
 };
 
 use crate::core::{
+    clock::{self, Clock, SystemClock},
+    logger::{self, Logger},
     constants,
-    dht::DHT,
+    dht::{DHT, BootstrapState},
     sqlite_storage::SqliteStorage,
     token_manager::TokenManager,
     server::{self, Server},
-    crypto_cache::CryptoCache,
+    crypto_cache::{CryptoCache, Received},
     bootstrap_channel::BootstrapChannel,
-    data_storage::DataStorage
+    data_storage::DataStorage,
+    event::EventBus,
+    transport::{TransportBinder, UdpTransport},
 };
 
 use crate::core::future::{
@@ -48,6 +58,10 @@ use crate::core::future::{
     GetPeerCmd,
     RemovePeerCmd,
     GetPeerIdsCmd,
+    GetStatsCmd,
+    GetRoutingTableCmd,
+    CheckReadyCmd,
+    Readiness,
 };
 
 pub(crate) struct NodeRunner {
@@ -58,6 +72,9 @@ pub(crate) struct NodeRunner {
 
     command_channel: Option<Arc<Mutex<LinkedList<Command>>>>,
     bootstr_channel: Option<Arc<Mutex<BootstrapChannel>>>,
+    notifier: Arc<Notify>,
+    events: Arc<EventBus>,
+    transport: TransportBinder,
 
     dht4: Option<Rc<RefCell<DHT>>>,
     dht6: Option<Rc<RefCell<DHT>>>,
@@ -66,10 +83,68 @@ pub(crate) struct NodeRunner {
     storage:  Rc<RefCell<dyn DataStorage>>,
     tokenman: Rc<RefCell<TokenManager>>,
     server:   Rc<RefCell<Server>>,
+    context:  Context,
 
     cloned: Option<Rc<RefCell<NodeRunner>>>,
 }
 
+// The clock and the logger of a node, which are entered on the thread for
+// as long as the node is polled and restored to the previous ones after,
+// as the nodes run by the application might share the same thread.
+#[derive(Clone)]
+pub(crate) struct Context {
+    clock: Arc<dyn Clock>,
+    logger: Option<Arc<Logger>>,
+}
+
+impl Context {
+    pub(crate) fn new(clock: Arc<dyn Clock>, logger: Option<Arc<Logger>>) -> Self {
+        Self { clock, logger }
+    }
+
+    // Notifier signaled whenever the clock is moved by hand, as the runtime
+    // timer waiting for the next job knows nothing about it.
+    pub(crate) fn watch_clock(&self) -> Arc<Notify> {
+        let notifier = Arc::new(Notify::new());
+        self.clock.watch(Arc::downgrade(&notifier));
+        notifier
+    }
+
+    pub(crate) fn enter(&self) -> Entered {
+        Entered {
+            clock: clock::enter(Some(self.clock.clone())),
+            logger: logger::enter(self.logger.clone()),
+        }
+    }
+}
+
+pub(crate) struct Entered {
+    clock: Option<Arc<dyn Clock>>,
+    logger: Option<Arc<Logger>>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        clock::enter(self.clock.take());
+        logger::enter(self.logger.take());
+    }
+}
+
+struct Scoped<F> {
+    context: Context,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let _entered = this.context.enter();
+        this.inner.as_mut().poll(cx)
+    }
+}
+
 impl NodeRunner {
     pub(crate) fn new(
         data_dir: String,
@@ -101,6 +176,9 @@ impl NodeRunner {
 
             command_channel: None,
             bootstr_channel: None,
+            notifier: Arc::new(Notify::new()),
+            events: Arc::new(EventBus::new()),
+            transport: UdpTransport::binder(),
 
             dht4: dht4.map(|v| Rc::new(RefCell::new(v))),
             dht6: dht6.map(|v| Rc::new(RefCell::new(v))),
@@ -109,6 +187,7 @@ impl NodeRunner {
             storage:  Rc::new(RefCell::new(SqliteStorage::new())),
             tokenman: Rc::new(RefCell::new(TokenManager::new())),
             server:   Rc::new(RefCell::new(Server::new(nodeid))),
+            context:  Context::new(Arc::new(SystemClock), None),
 
             cloned:   None,
         }
@@ -127,10 +206,39 @@ impl NodeRunner {
         } else if typid == TypeId::of::<Arc<Mutex<BootstrapChannel>>>() {
             let rc = field.downcast::<Arc<Mutex<BootstrapChannel>>>().unwrap();
             self.bootstr_channel = Some(rc.deref().clone());
+        } else if typid == TypeId::of::<Arc<Notify>>() {
+            let rc = field.downcast::<Arc<Notify>>().unwrap();
+            self.notifier = rc.deref().clone();
+        } else if typid == TypeId::of::<Arc<EventBus>>() {
+            let rc = field.downcast::<Arc<EventBus>>().unwrap();
+            self.events = rc.deref().clone();
+        } else if typid == TypeId::of::<TransportBinder>() {
+            let rc = field.downcast::<TransportBinder>().unwrap();
+            self.transport = rc.deref().clone();
+        } else if typid == TypeId::of::<Context>() {
+            let rc = field.downcast::<Context>().unwrap();
+            self.context = rc.deref().clone();
         }
         self
     }
 
+    pub(crate) fn set_max_datagram_size(&mut self, size: usize) {
+        self.server.borrow_mut().set_max_datagram_size(size);
+    }
+
+    pub(crate) fn set_subnet_limits(&mut self, bucket: usize, table: usize) -> &mut Self {
+        for dht in [self.dht4.as_ref(), self.dht6.as_ref()].into_iter().flatten() {
+            dht.borrow().rt().borrow_mut().set_subnet_limits(bucket, table);
+        }
+        self
+    }
+
+    pub(crate) fn set_loopback_allowed(&mut self, allowed: bool) {
+        for dht in [self.dht4.as_ref(), self.dht6.as_ref()].into_iter().flatten() {
+            dht.borrow_mut().set_loopback_allowed(allowed);
+        }
+    }
+
     pub(crate) fn id(&self) -> Rc<Id> {
         self.nodeid.clone()
     }
@@ -139,10 +247,20 @@ impl NodeRunner {
         self.cloned.as_ref().unwrap().clone()
     }
 
+    pub(crate) fn notifier(&self) -> Arc<Notify> {
+        self.notifier.clone()
+    }
+
+    pub(crate) fn clock_notifier(&self) -> Arc<Notify> {
+        self.context.watch_clock()
+    }
+
+    // Called with the runner borrowed already, the announcements have to go
+    // through self rather than a clone of the runner.
     fn persistent_announce(&mut self) {
         info!("Re-announce the persistent values and peers ...");
 
-        let before = SystemTime::now().checked_sub(Duration::from_millis(
+        let before = clock::now().checked_sub(Duration::from_millis(
             constants::MAX_VALUE_AGE as u64 + constants::RE_ANNOUNCE_INTERVAL * 2
         )).unwrap();
 
@@ -151,7 +269,6 @@ impl NodeRunner {
             .ok();
 
         let cloned_storage = self.storage.clone();
-        let cloned_runner  = self.cloned();
         result.map(|values| {
             values.iter().for_each(|item| {
                 let val_id = item.id();
@@ -160,14 +277,14 @@ impl NodeRunner {
                     .map_err(|e| warn!("{}", e))
                     .ok();
 
-                cloned_runner.borrow().store_value(
-                    Arc::new(Mutex::new(StoreValueCmd::new(item, false))),
+                self.store_value(
+                    Arc::new(Mutex::new(StoreValueCmd::new(item, false, &LookupOptions::default()))),
                     false
                 );
             })
         });
 
-        let before = SystemTime::now().checked_sub(Duration::from_millis(
+        let before = clock::now().checked_sub(Duration::from_millis(
             constants::MAX_PEER_AGE as u64 + constants::RE_ANNOUNCE_INTERVAL * 2
         )).unwrap();
 
@@ -176,7 +293,6 @@ impl NodeRunner {
             .ok();
 
         let cloned_storage = self.storage.clone();
-        let cloned_runner  = self.cloned();
         result.map(|peers| {
             peers.iter().for_each(|item| {
                 debug!("Reannouce the peers {}",  item.id());
@@ -184,8 +300,8 @@ impl NodeRunner {
                     .map_err(|e| warn!("{}", e))
                     .ok();
 
-                cloned_runner.borrow().announce_peer(
-                    Arc::new(Mutex::new(AnnouncePeerCmd::new(item, false))),
+                self.announce_peer(
+                    Arc::new(Mutex::new(AnnouncePeerCmd::new(item, false, &LookupOptions::default()))),
                     false
                 );
             })
@@ -195,27 +311,34 @@ impl NodeRunner {
     pub(crate) fn start(&mut self) -> Result<(), Error> {
         // Prepare SQlite storage
         let path = self.data_dir.clone() + "node.db";
-        self.storage.borrow_mut().open(path.as_str())?;
+        self.storage.borrow_mut().open(path.as_str()).map_err(|e| {
+            Error::Db(format!("Opening storage {} error: {}", path, e))
+        })?;
+        self.server.borrow_mut().set_events(self.events.clone());
 
         // Start IPv4 DHT if it exists
-        self.dht4.as_ref().map(|dht| dht.borrow_mut()
-            .set_field(self.server.clone())
-            .set_field(self.storage.clone())
-            .set_field(self.tokenman.clone())
-            .set_field(dht.clone())
-            .start()
-            .map(|addr| info!("Started DHT node on ipv4 address: {}", addr))
-        );
+        if let Some(dht) = self.dht4.as_ref() {
+            let addr = dht.borrow_mut()
+                .set_field(self.server.clone())
+                .set_field(self.storage.clone())
+                .set_field(self.tokenman.clone())
+                .set_field(self.events.clone())
+                .set_field(dht.clone())
+                .start()?;
+            info!("Started DHT node on ipv4 address: {}", addr);
+        }
 
         // Start IPv6 DHT if it exists
-        self.dht6.as_ref().map(|dht| dht.borrow_mut()
-            .set_field(self.server.clone())
-            .set_field(self.storage.clone())
-            .set_field(self.tokenman.clone())
-            .set_field(dht.clone())
-            .start()
-            .map(|addr| info!("Started DHT node on ipv6 address: {}", addr))
-        );
+        if let Some(dht) = self.dht6.as_ref() {
+            let addr = dht.borrow_mut()
+                .set_field(self.server.clone())
+                .set_field(self.storage.clone())
+                .set_field(self.tokenman.clone())
+                .set_field(self.events.clone())
+                .set_field(dht.clone())
+                .start()?;
+            info!("Started DHT node on ipv6 address: {}", addr);
+        }
 
         let scheduler = self.server.borrow().scheduler();
 
@@ -225,6 +348,12 @@ impl NodeRunner {
             ctxts.borrow_mut().expire();
         }, 2000, constants::EXPIRED_CHECK_INTERVAL);
 
+        // Handle replay window expiration.
+        let replay_window = self.server.borrow().replay_window();
+        scheduler.borrow_mut().add(move || {
+            replay_window.borrow_mut().expire();
+        }, 2000, constants::EXPIRED_CHECK_INTERVAL);
+
         // Handle SQlite storage expiration.
         let storage = self.storage.clone();
         scheduler.borrow_mut().add(move || {
@@ -237,47 +366,70 @@ impl NodeRunner {
         }, 1000, constants::RE_ANNOUNCE_INTERVAL);
 
         // Check incomming bootstrap nodes.
-        let chan = self.bootstr_channel.as_ref().unwrap().clone();
-        let dht4 = self.dht4.as_ref().map(|v| v.clone());
-        let dht6 = self.dht6.as_ref().map(|v| v.clone());
-
+        let cloned = self.cloned();
         scheduler.borrow_mut().add(move || {
-            let mut channel = chan.lock().unwrap();
-            channel.pop_all(|item| {
-                let ni = Rc::new(item);
-                dht4.as_ref().map(|dht| {
-                    dht.borrow_mut().add_bootstrap_node(ni.clone());
-                });
-                dht6.as_ref().map(|dht| {
-                    dht.borrow_mut().add_bootstrap_node(ni.clone());
-                });
-            });
+            cloned.borrow().handle_bootstrap_nodes();
         }, 100, constants::DHT_UPDATE_INTERVAL);
 
-        // Check incomming commands from outer Node.
-        let chan = self.command_channel.as_ref().unwrap().clone();
-        let node = self.cloned();
-        scheduler.borrow_mut().add(move || {
-            let mut channel = chan.lock().unwrap();
-            while let Some(cmd) = channel.pop_front() {
-                let borrowed = node.borrow();
-                match cmd {
-                    Command::FindNode(c)    => borrowed.find_node(c),
-                    Command::FindValue(c)   => borrowed.find_value(c),
-                    Command::FindPeer(c)    => borrowed.find_peer(c),
-                    Command::StoreValue(c)  => borrowed.store_value(c, true),
-                    Command::AnnouncePeer(c)=> borrowed.announce_peer(c, true),
-                    Command::GetValue(c)    => borrowed.get_value(c),
-                    Command::RemoveValue(c) => borrowed.remove_value(c),
-                    Command::GetValueIds(c) => borrowed.get_value_ids(c),
-                    Command::GetPeer(c)     => borrowed.get_peer(c),
-                    Command::RemovePeer(c)  => borrowed.remove_peer(c),
-                    Command::GetPeerIds(c)  => borrowed.get_peer_ids(c),
-                }
+        Ok(())
+    }
+
+    // Drain both channels from the outer Node, which is woken up by the
+    // notifier right after anything has been pushed into them.
+    pub(crate) fn handle_channels(&self) {
+        self.handle_bootstrap_nodes();
+        self.handle_commands();
+    }
+
+    fn handle_bootstrap_nodes(&self) {
+        let chan = match self.bootstr_channel.as_ref() {
+            Some(v) => v.clone(),
+            None => return,
+        };
+
+        // Each DHT only takes the bootstrap nodes it can reach, so that the
+        // bootstrap outcome of one network is not left to the other.
+        chan.lock().unwrap().pop_all(|item| {
+            let ni = Rc::new(item);
+            let dht = match ni.socket_addr().is_ipv4() {
+                true => self.dht4.as_ref(),
+                false => self.dht6.as_ref(),
+            };
+            if let Some(dht) = dht {
+                dht.borrow_mut().add_bootstrap_node(ni);
             }
-        }, 100, 100);
+        });
+    }
 
-        Ok(())
+    fn handle_commands(&self) {
+        let chan = match self.command_channel.as_ref() {
+            Some(v) => v.clone(),
+            None => return,
+        };
+
+        loop {
+            let cmd = match chan.lock().unwrap().pop_front() {
+                Some(v) => v,
+                None => break,
+            };
+            match cmd {
+                Command::FindNode(c)    => self.find_node(c),
+                Command::FindValue(c)   => self.find_value(c),
+                Command::FindPeer(c)    => self.find_peer(c),
+                Command::StoreValue(c)  => self.store_value(c, true),
+                Command::AnnouncePeer(c)=> self.announce_peer(c, true),
+                Command::GetValue(c)    => self.get_value(c),
+                Command::RemoveValue(c) => self.remove_value(c),
+                Command::GetValueIds(c) => self.get_value_ids(c),
+                Command::GetPeer(c)     => self.get_peer(c),
+                Command::RemovePeer(c)  => self.remove_peer(c),
+                Command::GetPeerIds(c)  => self.get_peer_ids(c),
+                Command::GetStats(c)    => self.get_stats(c),
+                Command::GetRoutingTable(c) => self.get_routing_table(c),
+                Command::CheckReady(c) => self.check_ready(c),
+                Command::Cancel(c)      => self.cancel(*c),
+            }
+        }
     }
 
     pub(crate) fn stop(&mut self) {
@@ -315,7 +467,8 @@ impl NodeRunner {
             ))
         );
 
-        let option = locked.option().clone();
+        let option = locked.option();
+        let options = locked.options().clone();
         if option == LookupOption::Arbitrary && found.has_value() {
             locked.complete(Ok(found));
             return;
@@ -347,19 +500,21 @@ impl NodeRunner {
             }
         ));
 
-        self.dht4.as_ref().map(|dht| dht.borrow().find_node(
-            locked.target(), option, complete_fn.clone()
-        ));
-        self.dht6.as_ref().map(|dht| dht.borrow().find_node(
-            locked.target(), option, complete_fn.clone()
-        ));
+        if let Some(dht) = self.dht4.as_ref() {
+            let taskid = dht.borrow().find_node(locked.target(), &options, complete_fn.clone());
+            locked.add_task(Network::IPv4, taskid);
+        }
+        if let Some(dht) = self.dht6.as_ref() {
+            let taskid = dht.borrow().find_node(locked.target(), &options, complete_fn.clone());
+            locked.add_task(Network::IPv6, taskid);
+        }
     }
 
     fn find_value(&self, cmd: Arc<Mutex<FindValueCmd>>) {
         let mut locked = cmd.lock().unwrap();
-        let value_id = locked.value_id();
+        let value_id = locked.value_id().clone();
 
-        let result = self.storage.borrow_mut().value(value_id);
+        let result = self.storage.borrow_mut().value(&value_id);
         if let Err(e) = result {
             error!("Query value from local storage error: {}", e);
             locked.complete(Err(e));
@@ -367,7 +522,8 @@ impl NodeRunner {
         }
 
         let found = result.unwrap();
-        let option = locked.option().clone();
+        let option = locked.option();
+        let options = locked.options().clone();
         if let Some(value) = found.as_ref() {
             if option == LookupOption::Arbitrary || !value.is_mutable() {
                 locked.complete(Ok(Some(value.clone())));
@@ -408,17 +564,19 @@ impl NodeRunner {
             };
         }));
 
-        self.dht4.as_ref().map(|dht| dht.borrow().find_value(
-            Rc::new(value_id.clone()), option, complete_fn.clone()
-        ));
-        self.dht6.as_ref().map(|dht| dht.borrow().find_value(
-            Rc::new(value_id.clone()), option, complete_fn.clone()
-        ));
+        if let Some(dht) = self.dht4.as_ref() {
+            let taskid = dht.borrow().find_value(Rc::new(value_id.clone()), &options, complete_fn.clone());
+            locked.add_task(Network::IPv4, taskid);
+        }
+        if let Some(dht) = self.dht6.as_ref() {
+            let taskid = dht.borrow().find_value(Rc::new(value_id.clone()), &options, complete_fn.clone());
+            locked.add_task(Network::IPv6, taskid);
+        }
     }
 
     fn find_peer(&self, cmd: Arc<Mutex<FindPeerCmd>>) {
         let mut locked = cmd.lock().unwrap();
-        let peer_id = locked.peer_id();
+        let peer_id = locked.peer_id().clone();
 
         let mut result = self.storage.borrow_mut().peers(
             locked.peer_id(),
@@ -441,11 +599,13 @@ impl NodeRunner {
                 s.finish()
             };
             if dedup.insert(hash) {
+                locked.stream_peer(&item);
                 found.push(item);
             }
         }
 
-        let option = locked.option().clone();
+        let option = locked.option();
+        let options = locked.options().clone();
         let expect = locked.expected_num();
 
         if option == LookupOption::Arbitrary && expect > 0 && found.len() >= expect {
@@ -453,6 +613,20 @@ impl NodeRunner {
             return;
         }
 
+        // Peers are streamed as soon as they arrive from either DHT.
+        let cloned_cmd = cmd.clone();
+        let mut streamed = dedup.clone();
+        let found_fn = Rc::new(RefCell::new(move |peer: &PeerInfo| {
+            let hash = {
+                let mut s = DefaultHasher::new();
+                peer.hash(&mut s);
+                s.finish()
+            };
+            if streamed.insert(hash) {
+                cloned_cmd.lock().unwrap().stream_peer(peer);
+            }
+        }));
+
         let cloned_cmd = cmd.clone();
         let cloned_storage = self.storage.clone();
         let ndhts = self.dht_num;
@@ -484,12 +658,29 @@ impl NodeRunner {
             }
         }));
 
-        self.dht4.as_ref().map(|dht| dht.borrow().find_peer(
-            Rc::new(peer_id.clone()), expect, option, complete_fn.clone()
-        ));
-        self.dht6.as_ref().map(|dht| dht.borrow().find_peer(
-            Rc::new(peer_id.clone()), expect, option, complete_fn.clone()
-        ));
+        if let Some(dht) = self.dht4.as_ref() {
+            let taskid = dht.borrow().find_peer(Rc::new(peer_id.clone()), expect, &options, found_fn.clone(), complete_fn.clone());
+            locked.add_task(Network::IPv4, taskid);
+        }
+        if let Some(dht) = self.dht6.as_ref() {
+            let taskid = dht.borrow().find_peer(Rc::new(peer_id.clone()), expect, &options, found_fn.clone(), complete_fn.clone());
+            locked.add_task(Network::IPv6, taskid);
+        }
+    }
+
+    // The caller has dropped the future of the lookup command, the tasks
+    // still running on its behalf are of no use any more.
+    fn cancel(&self, cmd: Command) {
+        for (network, taskid) in cmd.tasks() {
+            let dht = match network {
+                Network::IPv4 => self.dht4.as_ref(),
+                Network::IPv6 => self.dht6.as_ref(),
+            };
+            if let Some(dht) = dht {
+                debug!("Canceling task {} on {} network", taskid, network);
+                dht.borrow().taskman().borrow_mut().cancel(taskid);
+            }
+        }
     }
 
     fn store_value(&self, cmd: Arc<Mutex<StoreValueCmd>>, persistence_forced: bool) {
@@ -509,18 +700,28 @@ impl NodeRunner {
         let num_dhts = self.dht_num;
         let cloned_cmd = cmd.clone();
         let completion = Rc::new(RefCell::new(0));
-        let complete_fn = Rc::new(RefCell::new(move |_| {
+        // Rejected only if no DHT got the announcement accepted.
+        let mut accepted = false;
+        let mut rejected = None;
+        let complete_fn = Rc::new(RefCell::new(move |result: Result<Vec<NodeInfo>, Error>| {
+            match result {
+                Ok(_) => accepted = true,
+                Err(e) => _ = rejected.get_or_insert(e),
+            }
             *completion.borrow_mut() += 1;
             if *completion.borrow() >= num_dhts {
-                cloned_cmd.lock().unwrap().complete(Ok(()))
+                cloned_cmd.lock().unwrap().complete(match rejected.take() {
+                    Some(e) if !accepted => Err(e),
+                    _ => Ok(()),
+                })
             }
         }));
 
         self.dht4.as_ref().map(|dht| dht.borrow().store_value(
-            locked.value(), complete_fn.clone()
+            locked.value(), locked.options(), complete_fn.clone()
         ));
         self.dht6.as_ref().map(|dht| dht.borrow().store_value(
-            locked.value(), complete_fn.clone()
+            locked.value(), locked.options(), complete_fn.clone()
         ));
     }
 
@@ -541,18 +742,28 @@ impl NodeRunner {
         let num_dhts = self.dht_num;
         let cloned_cmd = cmd.clone();
         let completion = Rc::new(RefCell::new(0));
-        let complete_fn = Rc::new(RefCell::new(move |_|{
+        // Rejected only if no DHT got the announcement accepted.
+        let mut accepted = false;
+        let mut rejected = None;
+        let complete_fn = Rc::new(RefCell::new(move |result: Result<Vec<NodeInfo>, Error>| {
+            match result {
+                Ok(_) => accepted = true,
+                Err(e) => _ = rejected.get_or_insert(e),
+            }
             *completion.borrow_mut() += 1;
             if *completion.borrow() >= num_dhts {
-                cloned_cmd.lock().unwrap().complete(Ok(()))
+                cloned_cmd.lock().unwrap().complete(match rejected.take() {
+                    Some(e) if !accepted => Err(e),
+                    _ => Ok(()),
+                })
             }
         }));
 
         self.dht4.as_ref().map(|dht| dht.borrow().announce_peer(
-            locked.peer(), complete_fn.clone()
+            locked.peer(), locked.options(), complete_fn.clone()
         ));
         self.dht6.as_ref().map(|dht| dht.borrow().announce_peer(
-            locked.peer(), complete_fn.clone()
+            locked.peer(), locked.options(), complete_fn.clone()
         ));
     }
 
@@ -617,46 +828,129 @@ impl NodeRunner {
             }).ok();
     }
 
+    fn get_stats(&self, cmd: Arc<Mutex<GetStatsCmd>>) {
+        let stats = self.server.borrow().stats();
+        let snapshot = stats.borrow_mut().snapshot();
+        cmd.lock().unwrap().complete(Ok(snapshot));
+    }
+
+    fn get_routing_table(&self, cmd: Arc<Mutex<GetRoutingTableCmd>>) {
+        let mut locked = cmd.lock().unwrap();
+        let network = locked.network();
+        let dht = match network {
+            Network::IPv4 => self.dht4.as_ref(),
+            Network::IPv6 => self.dht6.as_ref(),
+        };
+
+        match dht {
+            Some(dht) => {
+                let rt = dht.borrow().rt();
+                let snapshot = RoutingTableSnapshot::from(network, &rt.borrow());
+                locked.complete(Ok(snapshot));
+            },
+            None => locked.complete(Err(Error::State(
+                format!("DHT on {} network is not enabled", network)
+            )))
+        }
+    }
+
+    // Ready once any DHT has bootstrapped and holds enough entries, or failed
+    // if the bootstrap nodes of all DHTs are unreachable.
+    fn check_ready(&self, cmd: Arc<Mutex<CheckReadyCmd>>) {
+        let mut failed = Vec::new();
+        let mut ready = false;
+        let mut bootstrap = Vec::new();
+
+        for dht in [self.dht4.as_ref(), self.dht6.as_ref()].into_iter().flatten() {
+            let dht = dht.borrow();
+            bootstrap.push((dht.network(), dht.bootstrap_state()));
+            match dht.bootstrap_state() {
+                BootstrapState::Completed => {
+                    if dht.rt().borrow().size_of_entries() >= constants::READY_IF_AT_LEAST_X_PEERS {
+                        ready = true;
+                    }
+                },
+                BootstrapState::Failed => failed.push(dht.network().to_string()),
+                BootstrapState::Pending => {},
+            }
+        }
+
+        let result = match !ready && failed.len() as i32 == self.dht_num {
+            true => Err(Error::Network(format!(
+                "Bootstrap nodes on {} network are unreachable", failed.join("/")
+            ))),
+            false => Ok(Readiness { ready, bootstrap }),
+        };
+        cmd.lock().unwrap().complete(result);
+    }
+
     pub(crate) fn encrypt_into(&self,
         recipient: &Id,
         plain: &[u8]
     ) -> Result<Vec<u8>, Error> {
-        self.encryption_ctx.borrow_mut().get(recipient).ctx()
-            .encrypt_into(plain)
+        self.encryption_ctx.borrow_mut().encrypt_into(recipient, plain)
     }
 
     pub(crate) fn decrypt_into(&self,
         sender: &Id,
         cipher: &[u8]
-    ) -> Result<Vec<u8>, Error> {
-        self.encryption_ctx.borrow_mut().get(sender).ctx()
-            .decrypt_into(cipher)
+    ) -> Result<(Vec<u8>, Option<SystemTime>, Received), Error> {
+        self.encryption_ctx.borrow_mut().decrypt_into(sender, cipher)
     }
+
+    // Only called for the packets passed the replay check.
+    pub(crate) fn accept(&self, sender: &Id, received: Received, ver: i32) {
+        let mut ctx = self.encryption_ctx.borrow_mut();
+        ctx.accept(sender, received);
+        ctx.learn_version(sender, ver);
+    }
+}
+
+// Reports the outcome of the startup back to the node, at most once.
+pub(crate) type StartedFn = Box<dyn FnOnce(Result<(), Error>)>;
+
+pub(crate) async fn run(runner: Rc<RefCell<NodeRunner>>,
+    quit: Arc<Mutex<bool>>,
+    started: StartedFn
+) {
+    let context = runner.borrow().context.clone();
+    Scoped {
+        context,
+        inner: Box::pin(serve(runner, quit, started)),
+    }.await
 }
 
-pub(crate) fn run_loop(runner: Rc<RefCell<NodeRunner>>,  quit: Arc<Mutex<bool>>) {
+async fn serve(runner: Rc<RefCell<NodeRunner>>,
+    quit: Arc<Mutex<bool>>,
+    started: StartedFn
+) {
     let server = runner.borrow().server.clone();
     let dht4 = runner.borrow().dht4.as_ref().map(|v| v.clone());
     let dht6 = runner.borrow().dht6.as_ref().map(|v| v.clone());
-
-    let mut to_quit = false;
+    let transport = runner.borrow().transport.clone();
+    let mut started = Some(started);
 
     server.borrow_mut().start();
-    runner.borrow_mut().start().err().map(|e| {
-        error!("{}", e);
-        to_quit = true;
-    });
-
-    if !to_quit {
-        _ = server::run_loop(
+    let mut result = runner.borrow_mut().start();
+    if result.is_ok() {
+        result = server::serve(
             runner.clone(),
             server.clone(),
             dht4,
             dht6,
-            quit.clone()
-        ).map_err(|err| {
-            error!("Internal error: {}.", err);
-        });
+            transport,
+            quit.clone(),
+            &mut started
+        ).await;
+    }
+
+    // Startup failures are reported back to the node, the report would
+    // be ignored if the node has already been started.
+    if let Err(e) = result {
+        error!("{}", e);
+        if let Some(started) = started.take() {
+            started(Err(e));
+        }
     }
 
     runner.borrow_mut().stop();
@@ -669,3 +963,16 @@ pub(crate) fn run_loop(runner: Rc<RefCell<NodeRunner>>,  quit: Arc<Mutex<bool>>)
     }
     drop(_quit);
 }
+
+// Run the node on a runtime of its own, which is bound to the calling thread.
+pub(crate) fn run_loop(runner: Rc<RefCell<NodeRunner>>,
+    quit: Arc<Mutex<bool>>,
+    started: StartedFn
+) {
+    let rt = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt.block_on(run(runner, quit, started));
+}
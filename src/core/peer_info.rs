@@ -179,7 +179,7 @@ impl PeerInfo {
             match k {
                 "id" => pk = Some(Id::from_cbor(v)?),
                 "nodeid" => nodeid = Some(Id::from_cbor(v)?),
-                "port" => port = v.as_integer()?.try_into().unwrap(),
+                "port" => port = v.as_integer()?.try_into().ok()?,
                 "url" => url = v.as_text().map(|v|v.to_string()),
                 "sig" => sig = Some(v.as_bytes()?.to_vec()),
                 _ => return None,
@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    as_millis,
+    Id,
+    Error,
+};
+use crate::core::{
+    clock,
+    constants,
+    msg::msg::Kind,
+};
+
+// Tracks the packets recently received from each remote node to reject
+// replayed ones. A packet is taken as a replay if its timestamp falls
+// out of the window, or its transaction id has been seen within it.
+// Legacy packets carry no timestamp, they are only checked against the
+// seen transaction ids, which are kept for the time they were received.
+// Older ones can't be told from the new, which is why the legacy packets
+// are only taken from the peers never seen sending the versioned formats.
+//
+// The check has to pass before the packet changes anything about the
+// sender, its crypto session included.
+pub(crate) struct ReplayWindow {
+    peers: HashMap<Id, Peer>,
+}
+
+impl ReplayWindow {
+    pub(crate) fn new() -> Self {
+        Self {
+            peers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn check(&mut self,
+        sender: &Id,
+        kind: Kind,
+        txid: i32,
+        timestamp: Option<SystemTime>
+    ) -> Result<(), Error> {
+        let window = Duration::from_millis(constants::REPLAY_WINDOW as u64);
+        let now = clock::now();
+        let time = match timestamp {
+            Some(v) => v,
+            None => now,
+        };
+
+        if clock::elapsed(time) > window || clock::elapsed_until(time) > window {
+            return Err(Error::Protocol(format!(
+                "Stale packet from {}, txid {}", sender, txid
+            )));
+        }
+
+        let peer = self.peers.entry(sender.clone()).or_insert_with(Peer::new);
+        peer.last_seen = now;
+
+        if peer.floor.is_some_and(|floor| time <= floor) {
+            return Err(Error::Protocol(format!(
+                "Stale packet from {}, txid {}", sender, txid
+            )));
+        }
+
+        // Error messages may come without a transaction id.
+        if txid == 0 {
+            return Ok(());
+        }
+        if !peer.seen.insert((kind, txid)) {
+            return Err(Error::Protocol(format!(
+                "Duplicate packet from {}, txid {}", sender, txid
+            )));
+        }
+
+        peer.order.push_back((kind, txid, time));
+        if peer.order.len() > constants::REPLAY_CACHE_SIZE {
+            // Packets no later than the evicted one can't be told from
+            // replays any more.
+            if let Some((kind, txid, time)) = peer.order.pop_front() {
+                peer.seen.remove(&(kind, txid));
+                peer.floor = Some(peer.floor.map_or(time, |floor| floor.max(time)));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn expire(&mut self) {
+        self.peers.retain(|_, peer| {
+            peer.expire();
+            as_millis!(peer.last_seen) < constants::REPLAY_WINDOW
+        });
+    }
+
+    #[cfg(test)]
+    pub(crate) fn size(&self) -> usize {
+        self.peers.len()
+    }
+}
+
+struct Peer {
+    seen: HashSet<(Kind, i32)>,
+    // Seen transaction ids in the order they were received.
+    order: VecDeque<(Kind, i32, SystemTime)>,
+    // Packets sent no later than this are rejected.
+    floor: Option<SystemTime>,
+    last_seen: SystemTime,
+}
+
+impl Peer {
+    fn new() -> Self {
+        Self {
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            floor: None,
+            last_seen: clock::now(),
+        }
+    }
+
+    // Entries out of the window are rejected by their timestamps anyway.
+    fn expire(&mut self) {
+        while let Some((kind, txid, time)) = self.order.front().cloned() {
+            if as_millis!(time) < constants::REPLAY_WINDOW {
+                break;
+            }
+            self.order.pop_front();
+            self.seen.remove(&(kind, txid));
+        }
+
+        if self.floor.is_some_and(|floor| as_millis!(floor) >= constants::REPLAY_WINDOW) {
+            self.floor = None;
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::time::SystemTime;
+use serde::ser::{Serialize, Serializer, SerializeStruct};
+
+use crate::{
+    Network,
+    NodeInfo,
+    Prefix,
+};
+
+use crate::core::{
+    kbucket::KBucket,
+    kbucket_entry::KBucketEntry,
+    node_info::Reachable,
+    routing_table::RoutingTable,
+};
+
+fn to_millis(time: &SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Read-only copy of a routing table entry.
+#[derive(Clone, Debug)]
+pub struct EntrySnapshot {
+    ni: NodeInfo,
+    created: SystemTime,
+    last_seen: SystemTime,
+    failed_requests: i32,
+    reachable: bool,
+}
+
+impl EntrySnapshot {
+    pub(crate) fn from(entry: &KBucketEntry) -> Self {
+        Self {
+            ni: entry.ni().as_ref().clone(),
+            created: *entry.created_time(),
+            last_seen: *entry.last_seen(),
+            failed_requests: entry.failed_requests(),
+            reachable: entry.reachable(),
+        }
+    }
+
+    pub fn node(&self) -> &NodeInfo {
+        &self.ni
+    }
+
+    pub fn version(&self) -> i32 {
+        self.ni.version()
+    }
+
+    pub fn created(&self) -> &SystemTime {
+        &self.created
+    }
+
+    pub fn last_seen(&self) -> &SystemTime {
+        &self.last_seen
+    }
+
+    pub fn failed_requests(&self) -> i32 {
+        self.failed_requests
+    }
+
+    pub fn is_reachable(&self) -> bool {
+        self.reachable
+    }
+}
+
+impl Serialize for EntrySnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("EntrySnapshot", 7)?;
+        state.serialize_field("id", &self.ni.id().to_base58())?;
+        state.serialize_field("address", &self.ni.socket_addr().to_string())?;
+        state.serialize_field("version", &self.ni.version_str())?;
+        state.serialize_field("created", &to_millis(&self.created))?;
+        state.serialize_field("lastSeen", &to_millis(&self.last_seen))?;
+        state.serialize_field("failedRequests", &self.failed_requests)?;
+        state.serialize_field("reachable", &self.reachable)?;
+        state.end()
+    }
+}
+
+impl fmt::Display for EntrySnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}; seen:{}; created:{}",
+            self.ni,
+            to_millis(&self.last_seen),
+            to_millis(&self.created)
+        )?;
+        if self.failed_requests > 0 {
+            write!(f, "; fail:{}", self.failed_requests)?;
+        }
+        if self.reachable {
+            write!(f, "; reachable")?;
+        }
+        Ok(())
+    }
+}
+
+// Read-only copy of a KBucket with its prefix and entries.
+#[derive(Clone, Debug)]
+pub struct BucketSnapshot {
+    prefix: Prefix,
+    entries: Vec<EntrySnapshot>,
+}
+
+impl BucketSnapshot {
+    pub(crate) fn from(bucket: &KBucket) -> Self {
+        Self {
+            prefix: bucket.prefix().as_ref().clone(),
+            entries: bucket.entries().iter()
+                .map(|item| EntrySnapshot::from(&item.borrow()))
+                .collect(),
+        }
+    }
+
+    pub fn prefix(&self) -> &Prefix {
+        &self.prefix
+    }
+
+    pub fn entries(&self) -> &[EntrySnapshot] {
+        &self.entries
+    }
+
+    pub fn size(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl Serialize for BucketSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("BucketSnapshot", 2)?;
+        state.serialize_field("prefix", &self.prefix.to_string())?;
+        state.serialize_field("entries", &self.entries)?;
+        state.end()
+    }
+}
+
+impl fmt::Display for BucketSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Prefix: {}, entries: {}", self.prefix, self.entries.len())?;
+        for item in self.entries.iter() {
+            writeln!(f, "  {}", item)?;
+        }
+        Ok(())
+    }
+}
+
+// Read-only copy of the routing table of the DHT on one network.
+#[derive(Clone, Debug)]
+pub struct RoutingTableSnapshot {
+    network: Network,
+    buckets: Vec<BucketSnapshot>,
+}
+
+impl RoutingTableSnapshot {
+    pub(crate) fn from(network: Network, rt: &RoutingTable) -> Self {
+        Self {
+            network,
+            buckets: rt.buckets().iter()
+                .map(|(_,v)| BucketSnapshot::from(&v.borrow()))
+                .collect(),
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn buckets(&self) -> &[BucketSnapshot] {
+        &self.buckets
+    }
+
+    pub fn size_of_entries(&self) -> usize {
+        self.buckets.iter().map(|v| v.size()).sum()
+    }
+}
+
+impl Serialize for RoutingTableSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RoutingTableSnapshot", 2)?;
+        state.serialize_field("network", &self.network.to_string())?;
+        state.serialize_field("buckets", &self.buckets)?;
+        state.end()
+    }
+}
+
+impl fmt::Display for RoutingTableSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Routing table/{}: buckets {}, entries {}",
+            self.network,
+            self.buckets.len(),
+            self.size_of_entries()
+        )?;
+        for item in self.buckets.iter() {
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
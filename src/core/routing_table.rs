@@ -1,5 +1,6 @@
 use std::fmt;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::cell::RefCell;
 use std::fs::File;
 use std::io::{Read, Write};
@@ -8,7 +9,7 @@ use std::collections::HashMap;
 use rbtree::RBTree;
 use ciborium::value::Value;
 use libsodium_sys::randombytes_uniform;
-use log::{info, warn};
+use log::{debug, info, warn};
 
 use crate::{
     as_millis,
@@ -17,11 +18,15 @@ use crate::{
 };
 
 use crate::core::{
+    subnet_of,
+    clock,
     constants,
     cbor,
     dht::DHT,
     node_info::Reachable,
-    kbucket::KBucket,
+    event::{EventBus, NodeEvent},
+    stats::Stats,
+    kbucket::{KBucket, PutResult},
     kbucket_entry::KBucketEntry,
     task::{
         ping_refresh::PingOption,
@@ -33,8 +38,13 @@ use crate::core::{
 pub(crate) struct RoutingTable {
     nodeid: Rc<Id>, // Node Id of current DHT Node.
     dht: Option<Rc<RefCell<DHT>>>,
+    events: Option<Arc<EventBus>>,
+    stats: Option<Rc<RefCell<Stats>>>,
     buckets: RBTree<Rc<Prefix>, Rc<RefCell<KBucket>>>,
 
+    bucket_subnet_limit: usize,
+    table_subnet_limit: usize,
+
     time_of_last_ping_check: SystemTime,
     maintenance_tasks: HashMap<Rc<Prefix>, Rc<RefCell<Box<dyn Task>>>>,
 }
@@ -52,8 +62,13 @@ impl RoutingTable {
         Self {
             nodeid,
             dht: None,
+            events: None,
+            stats: None,
             buckets,
 
+            bucket_subnet_limit: constants::BUCKET_SUBNET_LIMIT,
+            table_subnet_limit: constants::TABLE_SUBNET_LIMIT,
+
             time_of_last_ping_check: SystemTime::UNIX_EPOCH,
             maintenance_tasks: HashMap::new(),
         }
@@ -68,6 +83,29 @@ impl RoutingTable {
         self.dht.as_ref().unwrap().clone()
     }
 
+    pub(crate) fn set_events(&mut self, events: Arc<EventBus>) {
+        self.events = Some(events)
+    }
+
+    pub(crate) fn set_stats(&mut self, stats: Rc<RefCell<Stats>>) {
+        self.stats = Some(stats)
+    }
+
+    pub(crate) fn set_subnet_limits(&mut self, bucket: usize, table: usize) {
+        self.bucket_subnet_limit = bucket;
+        self.table_subnet_limit = table;
+    }
+
+    pub(crate) fn bucket_subnet_limit(&self) -> usize {
+        self.bucket_subnet_limit
+    }
+
+    fn publish(&self, event: NodeEvent) {
+        if let Some(events) = self.events.as_ref() {
+            events.publish(event);
+        }
+    }
+
     pub(crate) fn size(&self) -> usize {
         self.buckets.len()
     }
@@ -101,6 +139,19 @@ impl RoutingTable {
         self.buckets.remove(&key).unwrap()
     }
 
+    // Whether a reachable node with the given id would be taken into the
+    // table, or kept as a candidate for it, otherwise it's ignored no matter
+    // how it responds.
+    pub(crate) fn accepts(&self, id: &Id) -> bool {
+        let bucket = self.bucket(id);
+        let bucket = bucket.borrow();
+        !bucket.is_full() ||
+            bucket.needs_replacement() ||
+            bucket.wants_candidate(id) ||
+            (bucket.prefix().is_splittable() &&
+                bucket.prefix().split_branch(true).is_prefix_of(id))
+    }
+
     pub(crate) fn bucket_entry(&self, target: &Id) -> Option<Rc<RefCell<KBucketEntry>>> {
         self.bucket(target).borrow().entry(target)
     }
@@ -137,7 +188,19 @@ impl RoutingTable {
     }
 
     pub(crate) fn put(&mut self, entry: Rc<RefCell<KBucketEntry>>) {
-        self._put(entry)
+        let ni = entry.borrow().ni();
+        match self._put(entry) {
+            PutResult::Added => {
+                self.publish(NodeEvent::EntryAdded(ni.as_ref().clone()))
+            },
+            PutResult::Replaced(old) => {
+                self.publish(NodeEvent::EntryReplaced {
+                    old: old.borrow().ni().as_ref().clone(),
+                    new: ni.as_ref().clone()
+                })
+            },
+            _ => {}
+        }
     }
 
     pub(crate) fn remove(&mut self, id: &Id) {
@@ -156,6 +219,11 @@ impl RoutingTable {
         self._maintenance()
     }
 
+    #[cfg(test)]
+    pub(crate) fn merge_buckets(&mut self) {
+        self._merge_buckets()
+    }
+
     pub(crate) fn load(&mut self, path: &str) {
         let mut fp = match File::open(path) {
             Ok(v) => v,
@@ -214,18 +282,35 @@ impl RoutingTable {
                             Some(v) => v,
                             None => return,
                         };
-                        self._put(
+                        _ = self._put(
                             Rc::new(RefCell::new(entry))
                         );
                     }
                     len = v.len();
                 },
+                "cache" => {
+                    let v = match v.as_array() {
+                        Some(v) => v,
+                        None => return,
+                    };
+
+                    for item in v.iter() {
+                        let entry = match KBucketEntry::from_cbor(item) {
+                            Some(v) => v,
+                            None => return,
+                        };
+                        let bucket = self.bucket(entry.id());
+                        if self.admits(&bucket.borrow(), &entry) {
+                            bucket.borrow_mut()._put_cache(Rc::new(RefCell::new(entry)));
+                        }
+                    }
+                },
                 _ => return,
             };
         }
 
         info!("Loaded {} entries from persistent file, it was {} min old", len,
-            SystemTime::now().duration_since(timestamp).unwrap().as_secs() / 60
+            clock::elapsed(timestamp).as_secs() / 60
         );
     }
 
@@ -239,21 +324,28 @@ impl RoutingTable {
         };
 
         let mut entries = vec![];
+        let mut cache = vec![];
         for bucket in self.buckets.values() {
             bucket.borrow().entries().iter().for_each(|item| {
                 entries.push(item.borrow().to_cbor());
             });
-            // TODOO
+            bucket.borrow().cache_entries().iter().for_each(|item| {
+                cache.push(item.borrow().to_cbor());
+            });
         }
 
         let mut val = Value::Map(vec![
             (
                 Value::Text(String::from("timestamp")),
-                Value::Integer(SystemTime::now().elapsed().unwrap().as_secs().into())
+                Value::Integer(clock::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs().into())
             ),
             (
                 Value::Text(String::from("entries")),
                 Value::Array(entries)
+            ),
+            (
+                Value::Text(String::from("cache")),
+                Value::Array(cache)
             )
         ]);
 
@@ -285,8 +377,15 @@ impl RoutingTable {
         while let Some(entry) = borrowed.pop() {
             let id = entry.borrow().id().clone();
             match low.prefix().is_prefix_of(&id) {
-                true  => low._put(entry),
-                false => high._put(entry)
+                true  => _ = low._put(entry),
+                false => _ = high._put(entry)
+            }
+        }
+        for entry in borrowed._take_cache() {
+            let id = entry.borrow().id().clone();
+            match low.prefix().is_prefix_of(&id) {
+                true  => low._put_cache(entry),
+                false => high._put_cache(entry)
             }
         }
 
@@ -294,31 +393,91 @@ impl RoutingTable {
         self.buckets.insert(ph, Rc::new(RefCell::new(high)));
     }
 
-    fn _put(&mut self, input: Rc<RefCell<KBucketEntry>>) {
+    fn _put(&mut self, input: Rc<RefCell<KBucketEntry>>) -> PutResult {
         let nodeid = input.borrow().id().clone();
         let mut bucket = self.pop_bucket(&nodeid);
         while _needs_split(&bucket, &input) {
             self._split(&bucket);
             bucket = self.pop_bucket(&nodeid);
         }
-        bucket.borrow_mut()._put(input);
         let prefix = bucket.borrow().prefix().clone();
-        self.buckets.insert(prefix, bucket);
+        self.buckets.insert(prefix, bucket.clone());
+
+        if !self.admits(&bucket.borrow(), &input.borrow()) {
+            self.reject(&input.borrow());
+            return PutResult::Ignored;
+        }
+        let result = bucket.borrow_mut()._put(input);
+        result
+    }
+
+    // Whether the entries from the subnet of the node, the replacement
+    // candidates included, leave room for it in the bucket and the table,
+    // so that a single host can't fill the table with made up node ids.
+    fn admits(&self, bucket: &KBucket, entry: &KBucketEntry) -> bool {
+        let ni = entry.ni();
+        let subnet = match subnet_of(ni.socket_addr()) {
+            Some(v) => v,
+            None => return true,
+        };
+        if bucket.exists(entry.id()) {
+            return true;
+        }
+
+        let mut total = bucket.subnet_entries(&subnet, entry.id());
+        if total >= self.bucket_subnet_limit {
+            return false;
+        }
+        for (k,v) in self.buckets.iter() {
+            if k != bucket.prefix() {
+                total += v.borrow().subnet_entries(&subnet, entry.id());
+            }
+        }
+        total < self.table_subnet_limit
+    }
+
+    fn reject(&self, entry: &KBucketEntry) {
+        debug!("Node {} rejected, too many entries from its subnet", entry.ni());
+        if let Some(stats) = self.stats.as_ref() {
+            stats.borrow_mut().on_rejected_entry();
+        }
     }
 
     fn _remove(&mut self, id: &Id) {
         let bucket = self.bucket(id);
-        let mut borrowed_mut = bucket.borrow_mut();
-        let to_remove = match borrowed_mut.entry(id) {
-            Some(v) => v.clone(),
-            None => return,
+        let removed = {
+            let mut borrowed_mut = bucket.borrow_mut();
+            let to_remove = match borrowed_mut.entry(id) {
+                Some(v) => v.clone(),
+                None => return,
+            };
+            borrowed_mut._remove_bad_entry(to_remove, true)
         };
-        borrowed_mut._remove_bad_entry(to_remove, true);
+        if let Some(removed) = removed {
+            self._refill(&bucket, removed);
+        }
     }
 
     #[inline(always)]
     fn _on_timeout(&mut self, id: &Id) {
-        self.bucket(id).borrow_mut().on_timeout(id);
+        let bucket = self.bucket(id);
+        let removed = bucket.borrow_mut().on_timeout(id);
+        if let Some(removed) = removed {
+            self._refill(&bucket, removed);
+        }
+    }
+
+    // Take a replacement candidate in place of the removed entry.
+    fn _refill(&self, bucket: &Rc<RefCell<KBucket>>, removed: Rc<RefCell<KBucketEntry>>) {
+        let old = removed.borrow().ni().as_ref().clone();
+        let promoted = bucket.borrow_mut()._promote_candidate();
+        match promoted {
+            Some(new) => self.publish(NodeEvent::EntryReplaced {
+                old,
+                new: new.borrow().ni().as_ref().clone()
+            }),
+            None => self.publish(NodeEvent::EntryRemoved(old)),
+        }
     }
 
     #[inline(always)]
@@ -326,8 +485,66 @@ impl RoutingTable {
         self.bucket(id).borrow_mut().on_send(id);
     }
 
+    // Merge the sibling buckets back into their parent if the live entries
+    // of both fit in one bucket, which undoes the splits made when the
+    // entries far from the home bucket have gone.
     fn _merge_buckets(&mut self) {
-        // TODO:
+        let mut prefixes: Vec<Rc<Prefix>> = self.buckets.keys().cloned().collect();
+        let mut i = 0;
+        while i + 1 < prefixes.len() {
+            let low  = prefixes[i].clone();
+            let high = prefixes[i + 1].clone();
+            if !low.is_sibling_of(&high) ||
+                self.live_entries(&low) + self.live_entries(&high) > constants::MAX_ENTRIES_PER_BUCKET {
+                i += 1;
+                continue;
+            }
+
+            let parent = Rc::new(low.parent());
+            let home_bucket = parent.is_prefix_of(&self.nodeid);
+            let mut merged = KBucket::new(parent.clone(), home_bucket);
+
+            // Put the live entries first, the others only take the room left.
+            let mut entries = Vec::new();
+            let mut cache = Vec::new();
+            for prefix in [&low, &high] {
+                let bucket = self.buckets.remove(prefix).unwrap();
+                let mut borrowed = bucket.borrow_mut();
+                while let Some(entry) = borrowed.pop() {
+                    entries.push(entry);
+                }
+                cache.append(&mut borrowed._take_cache());
+            }
+            entries.sort_by_key(|entry| entry.borrow().needs_replacement());
+            for entry in entries {
+                let admitted = self.admits(&merged, &entry.borrow());
+                if !admitted {
+                    self.reject(&entry.borrow());
+                }
+                if !admitted || matches!(merged._put(entry.clone()), PutResult::Ignored) {
+                    self.publish(NodeEvent::EntryRemoved(entry.borrow().ni().as_ref().clone()));
+                }
+            }
+            for entry in cache {
+                if self.admits(&merged, &entry.borrow()) {
+                    merged._put_cache(entry);
+                }
+            }
+
+            self.buckets.insert(parent.clone(), Rc::new(RefCell::new(merged)));
+            prefixes.splice(i..i + 2, [parent]);
+
+            // The merged bucket might be merged with its own sibling again.
+            i = i.saturating_sub(1);
+        }
+    }
+
+    fn live_entries(&self, prefix: &Rc<Prefix>) -> usize {
+        self.buckets.get(prefix).map_or(0, |bucket| {
+            bucket.borrow().entries().iter()
+                .filter(|entry| !entry.borrow().needs_replacement())
+                .count()
+        })
     }
 
     pub(crate) fn try_ping_maintenance(&mut self,
@@ -358,7 +575,7 @@ impl RoutingTable {
             return;
         }
 
-        self.time_of_last_ping_check = SystemTime::now();
+        self.time_of_last_ping_check = clock::now();
         self._merge_buckets();
 
         let mut buckets: Vec<Rc<RefCell<KBucket>>> = self.buckets.values().map(|v| v.clone()).collect();
@@ -388,7 +605,9 @@ impl RoutingTable {
                 // We use this block to limit the scope of the mutable borrow.
                 let mut borrowed_mut = bucket.borrow_mut();
                 while let Some(entry) = to_remove.pop() {
-                    _ = borrowed_mut._remove_bad_entry(entry.clone(), true);
+                    if let Some(removed) = borrowed_mut._remove_bad_entry(entry.clone(), true) {
+                        self.publish(NodeEvent::EntryRemoved(removed.borrow().ni().as_ref().clone()));
+                    }
                 }
                 // Fix the wrong entries
                 while let Some(entry) = to_adjust.pop() {
@@ -407,7 +626,14 @@ impl RoutingTable {
 
         // Put the adjusted ones to their right buckets.
         while let Some(entry) = to_push.pop() {
-            self._put(entry);
+            _ = self._put(entry);
+        }
+
+        // Fill the room left by the removed entries with the candidates.
+        for bucket in self.buckets.values() {
+            while let Some(promoted) = bucket.borrow_mut()._promote_candidate() {
+                self.publish(NodeEvent::EntryAdded(promoted.borrow().ni().as_ref().clone()));
+            }
         }
     }
 }
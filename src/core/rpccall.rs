@@ -2,7 +2,6 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use std::time::SystemTime;
 use std::time::Duration;
-use libsodium_sys::randombytes_uniform;
 
 use crate::{
     unwrap,
@@ -11,6 +10,7 @@ use crate::{
 };
 
 use crate::core::{
+    clock,
     constants,
     dht::DHT,
     scheduler::Scheduler,
@@ -25,6 +25,7 @@ pub(crate) enum State {
     Timeout,
     Err,
     Responsed,
+    Canceled,
 }
 
 pub(crate) struct RpcCall {
@@ -36,6 +37,7 @@ pub(crate) struct RpcCall {
 
     sent: SystemTime,
     responsed: SystemTime,
+    timeout: Option<Duration>,  // derived from the round-trip times if not given.
 
     state: State,
 
@@ -43,27 +45,13 @@ pub(crate) struct RpcCall {
     responsed_fn: Box<dyn Fn(&RpcCall, Rc<RefCell<Box<dyn Msg>>>)>,
     stalled_fn: Box<dyn Fn(&RpcCall)>,
     timeout_fn: Box<dyn Fn(&RpcCall)>,
+    canceled_fn: Box<dyn Fn(&RpcCall)>,
 
     dht: Rc<RefCell<DHT>>,
     scheduler: Option<Rc<RefCell<Scheduler>>>,
     cloned: Option<Rc<RefCell<RpcCall>>>,
 }
 
-static mut NEXT_TXID: i32 = 0;
-fn next_txid() -> i32 {
-    unsafe {
-        if NEXT_TXID == 0 {
-            NEXT_TXID = randombytes_uniform(u32::MAX) as i32
-        }
-
-        NEXT_TXID += 1;
-        if NEXT_TXID == 0 {
-            NEXT_TXID += 1;
-        }
-        NEXT_TXID
-    }
-}
-
 impl RpcCall {
     pub(crate) fn new(target: Rc<NodeInfo>,
         dht: Rc<RefCell<DHT>>,
@@ -75,19 +63,21 @@ impl RpcCall {
         );
 
         RpcCall {
-            txid: next_txid(),
+            txid: 0,     // assigned by the server once being sent.
             target,
             req: Some(msg),
             rsp: None,
 
             sent: SystemTime::UNIX_EPOCH,
             responsed: SystemTime::UNIX_EPOCH,
+            timeout: None,
             state: State::Unsent,
 
             state_changed_fn: Box::new(|_, _, _| {}),
             responsed_fn: Box::new(|_, _| {}),
             stalled_fn: Box::new(|_| {}),
             timeout_fn: Box::new(|_| {}),
+            canceled_fn: Box::new(|_| {}),
 
             dht,
             scheduler: None,
@@ -99,6 +89,10 @@ impl RpcCall {
         self.txid
     }
 
+    pub(crate) fn set_txid(&mut self, txid: i32) {
+        self.txid = txid;
+    }
+
     pub(crate) fn dht(&self) -> Rc<RefCell<DHT>> {
         self.dht.clone()
     }
@@ -141,6 +135,26 @@ impl RpcCall {
         &self.sent
     }
 
+    // The round-trip time of the call, once answered.
+    pub(crate) fn rtt(&self) -> Option<Duration> {
+        if self.sent == SystemTime::UNIX_EPOCH || self.responsed == SystemTime::UNIX_EPOCH {
+            return None;
+        }
+        self.responsed.duration_since(self.sent).ok()
+    }
+
+    pub(crate) fn has_timeout(&self) -> bool {
+        self.timeout.is_some()
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX))
+    }
+
     pub(crate) fn set_state_changed_fn<F>(&mut self, f: F)
     where F: Fn(&RpcCall, &State, &State) + 'static {
         self.state_changed_fn = Box::new(f)
@@ -161,6 +175,26 @@ impl RpcCall {
         self.timeout_fn = Box::new(f)
     }
 
+    pub(crate) fn set_canceled_fn<F>(&mut self, f: F)
+    where F: Fn(&RpcCall) + 'static {
+        self.canceled_fn = Box::new(f)
+    }
+
+    pub(crate) fn is_canceled(&self) -> bool {
+        self.state == State::Canceled
+    }
+
+    // Cancel the call on behalf of the owner task, so the state changed
+    // function would not be invoked.
+    pub(crate) fn cancel(&mut self) {
+        if self.state == State::Unsent ||
+            self.state == State::Sent ||
+            self.state == State::Stalled {
+            self.state = State::Canceled;
+            (self.canceled_fn)(self);
+        }
+    }
+
     pub(crate) fn update_state(&mut self, new_state: State) {
         let prev_state = self.state.clone();
         self.state = new_state;
@@ -179,19 +213,20 @@ impl RpcCall {
     }
 
     pub(crate) fn send(&mut self, scheduler: Rc<RefCell<Scheduler>>) {
-        self.sent = SystemTime::now();
+        self.sent = clock::now();
         self.update_state(State::Sent);
 
         self.scheduler = Some(scheduler);
         let cloned = self.cloned.as_ref().unwrap().clone();
+        let check_after = (self.timeout().as_millis() as u64).min(2*1000);
         unwrap!(self.scheduler).borrow_mut().add_oneshot(move || {
             cloned.borrow_mut().check_timeout();
-        }, 2*1000);
+        }, check_after);
     }
 
     pub(crate) fn responsed(&mut self, msg: Rc<RefCell<Box<dyn Msg>>>) {
         self.rsp = Some(msg.clone());
-        self.responsed = SystemTime::now();
+        self.responsed = clock::now();
 
         match msg.borrow().kind() {
             msg::Kind::Request  => {},
@@ -213,8 +248,8 @@ impl RpcCall {
             return;
         }
 
-        let timeout = Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX);
-        let elapsed = self.sent.elapsed().unwrap();
+        let timeout = self.timeout();
+        let elapsed = clock::elapsed(self.sent);
 
         if timeout > elapsed {
             let remaining = (timeout - elapsed).as_millis() as u64;
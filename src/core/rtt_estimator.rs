@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use crate::core::constants;
+
+// Estimates the round-trip time to the remote nodes from the measured
+// samples, with the exponentially weighted moving mean and variance. The
+// timeout of a call is taken as the mean plus a few standard deviations,
+// and falls back to the maximum until the first sample comes in. Each
+// timeout doubles it until the next sample, as the late responses are
+// never measured (RFC 6298).
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RttEstimator {
+    mean: f64,      // in milliseconds.
+    variance: f64,
+    samples: u32,
+    backoff: u32,
+}
+
+impl RttEstimator {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub(crate) fn mean(&self) -> Option<Duration> {
+        match self.samples {
+            0 => None,
+            _ => Some(Duration::from_secs_f64(self.mean / 1000.0)),
+        }
+    }
+
+    pub(crate) fn update(&mut self, rtt: Duration) {
+        let sample = rtt.as_secs_f64() * 1000.0;
+        if self.samples == 0 {
+            // The deviation of a single sample is taken as half of it (RFC 6298).
+            self.mean = sample;
+            self.variance = (sample / 2.0).powi(2);
+        } else {
+            let gain = constants::RTT_SAMPLE_GAIN;
+            let delta = sample - self.mean;
+            self.mean += gain * delta;
+            self.variance = (1.0 - gain) * (self.variance + gain * delta * delta);
+        }
+        self.samples = self.samples.saturating_add(1);
+        self.backoff = 0;
+    }
+
+    pub(crate) fn backoff(&mut self) {
+        if self.samples > 0 {
+            self.backoff = (self.backoff + 1).min(16);
+        }
+    }
+
+    // Takes the samples of the other estimator on the same node, which
+    // count as one sample of their mean.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        if other.samples == 0 {
+            return;
+        }
+        if self.samples == 0 {
+            *self = other.clone();
+            return;
+        }
+        self.update(Duration::from_secs_f64(other.mean / 1000.0));
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        if self.samples == 0 {
+            return Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX);
+        }
+
+        let timeout = self.mean + constants::RTT_TIMEOUT_DEVIATIONS * self.variance.sqrt();
+        let timeout = (timeout.ceil() as u64).max(constants::RPC_CALL_TIMEOUT_MIN);
+        Duration::from_millis(timeout.saturating_mul(1 << self.backoff).min(
+            constants::RPC_CALL_TIMEOUT_MAX
+        ))
+    }
+}
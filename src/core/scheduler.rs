@@ -1,10 +1,13 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::LinkedList;
+use std::time::SystemTime;
 
 use rbtree::RBTree;
 use tokio::time::{Duration, Instant};
 
+use crate::core::clock;
+
 struct Job {
     cb: Box<dyn FnMut()>,
     interval: Option<Duration>,
@@ -32,16 +35,15 @@ impl Job {
 
 pub(crate) struct Scheduler {
     updated: bool,  // job has been added or popped recently.
-    now: Instant,   // to retify current time.
 
-    timers: RBTree<Instant, LinkedList<Box<Job>>>,
+    // keyed by the due time on the clock of the node.
+    timers: RBTree<SystemTime, LinkedList<Box<Job>>>,
 }
 
 impl Scheduler {
     pub(crate) fn new() -> Self {
         Scheduler {
             updated: false,
-            now: Instant::now(),
             timers: RBTree::new(),
         }
     }
@@ -68,15 +70,19 @@ impl Scheduler {
         self.updated
     }
 
+    // The due time of the first timer is mapped onto the runtime timer,
+    // which only wakes the loop up; whether jobs are due is decided by
+    // the clock in run_jobs.
     pub(crate) fn next_timeout(&self) -> Instant {
+        let now = Instant::now();
         self.timers.iter().next().map_or(
-            self.now + Duration::from_secs(3600), // 60*60
-            |timer| timer.0.clone()
+            now + Duration::from_secs(3600), // 60*60
+            |timer| now + clock::elapsed_until(timer.0)
         )
     }
 
     fn add_job(&mut self, start: Duration, job: Box<Job>) {
-        let start = self.now + start;
+        let start = clock::now() + start;
 
         match self.timers.get_mut(&start) {
             Some(timer) => {
@@ -92,34 +98,36 @@ impl Scheduler {
     }
 
     #[inline(always)]
-    fn pop_jobs(&mut self) -> Option<LinkedList<Box<Job>>> {
+    fn pop_due_jobs(&mut self, now: &SystemTime) -> Option<LinkedList<Box<Job>>> {
+        match self.timers.iter().next() {
+            Some((due, _)) if due <= now => {},
+            _ => return None,
+        }
         self.timers.pop_first().map(|(_,v)| v)
     }
 
-    #[inline(always)]
-    fn sync_time(&mut self) {
-        self.now = Instant::now();
-    }
-
     pub(crate) fn cancel(&mut self) {
         self.timers.clear();
     }
 }
 
+// Run all jobs which are due by now, periodic jobs are rescheduled relative
+// to the current time. Jobs added while running are left to the next round.
 pub(crate) fn run_jobs(s: Rc<RefCell<Scheduler>>) {
-    s.borrow_mut().sync_time();
-
-    let mut timer = match s.borrow_mut().pop_jobs() {
-        Some(timer) => timer,
-        None => return
-    };
-
-    while let Some(mut job) = timer.pop_front() {
-        job.invoke();
-        let next_start = match job.interval {
-            Some(interval) => interval,
-            None => continue,
-        };
-        s.borrow_mut().add_job(next_start, job);
+    let now = clock::now();
+    let mut timers = Vec::new();
+    while let Some(timer) = s.borrow_mut().pop_due_jobs(&now) {
+        timers.push(timer);
+    }
+
+    for mut timer in timers {
+        while let Some(mut job) = timer.pop_front() {
+            job.invoke();
+            let next_start = match job.interval {
+                Some(interval) => interval,
+                None => continue,
+            };
+            s.borrow_mut().add_job(next_start, job);
+        }
     }
 }
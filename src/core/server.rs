@@ -2,14 +2,15 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use std::time::SystemTime;
 use std::sync::{Arc, Mutex};
+use std::net::SocketAddr;
 use std::collections::{HashMap, LinkedList};
 
 use log::{warn, error};
+use libsodium_sys::randombytes_uniform;
 use tokio::{
     io,
-    runtime,
-    net::UdpSocket,
-    time::{sleep, interval_at, Duration}
+    sync::Notify,
+    time::{interval_at, Duration}
 };
 
 use crate::{
@@ -19,16 +20,53 @@ use crate::{
 };
 
 use crate::core::{
+    clock,
     constants,
     version,
     dht::DHT,
     rpccall::RpcCall,
-    node_runner::NodeRunner,
+    stats::Stats,
+    replay_window::ReplayWindow,
+    crypto_cache::Received,
+    event::{EventBus, NodeEvent},
+    node_runner::{NodeRunner, StartedFn},
     scheduler::{self, Scheduler},
+    transport::{Transport, TransportBinder},
     msg::msg::{self, Msg},
-    msg::{deser, serialize},
+    msg::{deser, serialize, serialize_within},
 };
 
+// Outgoing messages waiting to be written to the socket. Pushing a message
+// wakes up the pending write_socket future immediately.
+pub(crate) struct MsgQueue {
+    msgs: RefCell<LinkedList<Rc<RefCell<Box<dyn Msg>>>>>,
+    notify: Notify,
+}
+
+impl MsgQueue {
+    fn new() -> Self {
+        Self {
+            msgs: RefCell::new(LinkedList::new()),
+            notify: Notify::new(),
+        }
+    }
+
+    fn push(&self, msg: Rc<RefCell<Box<dyn Msg>>>) {
+        self.msgs.borrow_mut().push_back(msg);
+        self.notify.notify_one();
+    }
+
+    async fn pop(&self) -> Rc<RefCell<Box<dyn Msg>>> {
+        loop {
+            let msg = self.msgs.borrow_mut().pop_front();
+            if let Some(msg) = msg {
+                return msg;
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
 pub(crate) struct Server<> {
     nodeid: Rc<Id>,
     // started: SystemTime,
@@ -40,10 +78,15 @@ pub(crate) struct Server<> {
     last_reachable_check: SystemTime,
 
     calls: Rc<RefCell<HashMap<i32, Rc<RefCell<RpcCall>>>>>,
+    next_txid: i32,
 
-    queue4: Option<Rc<RefCell<LinkedList<Rc<RefCell<Box<dyn Msg>>>>>>>,
+    queue4: Option<Rc<MsgQueue>>,
+    queue6: Option<Rc<MsgQueue>>,
     scheduler:  Rc<RefCell<Scheduler>>,
-
+    stats: Rc<RefCell<Stats>>,
+    replay_window: Rc<RefCell<ReplayWindow>>,
+    events: Option<Arc<EventBus>>,
+    max_datagram_size: usize,
 }
 
 impl Server {
@@ -59,9 +102,15 @@ impl Server {
             last_reachable_check: SystemTime::UNIX_EPOCH,
 
             calls: Rc::new(RefCell::new(HashMap::new())),
-            queue4: Some(Rc::new(RefCell::new(LinkedList::new()))),
+            next_txid: unsafe { randombytes_uniform(u32::MAX) as i32 },
+            queue4: Some(Rc::new(MsgQueue::new())),
+            queue6: Some(Rc::new(MsgQueue::new())),
 
             scheduler: Rc::new(RefCell::new(Scheduler::new())),
+            stats: Rc::new(RefCell::new(Stats::new())),
+            replay_window: Rc::new(RefCell::new(ReplayWindow::new())),
+            events: None,
+            max_datagram_size: constants::MAX_DATAGRAM_SIZE,
         }
     }
 
@@ -69,6 +118,26 @@ impl Server {
         self.scheduler.clone()
     }
 
+    pub(crate) fn stats(&self) -> Rc<RefCell<Stats>> {
+        self.stats.clone()
+    }
+
+    pub(crate) fn replay_window(&self) -> Rc<RefCell<ReplayWindow>> {
+        self.replay_window.clone()
+    }
+
+    pub(crate) fn set_events(&mut self, events: Arc<EventBus>) {
+        self.events = Some(events);
+    }
+
+    pub(crate) fn set_max_datagram_size(&mut self, size: usize) {
+        self.max_datagram_size = size;
+    }
+
+    pub(crate) fn max_datagram_size(&self) -> usize {
+        self.max_datagram_size
+    }
+
     pub(crate) fn nodeid(&self) -> &Id {
         &self.nodeid
     }
@@ -93,37 +162,66 @@ impl Server {
     }
 
     pub(crate) fn update_reachability(&mut self) {
+        let reachable = self.reachable;
+
         // Avoid pinging too frequently if we're not receiving any response
         // (the connection might be dead)
         if self.recv_msg_num != self.recv_msg_num_at_last_reachable_check {
             self.reachable = true;
-            self.last_reachable_check = SystemTime::now();
+            self.last_reachable_check = clock::now();
             self.recv_msg_num_at_last_reachable_check = self.recv_msg_num;
-            return;
+        } else if as_millis!(self.last_reachable_check) >  constants::RPC_SERVER_REACHABILITY_TIMEOUT {
+            self.reachable = false;
         }
 
-        if as_millis!(self.last_reachable_check) >  constants::RPC_SERVER_REACHABILITY_TIMEOUT {
-            self.reachable = false;
+        if self.reachable != reachable {
+            if let Some(events) = self.events.as_ref() {
+                events.publish(NodeEvent::ReachabilityChanged(self.reachable));
+            }
         }
     }
 
     pub(crate) fn send_msg(&mut self, msg: Rc<RefCell<Box<dyn Msg>>>) {
         msg.borrow_mut().set_id(self.nodeid());
-        self.queue4.as_ref().unwrap().borrow_mut().push_back(msg);
+
+        let queue = match msg.borrow().remote_addr().is_ipv4() {
+            true => self.queue4.as_ref(),
+            false => self.queue6.as_ref(),
+        };
+        queue.unwrap().push(msg);
+    }
+
+    // The transaction ids go on from a random one, and are never zero,
+    // which is taken by the messages outside of any call.
+    fn next_txid(&mut self) -> i32 {
+        self.next_txid = self.next_txid.wrapping_add(1);
+        if self.next_txid == 0 {
+            self.next_txid += 1;
+        }
+        self.next_txid
     }
 
     pub(crate) fn send_call(&mut self, call: Rc<RefCell<RpcCall>>) {
-        let txid  = call.borrow().txid();
+        let txid  = self.next_txid();
+        call.borrow_mut().set_txid(txid);
         self.calls.borrow_mut().insert(txid, call.clone());
 
         let calls_cloned = self.calls.clone();
+        let stats_cloned = self.stats.clone();
         call.borrow_mut().set_responsed_fn(|_,_| {});
         call.borrow_mut().set_stalled_fn(|_| {});
         call.borrow_mut().set_timeout_fn(move |c| {
             if calls_cloned.borrow_mut().remove(&txid).is_some() {
+                if let Some(req) = c.req() {
+                    stats_cloned.borrow_mut().on_timeout_msg(req.borrow().as_ref());
+                }
                 c.dht().borrow_mut().on_timeout(c);
             };
         });
+        let calls_cloned = self.calls.clone();
+        call.borrow_mut().set_canceled_fn(move |_| {
+            calls_cloned.borrow_mut().remove(&txid);
+        });
 
         if let Some(msg) = call.borrow().req() {
             msg.borrow_mut().set_txid(txid);
@@ -133,116 +231,141 @@ impl Server {
     }
 }
 
-pub(crate) fn run_loop(
+pub(crate) async fn serve(
     runner: Rc<RefCell<NodeRunner>>,
     server: Rc<RefCell<Server>>,
     dht4: Option<Rc<RefCell<DHT>>>,
     dht6: Option<Rc<RefCell<DHT>>>,
-    quit: Arc<Mutex<bool>>) -> io::Result<()>
+    transport: TransportBinder,
+    quit: Arc<Mutex<bool>>,
+    started: &mut Option<StartedFn>) -> Result<(), Error>
 {
-    let rt = runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap();
-
-    rt.block_on(async move {
-        let mut sock4 = None;
-        let mut buff4 = None;
-        let mut queu4 = None;
-
-        if let Some(dht) = dht4.as_ref() {
-            sock4 = Some(UdpSocket::bind(dht.borrow().addr()).await?);
-            buff4 = Some(Rc::new(RefCell::new(vec![0u8; 1024])));
-            queu4 = server.borrow_mut().queue4.clone();
-        }
+    // One more byte to tell the oversized datagrams apart.
+    let max_datagram_size = server.borrow().max_datagram_size();
+    let mut sock4 = None;
+    let mut buff4 = None;
+    let mut queu4 = None;
+
+    if let Some(dht) = dht4.as_ref() {
+        sock4 = Some(bind_socket(&transport, dht.borrow().addr())?);
+        buff4 = Some(Rc::new(RefCell::new(vec![0u8; max_datagram_size + 1])));
+        queu4 = server.borrow_mut().queue4.clone();
+    }
 
-        let mut sock6 = None;
-        let mut buff6 = None;
-        let mut queu6 = None;
+    let mut sock6 = None;
+    let mut buff6 = None;
+    let mut queu6 = None;
 
-        if let Some(dht) = dht6.as_ref() {
-            sock6 = Some(UdpSocket::bind(dht.borrow().addr()).await?);
-            buff6 = Some(Rc::new(RefCell::new(vec![0u8; 1024])));
-            queu6 = None;
-        }
+    if let Some(dht) = dht6.as_ref() {
+        sock6 = Some(bind_socket(&transport, dht.borrow().addr())?);
+        buff6 = Some(Rc::new(RefCell::new(vec![0u8; max_datagram_size + 1])));
+        queu6 = server.borrow_mut().queue6.clone();
+    }
 
-        let mut interval = interval_at(
-            server.borrow().scheduler.borrow().next_timeout(),
-            Duration::from_secs(60*60)
-        );
+    let mut interval = interval_at(
+        server.borrow().scheduler.borrow().next_timeout(),
+        Duration::from_secs(60*60)
+    );
 
-        let mut running = true;
-        while running {
-            tokio::select! {
-                res = read_socket(sock4.as_ref(), buff4.as_ref(), server.clone(), |id, encrypted| {
-                    runner.borrow().decrypt_into(id, encrypted)
-                }), if sock4.is_some() => {
-                    match res {
-                        Ok(data) => {
-                            if let Some(msg) = data {
-                                dht4.as_ref().unwrap().borrow_mut().on_message(msg)
-                            }
-                        },
-                        Err(_) => {},
-                    }
-                }
+    if let Some(started) = started.take() {
+        started(Ok(()));
+    }
 
-                res = read_socket(sock6.as_ref(), buff6.as_ref(), server.clone(), |id, encrypted| {
-                    runner.borrow().decrypt_into(id, encrypted)
-                }), if sock6.is_some() => {
-                    match res {
-                        Ok(data) => {
-                            if let Some(msg) = data {
-                                dht6.as_ref().unwrap().borrow_mut().on_message(msg)
-                            }
-                        },
-                        Err(_) => {},
-                    }
+    let notifier = runner.borrow().notifier();
+    let clock_notifier = runner.borrow().clock_notifier();
+    let mut pending4 = None;
+    let mut pending6 = None;
+    let mut running = true;
+    while running {
+        tokio::select! {
+            _ = notifier.notified() => {
+                runner.borrow().handle_channels();
+            }
+
+            res = read_socket(sock4.as_deref(), buff4.as_ref(), server.clone(), |id, encrypted| {
+                runner.borrow().decrypt_into(id, encrypted)
+            }, |id, received, ver| {
+                runner.borrow().accept(id, received, ver)
+            }), if sock4.is_some() => {
+                match res {
+                    Ok(data) => {
+                        if let Some(msg) = data {
+                            dht4.as_ref().unwrap().borrow_mut().on_message(msg)
+                        }
+                    },
+                    Err(_) => {},
                 }
+            }
 
-                res = write_socket(sock4.as_ref(), queu4.as_ref(), dht4.as_ref(), |id, plain| {
-                    runner.borrow().encrypt_into(id, plain)
-                }), if sock4.is_some() => {
-                    match res {
-                        Ok(_) => {},
-                        Err(_) => {},
-                    }
+            res = read_socket(sock6.as_deref(), buff6.as_ref(), server.clone(), |id, encrypted| {
+                runner.borrow().decrypt_into(id, encrypted)
+            }, |id, received, ver| {
+                runner.borrow().accept(id, received, ver)
+            }), if sock6.is_some() => {
+                match res {
+                    Ok(data) => {
+                        if let Some(msg) = data {
+                            dht6.as_ref().unwrap().borrow_mut().on_message(msg)
+                        }
+                    },
+                    Err(_) => {},
                 }
+            }
 
-                res = write_socket(sock6.as_ref(), queu6.as_ref(), dht4.as_ref(), |id, plain| {
-                    runner.borrow().encrypt_into(id, plain)
-                }), if sock6.is_some() => {
-                    match res {
-                        Ok(_) => {},
-                        Err(_) => {},
-                    }
+            res = write_socket(sock4.as_deref(), queu4.as_ref(), dht4.as_ref(), &mut pending4, |id, plain| {
+                runner.borrow().encrypt_into(id, plain)
+            }), if sock4.is_some() => {
+                match res {
+                    Ok(_) => {},
+                    Err(_) => {},
                 }
+            }
 
-                _ = interval.tick() => {
-                    let scheduler = server.borrow().scheduler();
-                    scheduler::run_jobs(scheduler);
+            res = write_socket(sock6.as_deref(), queu6.as_ref(), dht6.as_ref(), &mut pending6, |id, plain| {
+                runner.borrow().encrypt_into(id, plain)
+            }), if sock6.is_some() => {
+                match res {
+                    Ok(_) => {},
+                    Err(_) => {},
                 }
             }
 
-            if *quit.lock().unwrap() {
-                running = false;
+            _ = interval.tick() => {
+                let scheduler = server.borrow().scheduler();
+                scheduler::run_jobs(scheduler);
             }
-            if server.borrow().scheduler.borrow().is_updated() {
+
+            _ = clock_notifier.notified() => {
                 interval.reset_at(server.borrow().scheduler.borrow().next_timeout());
             }
         }
 
-        Ok(())
+        if *quit.lock().unwrap() {
+            running = false;
+        }
+        if server.borrow().scheduler.borrow().is_updated() {
+            interval.reset_at(server.borrow().scheduler.borrow().next_timeout());
+        }
+    }
+
+    Ok(())
+}
+
+fn bind_socket(transport: &TransportBinder, addr: &SocketAddr) -> Result<Box<dyn Transport>, Error> {
+    transport(addr).map_err(|e| {
+        Error::Network(format!("Binding UDP socket on {} error: {}", addr, e))
     })
 }
 
-async fn read_socket<F>(
-    socket: Option<&UdpSocket>,
+async fn read_socket<F, V>(
+    socket: Option<&dyn Transport>,
     buffer: Option<&Rc<RefCell<Vec<u8>>>>,
     server: Rc<RefCell<Server>>,
-    mut decrypt: F
+    mut decrypt: F,
+    mut accept: V,
 ) -> Result<Option<Rc<RefCell<Box<dyn Msg>>>>, io::Error>
-where F: FnMut(&Id, &mut [u8]) -> Result<Vec<u8>, Error>
+where F: FnMut(&Id, &mut [u8]) -> Result<(Vec<u8>, Option<SystemTime>, Received), Error>,
+      V: FnMut(&Id, Received, i32)
 {
     let socket = match socket {
         Some(v) => v,
@@ -256,12 +379,33 @@ where F: FnMut(&Id, &mut [u8]) -> Result<Vec<u8>, Error>
 
     let mut buf = buf.borrow_mut();
     let (len, from) = socket.recv_from(&mut buf).await?;
-    let from_id = Id::try_from(&buf[0.. id::ID_BYTES]).unwrap();
+    let stats = server.borrow().stats();
+    stats.borrow_mut().on_received_bytes(len);
 
-    let plain = match decrypt(&from_id, &mut buf[id::ID_BYTES .. len]) {
+    if len <= id::ID_BYTES {
+        warn!("Got a truncated packet from {}, ignored it", from);
+        stats.borrow_mut().on_dropped_packet(len);
+        return Ok(None);
+    }
+    if len > server.borrow().max_datagram_size() {
+        warn!("Got an oversized packet from {}, ignored it", from);
+        stats.borrow_mut().on_dropped_packet(len);
+        return Ok(None);
+    }
+
+    let from_id = match Id::try_from(&buf[0.. id::ID_BYTES]) {
+        Ok(v) => v,
+        Err(_) => {
+            warn!("Got a packet with invalid sender id from {}, ignored it", from);
+            stats.borrow_mut().on_dropped_packet(len);
+            return Ok(None);
+        }
+    };
+    let (plain, timestamp, received) = match decrypt(&from_id, &mut buf[id::ID_BYTES .. len]) {
         Ok(v) => v,
         Err(err) => {
-            warn!("Decrypt packet from {} error {}, ignored it", err, from);
+            warn!("Decrypt packet from {} error {}, ignored it", from, err);
+            stats.borrow_mut().on_dropped_packet(len);
             return Ok(None);
         }
     };
@@ -270,10 +414,12 @@ where F: FnMut(&Id, &mut [u8]) -> Result<Vec<u8>, Error>
         Ok(msg) => msg,
         Err(err) => {
             warn!("Got a wrong packet from {} with {}, ignored it", from, err);
+            stats.borrow_mut().on_dropped_packet(len);
             return Ok(None);
         }
     };
 
+    stats.borrow_mut().on_received_msg(msg.borrow().as_ref());
     server.borrow_mut().recv_msg_num_incr();
 
     msg.borrow_mut().set_id(&from_id);
@@ -296,6 +442,18 @@ where F: FnMut(&Id, &mut [u8]) -> Result<Vec<u8>, Error>
         return Ok(None);
     }
 
+    // Reject replayed packets before they reach the DHT, or change
+    // anything about the crypto session with the sender.
+    let kind = msg.borrow().kind();
+    let txid = msg.borrow().txid();
+    let checked = server.borrow().replay_window().borrow_mut().check(&from_id, kind, txid, timestamp);
+    if let Err(err) = checked {
+        warn!("{} from {}, ignored it", err, from);
+        stats.borrow_mut().on_replayed_packet(len);
+        return Ok(None);
+    }
+    accept(&from_id, received, msg.borrow().ver());
+
     // Just respond to incoming request, no need to match them to pending requests
     if msg.borrow().kind() == msg::Kind::Request {
         return Ok(Some(msg));
@@ -354,11 +512,20 @@ where F: FnMut(&Id, &mut [u8]) -> Result<Vec<u8>, Error>
     Ok(None)
 }
 
+// The datagram popped from the queue and ready to go. It's kept by the
+// server loop rather than the write_socket future, so a send canceled by
+// another event is just sent again on the next round.
+struct Outgoing {
+    msg: Rc<RefCell<Box<dyn Msg>>>,
+    buf: Vec<u8>,
+}
+
 async fn write_socket<F>(
-    socket: Option<&UdpSocket>,
-    queue: Option<&Rc<RefCell<LinkedList<Rc<RefCell<Box<dyn Msg>>>>>>>,
+    socket: Option<&dyn Transport>,
+    queue: Option<&Rc<MsgQueue>>,
     dht: Option<&Rc<RefCell<DHT>>>,
-    mut encrypt: F) -> Result<(), io::Error>
+    pending: &mut Option<Outgoing>,
+    encrypt: F) -> Result<(), io::Error>
 where F: FnMut(&Id, &[u8]) -> Result<Vec<u8>, Error>
 {
     let socket = match socket {
@@ -376,38 +543,90 @@ where F: FnMut(&Id, &[u8]) -> Result<Vec<u8>, Error>
         None => return Ok(()),
     };
 
-    let msg = match queue.borrow_mut().pop_front() {
-        Some(v) => v,
-        None => {
-            sleep(Duration::from_millis(1000)).await;
-            return Ok(())
-        }
+    if pending.is_none() {
+        let msg = queue.pop().await;
+        *pending = match prepare_datagram(msg.clone(), &dht, encrypt) {
+            Some(buf) => Some(Outgoing { msg, buf }),
+            None => return Ok(()),
+        };
+    }
+
+    let outgoing = pending.as_ref().unwrap();
+    let addr = *outgoing.msg.borrow().remote_addr();
+    let res = socket.send_to(&outgoing.buf, &addr).await;
+    let outgoing = pending.take().unwrap();
+
+    match res {
+        Ok(_) => {
+            let stats = dht.borrow().server().borrow().stats();
+            stats.borrow_mut().on_sent_bytes(outgoing.buf.len());
+            stats.borrow_mut().on_sent_msg(outgoing.msg.borrow().as_ref());
+        },
+        Err(e) => warn!("Sending message failed {}", e),
     };
 
+    Ok(())
+}
+
+// Arms the timeout of the associated call and builds the datagram. Returns
+// None if the message is not going to be sent at all.
+fn prepare_datagram<F>(
+    msg: Rc<RefCell<Box<dyn Msg>>>,
+    dht: &Rc<RefCell<DHT>>,
+    mut encrypt: F) -> Option<Vec<u8>>
+where F: FnMut(&Id, &[u8]) -> Result<Vec<u8>, Error>
+{
+    // Drop the requests canceled before being sent out.
+    if let Some(call) = msg.borrow().associated_call() {
+        if call.borrow().is_canceled() {
+            return None;
+        }
+    }
+
     if let Some(call) = msg.borrow().associated_call() {
         let scheduler = dht.borrow().server().borrow().scheduler();
 
         dht.borrow_mut().on_send(call.borrow_mut().target_id());
+        if !call.borrow().has_timeout() {
+            let timeout = dht.borrow().rpc_timeout(call.borrow().target_id());
+            call.borrow_mut().set_timeout(timeout);
+        }
         call.borrow_mut().send(scheduler);
     }
 
-    let plain = serialize(msg.clone());
-    let encrypted = match encrypt(msg.borrow().remote_id(), &plain) {
+    // Trim the message if the datagram would exceed the limit, the size
+    // of the encryption is taken from the message at its full size.
+    let max_datagram_size = dht.borrow().server().borrow().max_datagram_size();
+    msg.borrow_mut().set_ver(version::ver());
+    let mut plain = serialize(msg.clone());
+    let mut encrypted = match encrypt(msg.borrow().remote_id(), &plain) {
         Ok(v) => v,
         Err(e) => {
             error!("Encrypting packet error {} for message {}", e, msg.borrow());
-            return Ok(())
+            return None
         },
     };
 
+    if id::ID_BYTES + encrypted.len() > max_datagram_size {
+        let overhead = id::ID_BYTES + encrypted.len() - plain.len();
+        plain = match serialize_within(msg.clone(), max_datagram_size.saturating_sub(overhead)) {
+            Some(v) => v,
+            None => {
+                warn!("Message {} exceeds the max datagram size {}, dropped it", msg.borrow(), max_datagram_size);
+                return None
+            }
+        };
+        encrypted = match encrypt(msg.borrow().remote_id(), &plain) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Encrypting packet error {} for message {}", e, msg.borrow());
+                return None
+            },
+        };
+    }
+
     let mut buf = Vec::new() as Vec<u8>;
     buf.extend_from_slice(msg.borrow().id().as_bytes());
     buf.extend_from_slice(&encrypted);
-
-    match socket.send_to(&buf, msg.borrow().remote_addr()).await {
-        Ok(_) => {},
-        Err(e) => warn!("Sending message failed {}", e),
-    };
-
-    Ok(())
+    Some(buf)
 }
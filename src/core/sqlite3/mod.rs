@@ -27,6 +27,7 @@ use crate::core::sqlite3::schema::peers::{
 };
 
 use diesel::prelude::*;
+use diesel::upsert::excluded;
 use diesel::result::Error;
 
 pub(crate) fn user_version(
@@ -92,8 +93,20 @@ pub(crate) fn put_value(
     v: NewValore
 ) -> Result<bool, Error> {
     use crate::core::sqlite3::schema::valores;
-    diesel::insert_into(valores::table)  // TOOD: Filter the existed one.
+    diesel::insert_into(valores::table)
         .values(&v)
+        .on_conflict(valores::id)
+        .do_update()
+        .set((
+            valores::publicKey.eq(excluded(valores::publicKey)),
+            valores::privateKey.eq(excluded(valores::privateKey)),
+            valores::recipient.eq(excluded(valores::recipient)),
+            valores::nonce.eq(excluded(valores::nonce)),
+            valores::signature.eq(excluded(valores::signature)),
+            valores::sequenceNumber.eq(excluded(valores::sequenceNumber)),
+            valores::data.eq(excluded(valores::data)),
+            valores::timestamp.eq(excluded(valores::timestamp)),
+        ))
         .execute(conn)
         .and_then(|num| Ok(num > 0))
 }
@@ -205,8 +218,19 @@ pub(crate) fn put_peer(
     v: NewPeer
 ) -> Result<bool, Error> {
     use crate::core::sqlite3::schema::peers;
-    diesel::insert_into(peers::table) // TOOD: Filter the existed one.
+    diesel::insert_into(peers::table)
         .values(&v)
+        .on_conflict((peers::id, peers::nodeId, peers::origin))
+        .do_update()
+        .set((
+            peers::persistent.eq(excluded(peers::persistent)),
+            peers::privateKey.eq(excluded(peers::privateKey)),
+            peers::port.eq(excluded(peers::port)),
+            peers::alternativeURL.eq(excluded(peers::alternativeURL)),
+            peers::signature.eq(excluded(peers::signature)),
+            peers::timestamp.eq(excluded(peers::timestamp)),
+            peers::announced.eq(excluded(peers::announced)),
+        ))
         .execute(conn)
         .and_then(|num| Ok(num > 0))
 }
@@ -10,6 +10,7 @@ use crate::{
 };
 
 use crate::core::{
+    clock,
     constants,
     value::PackBuilder as ValuePackBuilder,
     peer_info::PackBuilder as PeerPackBuilder,
@@ -387,7 +388,7 @@ impl DataStorage for SqliteStorage {
 
 #[inline(always)]
 fn millis_since_epoch() -> u128 {
-    SystemTime::now()
+    clock::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_millis()
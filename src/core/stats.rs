@@ -1,90 +1,252 @@
 use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::core::clock;
 use crate::core::msg::msg;
 
-pub(crate) struct Stats {
-    // TODO;
+pub use crate::core::msg::msg::{Method, Kind};
+
+const METHODS: usize = 7;
+const KINDS: usize = 3;
+
+const METHOD_LIST: [Method; METHODS] = [
+    Method::Unknown,
+    Method::Ping,
+    Method::FindNode,
+    Method::AnnouncePeer,
+    Method::FindPeer,
+    Method::StoreValue,
+    Method::FindValue,
+];
+
+const KIND_LIST: [Kind; KINDS] = [
+    Kind::Error,
+    Kind::Request,
+    Kind::Response,
+];
+
+// Counters of the traffic handled by the DHT server, a snapshot of which
+// can be acquired with Node::stats().
+#[derive(Clone)]
+pub struct Stats {
+    received_bytes: usize,
+    sent_bytes: usize,
+
+    // bytes counted within the last complete second.
+    received_bytes_in_sec: usize,
+    sent_bytes_in_sec: usize,
+
+    // bytes counted within the current second.
+    received_bytes_cur: usize,
+    sent_bytes_cur: usize,
+    window_start: SystemTime,
+
+    received_msgs: [[usize; KINDS]; METHODS],
+    sent_msgs: [[usize; KINDS]; METHODS],
+    timeout_msgs: [usize; METHODS],
+
+    dropped_packets: usize,
+    dropped_bytes: usize,
+    replayed_packets: usize,
+    rejected_entries: usize,
+}
+
+fn method_index(method: Method) -> usize {
+    method as usize
+}
+
+fn kind_index(kind: Kind) -> usize {
+    match kind {
+        Kind::Error => 0,
+        Kind::Request => 1,
+        Kind::Response => 2,
+    }
 }
 
 impl Stats {
     pub(crate) fn new() -> Self {
-        Stats {}
+        Stats {
+            received_bytes: 0,
+            sent_bytes: 0,
+            received_bytes_in_sec: 0,
+            sent_bytes_in_sec: 0,
+            received_bytes_cur: 0,
+            sent_bytes_cur: 0,
+            window_start: clock::now(),
+            received_msgs: [[0; KINDS]; METHODS],
+            sent_msgs: [[0; KINDS]; METHODS],
+            timeout_msgs: [0; METHODS],
+            dropped_packets: 0,
+            dropped_bytes: 0,
+            replayed_packets: 0,
+            rejected_entries: 0,
+        }
+    }
+
+    pub fn received_bytes(&self) -> usize {
+        self.received_bytes
+    }
+
+    pub fn sent_bytes(&self) -> usize {
+        self.sent_bytes
     }
 
-    pub(crate) fn received_bytes(&self) -> usize {
-        unimplemented!()
+    pub fn received_bytes_in_sec(&self) -> usize {
+        self.received_bytes_in_sec
     }
 
-    pub(crate) fn sent_bytes(&self) -> usize {
-        unimplemented!()
+    pub fn sent_bytes_in_sec(&self) -> usize {
+        self.sent_bytes_in_sec
     }
 
-    pub(crate) fn received_bytes_in_sec(&self) -> usize {
-        unimplemented!()
+    pub fn received_msgs(&self, method: Method, kind: Kind) -> usize {
+        self.received_msgs[method_index(method)][kind_index(kind)]
     }
 
-    pub(crate) fn sent_bytes_in_sec(&self) -> usize {
-        unimplemented!()
+    pub fn total_received_msgs(&self) -> usize {
+        self.received_msgs.iter().flatten().sum()
     }
 
-    pub(crate) fn received_msgs(&self, _: msg::Method, _: msg::Kind) -> usize {
-        unimplemented!()
+    pub fn sent_msgs(&self, method: Method, kind: Kind) -> usize {
+        self.sent_msgs[method_index(method)][kind_index(kind)]
     }
 
-    pub(crate) fn total_received_msgs(&self) -> usize {
-        unimplemented!()
+    pub fn total_sent_msgs(&self) -> usize {
+        self.sent_msgs.iter().flatten().sum()
     }
 
-    pub(crate) fn sent_msgs(&self, _: msg::Method, _: msg::Kind) -> usize {
-        unimplemented!()
+    pub fn timeout_msgs(&self, method: Method) -> usize {
+        self.timeout_msgs[method_index(method)]
     }
 
-    pub(crate) fn total_sent_msgs(&self) -> usize {
-        unimplemented!()
+    pub fn total_timeout_msgs(&self) -> usize {
+        self.timeout_msgs.iter().sum()
     }
 
-    pub(crate) fn timeout_msgs(&self, _: msg::Method) -> usize {
-        unimplemented!()
+    pub fn dropped_packets(&self) -> usize {
+        self.dropped_packets
+    }
+
+    pub fn dropped_bytes(&self) -> usize {
+        self.dropped_bytes
+    }
+
+    pub fn replayed_packets(&self) -> usize {
+        self.replayed_packets
+    }
+
+    // Nodes turned away from the routing table for the subnet limits.
+    pub fn rejected_entries(&self) -> usize {
+        self.rejected_entries
+    }
+
+    // Move the per-second window forward if the current second is over.
+    fn roll_window(&mut self) {
+        let elapsed = clock::elapsed(self.window_start);
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+
+        match elapsed < Duration::from_secs(2) {
+            true => {
+                self.received_bytes_in_sec = self.received_bytes_cur;
+                self.sent_bytes_in_sec = self.sent_bytes_cur;
+            },
+            false => {  // no traffic during the last complete second.
+                self.received_bytes_in_sec = 0;
+                self.sent_bytes_in_sec = 0;
+            }
+        }
+
+        self.received_bytes_cur = 0;
+        self.sent_bytes_cur = 0;
+        self.window_start = clock::now();
     }
 
-    pub(crate) fn total_timeout_msgs(&self) -> usize {
-        unimplemented!()
+    pub(crate) fn snapshot(&mut self) -> Stats {
+        self.roll_window();
+        self.clone()
     }
 
-    pub(crate) fn dropped_packets(&self) -> usize {
-        unimplemented!()
+    pub(crate) fn on_received_bytes(&mut self, bytes: usize) {
+        self.roll_window();
+        self.received_bytes += bytes;
+        self.received_bytes_cur += bytes;
     }
 
-    pub(crate) fn dropped_bytes(&self) -> usize {
-        unimplemented!()
+    pub(crate) fn on_sent_bytes(&mut self, bytes: usize) {
+        self.roll_window();
+        self.sent_bytes += bytes;
+        self.sent_bytes_cur += bytes;
     }
 
-    pub(crate) fn on_received_bytes(&mut self, _: usize) {
-        unimplemented!()
+    pub(crate) fn on_received_msg(&mut self, msg: &dyn msg::Msg) {
+        self.received_msgs[method_index(msg.method())][kind_index(msg.kind())] += 1;
     }
 
-    pub(crate) fn on_sent_bytes(&mut self, _: usize) {
-        unimplemented!()
+    pub(crate) fn on_sent_msg(&mut self, msg: &dyn msg::Msg) {
+        self.sent_msgs[method_index(msg.method())][kind_index(msg.kind())] += 1;
     }
 
-    pub(crate) fn on_received_msg(&mut self, _: &Box<dyn msg::Msg>) {
-        unimplemented!()
+    pub(crate) fn on_timeout_msg(&mut self, msg: &dyn msg::Msg) {
+        self.timeout_msgs[method_index(msg.method())] += 1;
     }
 
-    pub(crate) fn on_sent_msg(&mut self, _: &Box<dyn msg::Msg>) {
-        unimplemented!()
+    pub(crate) fn on_dropped_packet(&mut self, bytes: usize) {
+        self.dropped_packets += 1;
+        self.dropped_bytes += bytes;
     }
 
-    pub(crate) fn on_timeout_msg(&mut self, _: &Box<dyn msg::Msg>) {
-        unimplemented!()
+    // Replayed packets are counted as dropped ones as well.
+    pub(crate) fn on_replayed_packet(&mut self, bytes: usize) {
+        self.on_dropped_packet(bytes);
+        self.replayed_packets += 1;
     }
 
-    pub(crate) fn on_dropped_packet(&mut self, _: usize) {
-        unimplemented!()
+    pub(crate) fn on_rejected_entry(&mut self) {
+        self.rejected_entries += 1;
     }
 }
 
 impl fmt::Display for Stats {
-    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
-        unimplemented!()
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f,
+            "### Received: {} bytes ({} bytes/s), {} messages",
+            self.received_bytes,
+            self.received_bytes_in_sec,
+            self.total_received_msgs()
+        )?;
+        writeln!(f,
+            "### Sent: {} bytes ({} bytes/s), {} messages",
+            self.sent_bytes,
+            self.sent_bytes_in_sec,
+            self.total_sent_msgs()
+        )?;
+        writeln!(f,
+            "### Timeout: {} messages, Dropped: {} packets ({} bytes), Replayed: {} packets",
+            self.total_timeout_msgs(),
+            self.dropped_packets,
+            self.dropped_bytes,
+            self.replayed_packets
+        )?;
+        writeln!(f, "### Rejected: {} routing table entries", self.rejected_entries)?;
+
+        write!(f, "{:<16}", "")?;
+        for kind in KIND_LIST.iter() {
+            write!(f, "{:>12}{:>12}", format!("recv.{}", kind), format!("sent.{}", kind))?;
+        }
+        writeln!(f, "{:>12}", "timeout")?;
+
+        for method in METHOD_LIST.iter().skip(1) {
+            write!(f, "{:<16}", method.to_string())?;
+            for kind in KIND_LIST.iter() {
+                write!(f, "{:>12}{:>12}",
+                    self.received_msgs(*method, *kind),
+                    self.sent_msgs(*method, *kind)
+                )?;
+            }
+            writeln!(f, "{:>12}", self.timeout_msgs(*method))?;
+        }
+        Ok(())
     }
 }
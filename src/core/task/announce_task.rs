@@ -0,0 +1,47 @@
+use crate::Error;
+use crate::core::rpccall::RpcCall;
+use crate::core::msg::error_msg;
+
+// Outcome of an announcement to the closest nodes, as the number of nodes
+// accepted it and the first rejection.
+pub(crate) struct AnnounceTaskData {
+    accepted: usize,
+    rejected: Option<Error>,
+}
+
+impl AnnounceTaskData {
+    pub(crate) fn new() -> Self {
+        Self {
+            accepted: 0,
+            rejected: None,
+        }
+    }
+}
+
+pub(crate) trait AnnounceTask {
+    fn data(&self) -> &AnnounceTaskData;
+    fn data_mut(&mut self) -> &mut AnnounceTaskData;
+
+    // Fails with the rejection of a remote node only if no node accepted
+    // the announcement.
+    fn result(&self) -> Result<(), Error> {
+        match (self.data().accepted, self.data().rejected.as_ref()) {
+            (0, Some(err)) => Err(err.clone()),
+            _ => Ok(()),
+        }
+    }
+
+    fn call_accepted(&mut self) {
+        self.data_mut().accepted += 1;
+    }
+
+    fn call_rejected(&mut self, call: &RpcCall) {
+        if self.data().rejected.is_some() {
+            return;
+        }
+        self.data_mut().rejected = call.rsp().and_then(|rsp| {
+            let rsp = rsp.borrow();
+            rsp.as_any().downcast_ref::<error_msg::Message>().map(|v| v.to_error())
+        });
+    }
+}
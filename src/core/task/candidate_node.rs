@@ -1,5 +1,5 @@
 use std::rc::Rc;
-use std::time::SystemTime;
+use std::time::{SystemTime, Duration};
 
 use crate::{
     Id,
@@ -7,6 +7,7 @@ use crate::{
 };
 
 use crate::core::{
+    clock,
     node_info::Reachable,
 };
 
@@ -23,6 +24,12 @@ pub(crate) struct CandidateNode {
     pinged: i32,
 
     token: i32,
+
+    // lookup rounds taken to reach this node.
+    hops: usize,
+
+    // round-trip time measured on the node before, if ever.
+    rtt: Option<Duration>,
 }
 
 impl CandidateNode {
@@ -37,15 +44,35 @@ impl CandidateNode {
 
             pinged: 0,
             token: 0,
+            hops: 0,
+            rtt: None,
         }
     }
 
+    pub(crate) fn with_hops(mut self, hops: usize) -> Self {
+        self.hops = hops;
+        self
+    }
+
+    pub(crate) fn hops(&self) -> usize {
+        self.hops
+    }
+
+    pub(crate) fn with_rtt(mut self, rtt: Option<Duration>) -> Self {
+        self.rtt = rtt;
+        self
+    }
+
+    pub(crate) fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
     pub(crate) fn id(&self) -> &Id {
         self.ni.id()
     }
 
     pub(crate) fn set_sent(&mut self) {
-        self.last_sent = SystemTime::now();
+        self.last_sent = clock::now();
         self.pinged += 1;
     }
 
@@ -62,7 +89,7 @@ impl CandidateNode {
     }
 
     pub(crate) fn set_replied(&mut self) {
-        self.last_replied = SystemTime::now();
+        self.last_replied = clock::now();
     }
 
     pub(crate) fn set_token(&mut self, token: i32) {
@@ -2,6 +2,7 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use std::net::SocketAddr;
 use std::cmp::Ordering;
+use std::time::Duration;
 use std::vec::Vec;
 use std::collections::HashSet;
 use std::collections::HashMap;
@@ -40,15 +41,16 @@ impl ClosestCandidates {
         self.closest.len()
     }
 
+    // Whether the node has ever been taken as a candidate, even if removed.
+    pub(crate) fn knows(&self, node: &NodeInfo) -> bool {
+        self.dedup_ids.contains(node.id()) ||
+            self.dedup_addrs.contains(node.socket_addr())
+    }
+
+    // The removed nodes are kept in the dedup sets, otherwise they would be
+    // added back by the later responses and queried over and over again.
     pub(crate) fn remove(&mut self, target: &Id) -> Option<Rc<RefCell<CandidateNode>>> {
-        let removed = self.closest.remove(target);
-        if let Some(cn) = removed.as_ref() {
-            self.dedup_ids.remove(target);
-            self.dedup_addrs.remove(
-                cn.borrow().ni().socket_addr()
-            );
-        }
-        removed
+        self.closest.remove(target)
     }
 
    pub(crate) fn next(&mut self) -> Option<Rc<RefCell<CandidateNode>>> {
@@ -59,7 +61,7 @@ impl ClosestCandidates {
             }
         });
 
-        candidated.sort_by(|a,b| self.candidate_order(a,b));
+        candidated.sort_by(|a,b| self.next_order(a,b));
         candidated.pop()
     }
 
@@ -78,7 +80,11 @@ impl ClosestCandidates {
         }
     }*/
 
-    pub(crate) fn add(&mut self, candidates: &[Rc<NodeInfo>]) {
+    pub(crate) fn add(&mut self,
+        candidates: &[Rc<NodeInfo>],
+        hops: usize,
+        rtts: &HashMap<Id, Duration>)
+    {
         for item in candidates.iter() {
             if self.dedup_ids.contains(item.id()) {
                 continue;
@@ -95,7 +101,7 @@ impl ClosestCandidates {
                 Rc::new(RefCell::new(CandidateNode::new(
                     item.clone(),
                     false
-                )))
+                ).with_hops(hops).with_rtt(rtts.get(item.id()).cloned())))
             );
         }
 
@@ -126,6 +132,32 @@ impl ClosestCandidates {
         self.closest.shrink_to(self.capacity);
     }
 
+    // The reverse of the candidate order, as the next one is popped off the
+    // tail, except the nodes as far from the target as each other, by the
+    // bucket they fall into, go with the faster ones first. The nodes never
+    // measured are taken as the slowest.
+    fn next_order(&self,
+        a: &Rc<RefCell<CandidateNode>>,
+        b: &Rc<RefCell<CandidateNode>>) -> Ordering
+    {
+        let a = a.borrow();
+        let b = b.borrow();
+        let prefix_len = |id: &Id| {
+            let distance = distance(&self.target, id);
+            let zeros = distance.as_bytes().iter().take_while(|v| **v == 0).count();
+            match distance.as_bytes().get(zeros) {
+                Some(v) => zeros * 8 + v.leading_zeros() as usize,
+                None => zeros * 8,
+            }
+        };
+        let rtt = |cn: &CandidateNode| cn.rtt().unwrap_or(Duration::MAX);
+
+        b.pinged().cmp(&a.pinged())
+            .then_with(|| prefix_len(a.id()).cmp(&prefix_len(b.id())))
+            .then_with(|| rtt(&b).cmp(&rtt(&a)))
+            .then_with(|| self.target.three_way_compare(b.id(), a.id()))
+    }
+
     fn candidate_order(&self,
         a: &Rc<RefCell<CandidateNode>>,
         b: &Rc<RefCell<CandidateNode>>) -> Ordering
@@ -1,14 +1,15 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::time::Duration;
+use std::collections::HashMap;
 
 use crate::{
     Id,
-    NodeInfo
+    NodeInfo,
+    LookupOptions,
 };
 
 use crate::core::{
-    is_any_unicast,
-    is_bogon,
     constants,
     node_info::Reachable,
     rpccall::RpcCall,
@@ -25,24 +26,137 @@ use super::{
     candidate_node::CandidateNode,
 };
 
+// One of the disjoint paths taken towards the target, which never queries
+// the nodes known to the other paths, so that the adversarial nodes met on
+// one path can't lead the others astray (S/Kademlia).
+struct LookupPath {
+    candidates: ClosestCandidates,
+    closest: ClosestSet,
+}
+
+impl LookupPath {
+    fn new(target: Rc<Id>, width: usize) -> Self {
+        Self {
+            candidates: ClosestCandidates::new(target.clone(), 3 * width),
+            closest: ClosestSet::new(target, width),
+        }
+    }
+
+    fn is_done(&self, target: &Id) -> bool {
+        self.candidates.size() == 0 ||
+            (self.closest.is_eligible() &&
+                target.three_way_compare(&self.closest.tail(), &self.candidates.head()).is_le())
+    }
+}
+
 pub(crate) struct LookupTaskData {
     target: Rc<Id>,
-    closest_set: Rc<RefCell<ClosestSet>>,
-    closest_candidates: ClosestCandidates,
+    width: usize,
+    closest_set: Rc<RefCell<ClosestSet>>,  // merged from all the paths.
+    paths: Vec<LookupPath>,
+    next_path: usize,
+
+    hops: usize,    // rounds to reach the candidates being added.
+    path: Option<usize>,    // path of the candidates being added, or spread
+                            // over all the paths before any response.
+    max_hops: Option<usize>,
 }
 
 impl LookupTaskData {
     pub(crate) fn new(target: Rc<Id>) -> Self {
+        Self::with_width(target, constants::MAX_ENTRIES_PER_BUCKET, 1)
+    }
+
+    fn with_width(target: Rc<Id>, width: usize, paths: usize) -> Self {
         Self {
             target: target.clone(),
+            width,
             closest_set: Rc::new(RefCell::new(ClosestSet::new(
                 target.clone(),
-                constants::MAX_ENTRIES_PER_BUCKET
+                width
             ))),
-            closest_candidates: ClosestCandidates::new(
-                target, 3 * constants::MAX_ENTRIES_PER_BUCKET,
-            )
+            paths: (0..paths).map(|_| LookupPath::new(target.clone(), width)).collect(),
+            next_path: 0,
+            hops: 1,
+            path: None,
+            max_hops: None,
+        }
+    }
+
+    // Should be applied before the task gets started.
+    pub(crate) fn set_options(&mut self, options: &LookupOptions) {
+        *self = Self::with_width(self.target.clone(), options.width(), options.disjoint_paths());
+        self.max_hops = options.max_hops();
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn add_candidates(&mut self,
+        mut candidates: Vec<Rc<NodeInfo>>,
+        rtts: HashMap<Id, Duration>)
+    {
+        let hops = self.hops;
+        if let Some(path) = self.path {
+            // None of the nodes known to a path is taken by another one.
+            candidates.retain(|item| {
+                !self.paths.iter().enumerate().any(|(idx, v)| idx != path && v.candidates.knows(item))
+            });
+            self.paths[path].candidates.add(&candidates, hops, &rtts);
+            return;
+        }
+
+        // Deal the nodes out to the paths from the closest one, so that
+        // each path starts with the nodes as close as the others'. The
+        // same node may come twice, from the same id or address.
+        candidates.sort_by(|a, b| self.target.three_way_compare(a.id(), b.id()));
+        let count = self.paths.len();
+        let mut next = 0;
+        for item in candidates {
+            if self.paths.iter().any(|v| v.candidates.knows(&item)) {
+                continue;
+            }
+            self.paths[next % count].candidates.add(&[item], hops, &rtts);
+            next += 1;
+        }
+    }
+
+    // The paths take turns to make the requests.
+    pub(crate) fn next_candidate(&mut self) -> Option<Rc<RefCell<CandidateNode>>> {
+        let count = self.paths.len();
+        for _ in 0..count {
+            let idx = self.next_path;
+            self.next_path = (idx + 1) % count;
+            if let Some(next) = self.paths[idx].candidates.next() {
+                return Some(next);
+            }
         }
+        None
+    }
+
+    fn remove_candidate(&mut self, id: &Id) -> Option<(usize, Rc<RefCell<CandidateNode>>)> {
+        self.paths.iter_mut().enumerate().find_map(|(idx, path)| {
+            path.candidates.remove(id).map(|cn| (idx, cn))
+        })
+    }
+
+    // The nodes carried by the response are one more round away, and left
+    // to the path taking the responding node.
+    pub(crate) fn take_responsed(&mut self, id: &Id) -> Option<(usize, Rc<RefCell<CandidateNode>>)> {
+        let (path, cn) = self.remove_candidate(id)?;
+        self.hops = cn.borrow().hops() + 1;
+        self.path = Some(path);
+        Some((path, cn))
+    }
+
+    fn is_done(&self) -> bool {
+        self.paths.iter().all(|path| path.is_done(&self.target))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn paths_knowing(&self, node: &NodeInfo) -> usize {
+        self.paths.iter().filter(|v| v.candidates.knows(node)).count()
     }
 }
 
@@ -60,17 +174,16 @@ pub(crate) trait LookupTask {
     }
 
     fn add_candidates(&mut self, nodes: &[Rc<NodeInfo>]) {
+        let hops = self.data().hops;
+        if self.data().max_hops.is_some_and(|max| hops > max) {
+            return;
+        }
+
         let mut candidates = Vec::new();
         let dht = self.dht();
 
         for item in nodes.iter() {
-            let bogon = if cfg!(feature = "devp") {
-                !is_any_unicast(&item.ip())
-            } else {
-                is_bogon(&&item.socket_addr())
-            };
-
-            if bogon || dht.borrow().id() == item.id() ||
+            if dht.borrow().is_bogon(item.socket_addr()) || dht.borrow().id() == item.id() ||
                 dht.borrow().addr() == item.socket_addr() ||
                 self.data().closest_set.borrow().contains(item.id()) {
                 continue;
@@ -79,35 +192,37 @@ pub(crate) trait LookupTask {
         }
 
         if !candidates.is_empty() {
-            self.data_mut().closest_candidates.add(&candidates)
+            // The faster nodes are queried first among the ones as close.
+            let rtts = candidates.iter().filter_map(|v| {
+                dht.borrow().round_trip_time(v.id()).map(|rtt| (v.id().clone(), rtt))
+            }).collect();
+            self.data_mut().add_candidates(candidates, rtts)
         }
     }
 
     fn remove_candidate(&mut self, id: &Id) -> Option<Rc<RefCell<CandidateNode>>> {
-        self.data_mut().closest_candidates.remove(id)
+        self.data_mut().remove_candidate(id).map(|(_, cn)| cn)
     }
 
     fn next_candidate(&mut self) -> Option<Rc<RefCell<CandidateNode>>> {
-       self.data_mut().closest_candidates.next()
+       self.data_mut().next_candidate()
     }
 
-    fn add_closest(&mut self, candidate_node: Rc<RefCell<CandidateNode>>) {
-        self.data_mut().closest_set.borrow_mut().add(candidate_node)
+    fn add_closest(&mut self, path: usize, candidate_node: Rc<RefCell<CandidateNode>>) {
+        let data = self.data_mut();
+        data.paths[path].closest.add(candidate_node.clone());
+        data.closest_set.borrow_mut().add(candidate_node)
     }
 
     fn is_done(&self) -> bool {
-        let data = self.data();
-        data.closest_candidates.size() == 0 ||
-            (data.closest_set.borrow().is_eligible() &&
-                data.target.three_way_compare(
-                    &data.closest_set.borrow().tail(), &data.closest_candidates.head()).is_le())
+        self.data().is_done()
     }
 
     fn call_responsed(&mut self, call: &RpcCall, msg: &dyn LookupResponse) {
-        if let Some(cn) = self.remove_candidate(call.target_id()) {
+        if let Some((path, cn)) = self.data_mut().take_responsed(call.target_id()) {
             cn.borrow_mut().set_replied();
             cn.borrow_mut().set_token(msg.token());
-            self.add_closest(cn);
+            self.add_closest(path, cn);
         }
     }
 
@@ -1,4 +1,4 @@
-mod closest_candidates;
+pub(crate) mod closest_candidates;
 mod candidate_node;
 mod closest_set;
 
@@ -6,6 +6,7 @@ pub(crate) mod task_manager;
 
 pub(crate) mod task;
 pub(crate) mod lookup_task;
+pub(crate) mod announce_task;
 
 pub(crate) mod ping_refresh;
 pub(crate) mod node_lookup;
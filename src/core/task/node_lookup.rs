@@ -5,13 +5,13 @@ use log::error;
 
 use crate::{
     Id,
+    LookupOptions,
     id::MAX_ID,
     Network,
     NodeInfo
 };
 
 use crate::core::{
-    constants,
     rpccall::RpcCall,
     dht::DHT,
     kclosest_nodes::KClosestNodes,
@@ -52,6 +52,11 @@ impl NodeLookupTask {
         }
     }
 
+    pub(crate) fn with_options(&mut self, options: &LookupOptions) {
+        self.base_data.set_options(options);
+        self.lookup_data.set_options(options);
+    }
+
     pub(crate) fn set_bootstrap(&mut self, bootstrap: bool) {
         self.bootstrap = bootstrap
     }
@@ -122,7 +127,7 @@ impl Task for NodeLookupTask {
                 target,
                 Task::data(self).dht().borrow().ni(),
                 Task::data(self).dht().borrow().rt(),
-                constants::MAX_ENTRIES_PER_BUCKET *2,
+                2 * LookupTask::data(self).width(),
                 move |e| e.borrow().is_eligible_for_nodes_list()
             );
             kns.fill(false);
@@ -142,8 +147,8 @@ impl Task for NodeLookupTask {
             let msg = Rc::new(RefCell::new({
                 let mut msg = Box::new(req::Message::new());
                 msg.with_target(self.target());
-                msg.with_want4(true);
-                msg.with_want6(false);
+                msg.with_want4(self.dht().borrow().network() == Network::IPv4);
+                msg.with_want6(self.dht().borrow().network() == Network::IPv6);
                 msg.with_want_token(self.want_token);
                 msg as Box<dyn Msg>
             }));
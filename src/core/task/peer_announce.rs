@@ -4,8 +4,9 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use log::error;
 
-use crate::PeerInfo;
+use crate::{PeerInfo, LookupOptions};
 use crate::core::dht::DHT;
+use crate::core::rpccall::RpcCall;
 use crate::core::msg::{
     msg::Msg,
     announce_peer_req as req,
@@ -13,6 +14,7 @@ use crate::core::msg::{
 
 use super::{
     closest_set::ClosestSet,
+    announce_task::{AnnounceTask, AnnounceTaskData},
     candidate_node::CandidateNode,
     task::{Task, TaskData},
 };
@@ -24,6 +26,8 @@ pub(crate) struct PeerAnnounceTask {
 
     todo: Rc<RefCell<LinkedList<Rc<RefCell<CandidateNode>>>>>,
     peer: Rc<PeerInfo>,
+
+    announce_data: AnnounceTaskData,
 }
 
 impl PeerAnnounceTask {
@@ -43,8 +47,13 @@ impl PeerAnnounceTask {
             listeners: Vec::new(),
             peer,
             todo: Rc::new(RefCell::new(todo)),
+            announce_data: AnnounceTaskData::new(),
         }
     }
+
+    pub(crate) fn with_options(&mut self, options: &LookupOptions) {
+        self.base_data.set_options(options);
+    }
 }
 
 impl Task for PeerAnnounceTask {
@@ -70,6 +79,14 @@ impl Task for PeerAnnounceTask {
         }
     }
 
+    fn call_responsed(&mut self, _: &RpcCall, _: Rc<RefCell<Box<dyn Msg>>>) {
+        self.call_accepted();
+    }
+
+    fn call_error(&mut self, call: &RpcCall) {
+        self.call_rejected(call);
+    }
+
     fn update(&mut self) {
         while self.can_request() {
             let cn = match self.todo.borrow().front() {
@@ -95,3 +112,13 @@ impl Task for PeerAnnounceTask {
         }
     }
 }
+
+impl AnnounceTask for PeerAnnounceTask {
+    fn data(&self) -> &AnnounceTaskData {
+        &self.announce_data
+    }
+
+    fn data_mut(&mut self) -> &mut AnnounceTaskData {
+        &mut self.announce_data
+    }
+}
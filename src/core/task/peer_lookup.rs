@@ -5,12 +5,12 @@ use log::error;
 
 use crate::{
     Id,
+    LookupOptions,
     Network,
     PeerInfo
 };
 
 use crate::core::{
-    constants,
     dht::DHT,
     rpccall::RpcCall,
     kclosest_nodes::KClosestNodes,
@@ -47,6 +47,11 @@ impl PeerLookupTask {
         }
     }
 
+    pub(crate) fn with_options(&mut self, options: &LookupOptions) {
+        self.base_data.set_options(options);
+        self.lookup_data.set_options(options);
+    }
+
     pub(crate) fn set_result_fn<F>(&mut self, f: F)
     where F: FnMut(Rc<RefCell<Box<dyn Task>>>, Vec<PeerInfo>) + 'static
     {
@@ -98,7 +103,7 @@ impl Task for PeerLookupTask {
                 LookupTask::target(self),
                 Task::data(self).dht().borrow().ni(),
                 Task::data(self).dht().borrow().rt(),
-                constants::MAX_ENTRIES_PER_BUCKET *2,
+                2 * LookupTask::data(self).width(),
                 move |_| true
             );
             kns.fill(false);
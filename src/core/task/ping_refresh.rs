@@ -7,6 +7,7 @@ use log::{error, debug};
 use crate::core::{
     kbucket::KBucket,
     kbucket_entry::KBucketEntry,
+    node_info::Reachable,
     rpccall::RpcCall,
     dht::DHT,
 };
@@ -35,7 +36,7 @@ pub(crate) struct PingRefreshTask {
     todo: Rc<RefCell<LinkedList<Rc<RefCell<KBucketEntry>>>>>,
 
     check_all: bool,
-    _probe_cache: bool,
+    probe_cache: bool,
     remove_on_timeout: bool,
 }
 
@@ -51,7 +52,7 @@ impl PingRefreshTask {
             todo: Rc::new(RefCell::new(LinkedList::new())),
 
             check_all:          option == PingOption::CheckAll,
-            _probe_cache:       option == PingOption::ProbeCache,
+            probe_cache:        option == PingOption::ProbeCache,
             remove_on_timeout:  option == PingOption::RemoveOnTimeout
         };
         task.sync_from_bucket();
@@ -64,7 +65,17 @@ impl PingRefreshTask {
             if entry.borrow().needs_ping() || self.check_all || self.remove_on_timeout {
                 self.todo.borrow_mut().push_back(entry.clone())
             }
-        })
+        });
+
+        // Verify the latest unverified candidate, so that it could be
+        // promoted once an entry gets removed.
+        if self.probe_cache {
+            let candidate = self.bucket.borrow().cache_entries().into_iter().rev()
+                .find(|entry| !entry.borrow().reachable());
+            if let Some(candidate) = candidate {
+                self.todo.borrow_mut().push_back(candidate);
+            }
+        }
     }
 }
 
@@ -1,18 +1,21 @@
 use std::fmt;
 use std::any::Any;
 use std::rc::Rc;
-use std::cell::RefCell;
-use std::time::SystemTime;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, SystemTime};
 use std::collections::HashMap;
 use log::debug;
 
 use crate::{
     addr_family,
     NodeInfo,
+    LookupOptions,
     Error
 };
 
 use crate::core::{
+    clock,
+    constants,
     rpccall::{RpcCall, State as CallState},
     dht::DHT,
     msg::msg::Msg,
@@ -40,16 +43,23 @@ impl fmt::Display for State {
 }
 
 pub(crate) type TaskId = i32;
-static mut NEXT_TASKID: TaskId= 0;
+
+// Tasks are only created on the thread running the node that owns them, and
+// the nodes sharing a thread draw from the same sequence, which keeps the ids
+// apart from the other nodes in the same process.
+thread_local! {
+    static NEXT_TASKID: Cell<TaskId> = const { Cell::new(0) };
+}
 
 fn next_taskid() -> TaskId {
-    unsafe {
-        NEXT_TASKID += 1;
-        if NEXT_TASKID == 0 {
-            NEXT_TASKID += 1;
+    NEXT_TASKID.with(|v| {
+        let mut id = v.get().wrapping_add(1);
+        if id == 0 {
+            id += 1;
         }
-        NEXT_TASKID
-    }
+        v.set(id);
+        id
+    })
 }
 
 pub(crate) struct TaskData {
@@ -61,6 +71,8 @@ pub(crate) struct TaskData {
     finished_time: SystemTime,
 
     inflights: HashMap<TaskId, Rc<RefCell<RpcCall>>>,
+    alpha: usize,           // maximum number of calls in flight.
+    rpc_timeout: Option<Duration>,    // derived per call if not given.
 
     nested: Option<Rc<RefCell<Box<dyn Task>>>>,
     cloned: Option<Rc<RefCell<Box<dyn Task>>>>,
@@ -79,6 +91,8 @@ impl TaskData {
             finished_time: SystemTime::UNIX_EPOCH,
 
             inflights: HashMap::new(),
+            alpha: constants::LOOKUP_CONCURRENCY,
+            rpc_timeout: None,
 
             nested: None,
             cloned: None,
@@ -90,6 +104,11 @@ impl TaskData {
         self.dht.clone()
     }
 
+    pub(crate) fn set_options(&mut self, options: &LookupOptions) {
+        self.alpha = options.alpha();
+        self.rpc_timeout = options.rpc_timeout();
+    }
+
     pub(crate) fn task(&self) -> Rc<RefCell<Box<dyn Task>>> {
         self.cloned.as_ref().unwrap().clone()
     }
@@ -162,14 +181,14 @@ pub(crate) trait Task {
 
     fn start(&mut self) {
         if self.set_state(&[State::Queued], State::Running) {
-            self.data_mut().started_time = SystemTime::now();
+            self.data_mut().started_time = clock::now();
             self.prepare();
             self.update();
         }
 
         if self.is_done() &&
             self.set_state(&[State::Running], State::Finished) {
-            self.data_mut().finished_time = SystemTime::now();
+            self.data_mut().finished_time = clock::now();
             self.notify_completion();
         }
     }
@@ -182,7 +201,15 @@ pub(crate) trait Task {
         ];
 
         if self.set_state(&expected, State::Canceled) {
-            self.data_mut().finished_time = SystemTime::now();
+            self.data_mut().finished_time = clock::now();
+
+            // Nobody is interested in the responses to the calls in flight,
+            // except the one being handled, which is borrowed right now.
+            for (_, call) in self.data_mut().inflights.drain() {
+                if let Ok(mut call) = call.try_borrow_mut() {
+                    call.cancel();
+                }
+            }
             self.notify_completion();
         }
 
@@ -198,7 +225,7 @@ pub(crate) trait Task {
             State::Running
         ];
         if self.set_state(&expected, State::Finished) {
-            self.data_mut().finished_time = SystemTime::now();
+            self.data_mut().finished_time = clock::now();
             //debug!("Task finished: {}", self);
             self.notify_completion();
         }
@@ -217,7 +244,7 @@ pub(crate) trait Task {
     }
 
     fn can_request(&self) -> bool {
-        self.data().inflights.len() < 10 && !self.is_finished()
+        self.data().inflights.len() < self.data().alpha && !self.is_finished()
     }
 
     fn send_call(&mut self,
@@ -237,6 +264,9 @@ pub(crate) trait Task {
         let task = self.data().task();
 
         call.borrow_mut().set_cloned(call.clone());
+        if let Some(timeout) = self.data().rpc_timeout {
+            call.borrow_mut().set_timeout(timeout);
+        }
         call.borrow_mut().set_state_changed_fn (move|c, _, cur| {
             let mut task = task.borrow_mut();
             let mut update_needed = true;
@@ -270,13 +300,17 @@ pub(crate) trait Task {
                 _ => {}
             }
 
+            // Keep going with the next candidates in place of the finished call.
+            let finished = matches!(cur, CallState::Responsed | CallState::Err | CallState::Timeout);
+            if finished && !task.is_finished() {
+                task.update();
+            }
             if update_needed && task.is_done() {
                 task.finish();
             }
         });
 
         (cb)(call.clone());
-        self.data_mut().inflights.insert(call.borrow().txid(), call.clone());
 
         debug!("Task#{} sending call to {}{}",
             self.taskid(),
@@ -284,10 +318,13 @@ pub(crate) trait Task {
             self.data().dht.borrow().addr()
         );
 
+        // The txid is assigned by the server.
         self.data().dht.borrow()
             .server()
             .borrow_mut()
-            .send_call(call);
+            .send_call(call.clone());
+        let txid = call.borrow().txid();
+        self.data_mut().inflights.insert(txid, call);
 
         Ok(())
     }
@@ -4,6 +4,7 @@ use std::cell::RefCell;
 
 use super::task::{
     Task,
+    TaskId,
     State
 };
 
@@ -13,6 +14,8 @@ pub(crate) struct TaskManager {
     queued:     LinkedList<Rc<RefCell<Box<dyn Task>>>>,
     running:    LinkedList<Rc<RefCell<Box<dyn Task>>>>,
     canceling: bool,
+
+    wakeup_fn: Box<dyn Fn()>,
 }
 
 impl TaskManager {
@@ -21,9 +24,17 @@ impl TaskManager {
             queued : LinkedList::new(),
             running: LinkedList::new(),
             canceling: false,
+            wakeup_fn: Box::new(|| {}),
         }
     }
 
+    // The wakeup function is invoked whenever a new task gets queued, and
+    // is expected to arrange a dequeue() as soon as possible.
+    pub(crate) fn set_wakeup_fn<F>(&mut self, f: F)
+    where F: Fn() + 'static {
+        self.wakeup_fn = Box::new(f);
+    }
+
     pub(crate) fn add(&mut self, task: Rc<RefCell<Box<dyn Task>>>) {
         self.add_prior(task, false)
     }
@@ -50,6 +61,7 @@ impl TaskManager {
             true => self.queued.push_front(task),
             false => self.queued.push_back(task),
         }
+        (self.wakeup_fn)();
     }
 
     // Check whether it's able to dequeue a runnable job from queue.
@@ -61,6 +73,12 @@ impl TaskManager {
     }
 
     pub(crate) fn dequeue(&mut self) {
+        // Release the slots taken by the tasks already done.
+        self.running = std::mem::take(&mut self.running)
+            .into_iter()
+            .filter(|t| !t.borrow().is_finished())
+            .collect();
+
         while self.can_dequeue() {
             let task = self.queued.pop_front().unwrap();
             if task.borrow().is_finished() {
@@ -71,12 +89,31 @@ impl TaskManager {
             }
 
             task.borrow_mut().start();
-            if !task.borrow().is_finished() { // TODO: how can we tackle the jobs on running queue.
+            if !task.borrow().is_finished() {
                 self.running.push_back(task);
             }
         }
     }
 
+    pub(crate) fn cancel(&mut self, taskid: TaskId) {
+        let matched = |t: &Rc<RefCell<Box<dyn Task>>>| t.borrow().taskid() == taskid;
+        let task = self.running.iter().find(|t| matched(t))
+            .or_else(|| self.queued.iter().find(|t| matched(t)))
+            .cloned();
+
+        if let Some(task) = task {
+            self.running = std::mem::take(&mut self.running)
+                .into_iter()
+                .filter(|t| !matched(t))
+                .collect();
+            self.queued = std::mem::take(&mut self.queued)
+                .into_iter()
+                .filter(|t| !matched(t))
+                .collect();
+            task.borrow_mut().cancel();
+        }
+    }
+
     pub(crate) fn cancel_all(&mut self) {
         self.canceling = true;
         while let Some(t) = self.running.pop_front() {
@@ -4,8 +4,9 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use log::error;
 
-use crate::Value;
+use crate::{Value, LookupOptions};
 use crate::core::dht::DHT;
+use crate::core::rpccall::RpcCall;
 use crate::core::msg::{
     store_value_req as req,
     msg::Msg
@@ -14,6 +15,7 @@ use crate::core::msg::{
 use super::{
     task::{Task, TaskData},
     closest_set::ClosestSet,
+    announce_task::{AnnounceTask, AnnounceTaskData},
     candidate_node::CandidateNode,
 };
 
@@ -24,6 +26,8 @@ pub(crate) struct ValueAnnounceTask {
 
     todo: Rc<RefCell<LinkedList<Rc<RefCell<CandidateNode>>>>>,
     value: Rc<Value>,
+
+    announce_data: AnnounceTaskData,
 }
 
 impl ValueAnnounceTask {
@@ -43,8 +47,13 @@ impl ValueAnnounceTask {
             listeners: Vec::new(),
             todo: Rc::new(RefCell::new(todo)),
             value,
+            announce_data: AnnounceTaskData::new(),
         }
     }
+
+    pub(crate) fn with_options(&mut self, options: &LookupOptions) {
+        self.base_data.set_options(options);
+    }
 }
 
 impl Task for ValueAnnounceTask {
@@ -70,6 +79,14 @@ impl Task for ValueAnnounceTask {
         }
     }
 
+    fn call_responsed(&mut self, _: &RpcCall, _: Rc<RefCell<Box<dyn Msg>>>) {
+        self.call_accepted();
+    }
+
+    fn call_error(&mut self, call: &RpcCall) {
+        self.call_rejected(call);
+    }
+
     fn update(&mut self) {
         while self.can_request() {
             let cn = match self.todo.borrow().front() {
@@ -95,3 +112,13 @@ impl Task for ValueAnnounceTask {
         }
     }
 }
+
+impl AnnounceTask for ValueAnnounceTask {
+    fn data(&self) -> &AnnounceTaskData {
+        &self.announce_data
+    }
+
+    fn data_mut(&mut self) -> &mut AnnounceTaskData {
+        &mut self.announce_data
+    }
+}
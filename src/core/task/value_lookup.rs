@@ -5,12 +5,12 @@ use log::{warn, error};
 
 use crate::{
     Id,
+    LookupOptions,
     Value,
     Network
 };
 
 use crate::core::{
-    constants,
     dht::DHT,
     rpccall::RpcCall,
     kclosest_nodes::KClosestNodes,
@@ -49,6 +49,11 @@ impl ValueLookupTask {
         }
     }
 
+    pub(crate) fn with_options(&mut self, options: &LookupOptions) {
+        self.base_data.set_options(options);
+        self.lookup_data.set_options(options);
+    }
+
     pub(crate) fn set_result_fn<F>(&mut self, f: F)
     where F: FnMut(Rc<RefCell<Box<dyn Task>>>, Option<Rc<Value>>) + 'static,
     {
@@ -103,7 +108,7 @@ impl Task for ValueLookupTask {
                 LookupTask::target(self),
                 Task::data(self).dht().borrow().ni(),
                 Task::data(self).dht().borrow().rt(),
-                constants::MAX_ENTRIES_PER_BUCKET *2,
+                2 * LookupTask::data(self).width(),
                 move |_| true
             );
             kns.fill(false);
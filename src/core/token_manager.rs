@@ -8,6 +8,7 @@ use crate::{
     randomize_bytes,
     Id,
 };
+use crate::core::clock;
 
 const TOKEN_TIMEOUT: u128 = 5 * 60 * 1000; // 5 minutes
 
@@ -24,15 +25,15 @@ impl TokenManager {
 
         TokenManager {
             session_secret: seed,
-            timestamp: RefCell::new(SystemTime::now()),
-            previous_timestamp: RefCell::new(SystemTime::now()),
+            timestamp: RefCell::new(clock::now()),
+            previous_timestamp: RefCell::new(clock::now()),
         }
     }
 
     fn update_token_timestamp(&self) {
-        if self.timestamp.borrow().elapsed().unwrap().as_millis() > TOKEN_TIMEOUT {
+        if clock::elapsed(*self.timestamp.borrow()).as_millis() > TOKEN_TIMEOUT {
             *self.previous_timestamp.borrow_mut() = *self.timestamp.borrow();
-            *self.timestamp.borrow_mut() = SystemTime::now();
+            *self.timestamp.borrow_mut() = clock::now();
         }
     }
 
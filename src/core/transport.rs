@@ -0,0 +1,57 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + 'a>>;
+
+// Datagram transport underneath the DHT server. The futures returned are
+// dropped whenever another event wins the race in the server loop, so
+// recv_from must not lose any datagram when being canceled. A canceled
+// send_to is called again with the same datagram, it must not have been
+// sent out unless the future completes.
+pub trait Transport {
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: &'a SocketAddr) -> TransportFuture<'a, usize>;
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)>;
+}
+
+// Creates the transport for each address the node listens on. It's called
+// on the working thread of the node, within the context of its runtime.
+pub type TransportBinder = Arc<dyn Fn(&SocketAddr) -> io::Result<Box<dyn Transport>> + Send + Sync>;
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn bind(addr: &SocketAddr) -> io::Result<Self> {
+        let socket = std::net::UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket: UdpSocket::from_std(socket)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub(crate) fn binder() -> TransportBinder {
+        Arc::new(|addr| {
+            Ok(Box::new(UdpTransport::bind(addr)?) as Box<dyn Transport>)
+        })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: &'a SocketAddr) -> TransportFuture<'a, usize> {
+        Box::pin(self.socket.send_to(buf, target))
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(self.socket.recv_from(buf))
+    }
+}
@@ -187,6 +187,21 @@ impl PackBuilder {
         assert!(self.data.len() > 0);
         Value::packed(self)
     }
+
+    // Build the value decoded from a message, or nothing if the
+    // parts received do not make up a well-formed value.
+    pub(crate) fn try_build(self) -> Option<Value> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if self.pk.is_some() && (self.nonce.is_none() || self.sig.is_none()) {
+            return None;
+        }
+        if self.rec.is_some() && self.pk.is_none() {
+            return None;
+        }
+        Some(Value::packed(self))
+    }
 }
 
 impl Value {
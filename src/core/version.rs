@@ -2,7 +2,10 @@ use std::collections::HashMap;
 use once_cell::sync::Lazy;
 
 pub(crate) const NODE_TAG_NAME: &str = "MK";
-pub(crate) const NODE_VERSION: i32 = 1;
+pub(crate) const NODE_VERSION: i32 = 2;
+
+// The first node version reading the versioned packet format.
+const VERSIONED_PACKET_SINCE: i32 = 2;
 
 static NAMES: Lazy<HashMap<String, String>> = Lazy::new(|| {
     let mut map = HashMap::new();
@@ -22,6 +25,15 @@ pub(crate) fn build(short_name: &str, ver: i32) -> i32 {
     (bytes[1] as u32) << 16 | (ver as u32) & 0x000000FF) as i32
 }
 
+// Whether a peer of the given version reads the versioned packet format.
+pub(crate) fn supports_versioned_packet(ver: i32) -> bool {
+    let tag = NODE_TAG_NAME.as_bytes();
+    let ver = ver as u32;
+    (ver >> 24) as u8 == tag[0] &&
+        ((ver & 0x00FF0000) >> 16) as u8 == tag[1] &&
+        (ver & 0x0000FFFF) as i32 >= VERSIONED_PACKET_SINCE
+}
+
 pub(crate) fn canonical_version(ver: i32) -> String {
     let ver = ver as u32;
     if ver == 0 {
@@ -0,0 +1,50 @@
+// Entry points for the fuzz targets under fuzz/, only built with the
+// "fuzzing" feature. Each one decodes arbitrary input and, on success,
+// prints and encodes the result again, which must never panic.
+
+use ciborium::Value as CVal;
+
+use crate::core::{
+    cbor,
+    msg,
+    kbucket_entry::KBucketEntry,
+};
+use crate::{
+    Id,
+    NodeInfo,
+    PeerInfo,
+};
+
+fn read_cbor(data: &[u8]) -> Option<CVal> {
+    ciborium::de::from_reader(cbor::Reader::new(data)).ok()
+}
+
+pub fn deser(data: &[u8]) {
+    if let Ok(msg) = msg::deser(data) {
+        // as the server does for every received message.
+        msg.borrow_mut().set_id(&Id::random());
+        _ = msg.borrow().to_string();
+        _ = msg::serialize(msg);
+    }
+}
+
+pub fn node_info(data: &[u8]) {
+    if let Some(ni) = read_cbor(data).as_ref().and_then(NodeInfo::from_cbor) {
+        _ = ni.to_string();
+        _ = ni.to_cbor();
+    }
+}
+
+pub fn peer_info(data: &[u8]) {
+    if let Some(peer) = read_cbor(data).as_ref().and_then(PeerInfo::from_cbor) {
+        _ = peer.to_string();
+        _ = peer.to_cbor();
+    }
+}
+
+pub fn kbucket_entry(data: &[u8]) {
+    if let Some(entry) = read_cbor(data).as_ref().and_then(KBucketEntry::from_cbor) {
+        _ = entry.to_string();
+        _ = entry.to_cbor();
+    }
+}
@@ -1,6 +1,9 @@
 pub mod core;
 pub mod activeproxy;
 
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+
 #[cfg(test)]
 mod unitests;
 
@@ -9,7 +12,12 @@ pub use {
     core::id::Id,
     core::node::Node,
     core::error::Error,
+    core::error::ErrorCode,
     core::error,
+    core::event::{
+        NodeEvent,
+        EventStream
+    },
     core::config,
     core::config::Config,
     core::prefix::Prefix,
@@ -26,8 +34,29 @@ pub use {
     },
     core::network::Network,
     core::node_status::NodeStatus,
-    core::lookup_option::LookupOption,
+    core::lookup_option::{
+        LookupOption,
+        LookupOptions,
+    },
     core::joint_result::JointResult,
+    core::future::PeerStream,
+    core::stats,
+    core::routing_snapshot::{
+        RoutingTableSnapshot,
+        BucketSnapshot,
+        EntrySnapshot
+    },
+    core::stats::Stats,
+    core::clock::{
+        Clock,
+        SystemClock,
+        ManualClock
+    },
+    core::transport::{
+        Transport,
+        TransportFuture,
+        UdpTransport
+    },
     core::default_configuration as configuration,
     core::signature,
     core::signature::Signature,
@@ -38,7 +67,7 @@ pub use {
 #[macro_export]
 macro_rules! as_millis {
     ($time:expr) => {{
-        $time.elapsed().unwrap().as_millis()
+        $crate::core::clock::elapsed($time).as_millis()
     }};
 }
 
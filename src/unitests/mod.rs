@@ -9,11 +9,22 @@
 #[cfg(test)] mod test_version;
 #[cfg(test)] mod test_addr;
 #[cfg(test)] mod test_logger;
+#[cfg(test)] mod test_stats;
+#[cfg(test)] mod test_event;
+#[cfg(test)] mod test_future;
+#[cfg(test)] mod test_clock;
+#[cfg(test)] mod test_crypto_cache;
+#[cfg(test)] mod test_replay_window;
+#[cfg(test)] mod test_rtt_estimator;
+#[cfg(test)] mod test_closest_candidates;
+#[cfg(test)] mod test_lookup_task;
+#[cfg(test)] mod test_decoding;
 
 #[cfg(test)] mod test_find_node_req;
 #[cfg(test)] mod test_find_node_rsp;
 #[cfg(test)] mod test_find_peer_req;
 #[cfg(test)] mod test_find_peer_rsp;
+#[cfg(test)] mod test_error_msg;
 
 #[cfg(test)] use std::env;
 #[cfg(test)] use std::fs;
@@ -1,11 +1,14 @@
+use std::rc::Rc;
 use std::net::{
     IpAddr,
     SocketAddr
 };
+use crate::Id;
 use crate::core::{
     is_bogon,
     is_global_unicast,
-    is_any_unicast
+    is_any_unicast,
+    dht::DHT,
 };
 
 #[test]
@@ -36,6 +39,9 @@ fn test_is_global_unicast() {
 fn test_is_any_unicast() {
     assert_eq!(is_any_unicast(&"192.168.1.1".parse::<IpAddr>().unwrap()), true);
     assert_eq!(is_any_unicast(&"10.0.0.1".parse::<IpAddr>().unwrap()), true);
+    assert_eq!(is_any_unicast(&"127.0.0.1".parse::<IpAddr>().unwrap()), false);
+    assert_eq!(is_any_unicast(&"::1".parse::<IpAddr>().unwrap()), false);
+    assert_eq!(is_any_unicast(&"224.0.0.1".parse::<IpAddr>().unwrap()), false);
 }
 
 #[test]
@@ -49,3 +55,18 @@ fn test_is_bogon() {
 
     assert_eq!(is_bogon(&"192.168.0.8:0".parse::<SocketAddr>().unwrap()), true);
 }
+
+#[test]
+fn test_dht_loopback_allowed() {
+    let addr = "[::1]:39001".parse::<SocketAddr>().unwrap();
+    let mut dht = DHT::new(Rc::new(Id::random()), addr);
+
+    assert_eq!(dht.is_bogon(&"[::1]:39002".parse::<SocketAddr>().unwrap()), true);
+    assert_eq!(dht.is_bogon(&"127.0.0.1:39002".parse::<SocketAddr>().unwrap()), true);
+    assert_eq!(dht.is_bogon(&"[2001:4860:4860::8888]:1234".parse::<SocketAddr>().unwrap()), false);
+
+    dht.set_loopback_allowed(true);
+    assert_eq!(dht.is_bogon(&"[::1]:39002".parse::<SocketAddr>().unwrap()), false);
+    assert_eq!(dht.is_bogon(&"127.0.0.1:39002".parse::<SocketAddr>().unwrap()), false);
+    assert_eq!(dht.is_bogon(&"224.0.0.1:39002".parse::<SocketAddr>().unwrap()), true);
+}
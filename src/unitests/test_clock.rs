@@ -0,0 +1,164 @@
+use std::{
+    fs,
+    rc::Rc,
+    sync::Arc,
+    cell::RefCell,
+    net::SocketAddr,
+    time::Duration,
+};
+use serial_test::serial;
+
+use crate::{
+    Id,
+    ValueBuilder,
+};
+use crate::unitests::create_random_bytes;
+use crate::core::{
+    clock::{self, Clock, ManualClock},
+    data_storage::DataStorage,
+    sqlite_storage::SqliteStorage,
+    token_manager::TokenManager,
+    kbucket_entry::KBucketEntry,
+    scheduler::{self, Scheduler},
+};
+
+// Install a virtual clock on the thread of the running test.
+fn manual_clock() -> Arc<ManualClock> {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+    clock
+}
+
+#[test]
+fn test_manual_clock() {
+    let clock = manual_clock();
+    let start = clock::now();
+
+    assert_eq!(clock::now(), start);
+    clock.advance(Duration::from_secs(90));
+    assert_eq!(clock::now(), start + Duration::from_secs(90));
+    assert_eq!(clock::elapsed(start), Duration::from_secs(90));
+
+    // elapsed time is never negative.
+    clock.set(start - Duration::from_secs(1));
+    assert_eq!(clock::elapsed(start), Duration::ZERO);
+}
+
+#[test]
+fn test_manual_clock_watch() {
+    let clock = ManualClock::new();
+    let notifier = Arc::new(tokio::sync::Notify::new());
+    clock.watch(Arc::downgrade(&notifier));
+
+    // The permit is kept for the node which is not waiting yet.
+    clock.advance(Duration::from_secs(1));
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+    runtime.block_on(async {
+        let notified = tokio::time::timeout(Duration::from_millis(100), notifier.notified()).await;
+        assert!(notified.is_ok());
+    });
+}
+
+#[test]
+fn test_token_rotation() {
+    let clock = manual_clock();
+    let man = TokenManager::new();
+
+    let nodeid = Id::random();
+    let target = Id::random();
+    let addr = "192.168.1.123:39001".parse::<SocketAddr>().unwrap();
+
+    let token = man.generate_token(&nodeid, &addr, &target);
+    assert!(man.verify_token(token, &nodeid, &addr, &target));
+
+    // the previous token is still accepted after one rotation.
+    clock.advance(Duration::from_secs(6 * 60));
+    assert!(man.verify_token(token, &nodeid, &addr, &target));
+    assert_ne!(man.generate_token(&nodeid, &addr, &target), token);
+
+    // but not after two rotations.
+    clock.advance(Duration::from_secs(6 * 60));
+    assert!(!man.verify_token(token, &nodeid, &addr, &target));
+}
+
+#[test]
+fn test_stale_entry() {
+    let clock = manual_clock();
+    let addr = "192.168.1.123:39001".parse::<SocketAddr>().unwrap();
+    let mut entry = KBucketEntry::new(Id::random(), addr);
+
+    entry.signal_response();
+    assert!(!entry.needs_ping());
+    clock.advance(Duration::from_secs(16 * 60));
+    assert!(entry.needs_ping());
+
+    entry.signal_response();
+    for _ in 0..6 {
+        entry.signal_request_timeout();
+    }
+    assert!(!entry.needs_replacement());
+    clock.advance(Duration::from_secs(16 * 60));
+    assert!(entry.needs_replacement());
+}
+
+#[test]
+fn test_scheduler() {
+    let clock = manual_clock();
+    let scheduler = Rc::new(RefCell::new(Scheduler::new()));
+    let oneshot = Rc::new(RefCell::new(0));
+    let periodic = Rc::new(RefCell::new(0));
+
+    let cloned = oneshot.clone();
+    scheduler.borrow_mut().add_oneshot(move || {
+        *cloned.borrow_mut() += 1;
+    }, 1000);
+    let cloned = periodic.clone();
+    scheduler.borrow_mut().add(move || {
+        *cloned.borrow_mut() += 1;
+    }, 500, 1000);
+
+    scheduler::run_jobs(scheduler.clone());
+    assert_eq!(*oneshot.borrow(), 0);
+    assert_eq!(*periodic.borrow(), 0);
+
+    clock.advance(Duration::from_millis(500));
+    scheduler::run_jobs(scheduler.clone());
+    assert_eq!(*oneshot.borrow(), 0);
+    assert_eq!(*periodic.borrow(), 1);
+
+    clock.advance(Duration::from_millis(1000));
+    scheduler::run_jobs(scheduler.clone());
+    assert_eq!(*oneshot.borrow(), 1);
+    assert_eq!(*periodic.borrow(), 2);
+
+    clock.advance(Duration::from_secs(60));
+    scheduler::run_jobs(scheduler.clone());
+    assert_eq!(*oneshot.borrow(), 1);
+    assert_eq!(*periodic.borrow(), 3);
+}
+
+#[test]
+#[serial]
+fn test_storage_expiry() {
+    let clock = manual_clock();
+    let path = "clock.db".to_string();
+    let mut storage = SqliteStorage::new();
+    storage.open(&path).expect("opening db error");
+
+    let value = ValueBuilder::new(&create_random_bytes(32))
+        .build()
+        .expect("Failed to build value");
+    storage.put_value(&value, None, Some(false), Some(false)).unwrap();
+
+    clock.advance(Duration::from_secs(119 * 60));
+    storage.expire();
+    assert_eq!(storage.value(&value.id()).unwrap(), Some(value.clone()));
+
+    clock.advance(Duration::from_secs(2 * 60));
+    assert_eq!(storage.value(&value.id()).unwrap(), None);
+    storage.expire();
+    assert!(storage.value_ids().unwrap().is_empty());
+
+    storage.close();
+    _ = fs::remove_file(&path);
+}
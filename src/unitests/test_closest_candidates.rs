@@ -0,0 +1,88 @@
+use std::rc::Rc;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::collections::HashMap;
+
+use crate::{
+    Id,
+    NodeInfo,
+    id::ID_BYTES,
+};
+use crate::core::task::closest_candidates::ClosestCandidates;
+
+fn node(port: u16) -> Rc<NodeInfo> {
+    let addr = format!("1.2.3.4:{}", port).parse::<SocketAddr>().unwrap();
+    Rc::new(NodeInfo::new(Id::random(), addr))
+}
+
+// A node sharing the first `prefix_len` bits with the target, told apart
+// from the others of the same prefix by the last byte.
+fn node_at(target: &Id, prefix_len: usize, tail: u8, port: u16) -> Rc<NodeInfo> {
+    let mut bytes = [0u8; ID_BYTES];
+    bytes.copy_from_slice(target.as_bytes());
+    bytes[prefix_len / 8] ^= 0x80 >> (prefix_len % 8);
+    bytes[ID_BYTES - 1] ^= tail;
+    let addr = format!("1.2.3.4:{}", port).parse::<SocketAddr>().unwrap();
+    Rc::new(NodeInfo::new(Id::from_bytes(bytes), addr))
+}
+
+#[test]
+fn test_add() {
+    let mut cands = ClosestCandidates::new(Rc::new(Id::random()), 8);
+    let nodes = (0..4).map(|i| node(39001 + i)).collect::<Vec<_>>();
+    cands.add(&nodes, 0, &HashMap::new());
+    assert_eq!(cands.size(), 4);
+
+    // Same id or same address as the nodes taken already.
+    let same_id = Rc::new(NodeInfo::new(nodes[0].id().clone(), "1.2.3.5:39001".parse().unwrap()));
+    let same_addr = Rc::new(NodeInfo::new(Id::random(), *nodes[1].socket_addr()));
+    cands.add(&[same_id, same_addr], 0, &HashMap::new());
+    assert_eq!(cands.size(), 4);
+}
+
+// The nodes removed after failing to respond are given back by the other
+// nodes later on, which must not make them candidates again.
+#[test]
+fn test_remove() {
+    let mut cands = ClosestCandidates::new(Rc::new(Id::random()), 8);
+    let nodes = (0..4).map(|i| node(39001 + i)).collect::<Vec<_>>();
+    cands.add(&nodes, 0, &HashMap::new());
+
+    assert!(cands.remove(nodes[0].id()).is_some());
+    assert!(cands.remove(nodes[0].id()).is_none());
+    assert_eq!(cands.size(), 3);
+    assert!(cands.knows(&nodes[0]));
+
+    cands.add(&nodes, 1, &HashMap::new());
+    assert_eq!(cands.size(), 3);
+    while let Some(cn) = cands.next() {
+        assert_ne!(cn.borrow().id(), nodes[0].id());
+        cands.remove(&cn.borrow().id().clone());
+    }
+    assert_eq!(cands.size(), 0);
+}
+
+#[test]
+fn test_next_order() {
+    let target = Rc::new(Id::random());
+    let mut cands = ClosestCandidates::new(target.clone(), 8);
+
+    let far = node_at(&target, 10, 0, 39001);
+    let slow = node_at(&target, 20, 1, 39002);
+    let fast = node_at(&target, 20, 2, 39003);
+    let unmeasured = node_at(&target, 20, 3, 39004);
+    let mut rtts = HashMap::new();
+    rtts.insert(far.id().clone(), Duration::from_millis(10));
+    rtts.insert(slow.id().clone(), Duration::from_millis(300));
+    rtts.insert(fast.id().clone(), Duration::from_millis(50));
+    cands.add(&[far.clone(), slow.clone(), fast.clone(), unmeasured.clone()], 0, &rtts);
+
+    // The closer nodes go first however fast the others are, the faster
+    // ones first among the nodes as close, and the unmeasured ones last.
+    for expected in [&fast, &slow, &unmeasured, &far] {
+        let cn = cands.next().unwrap();
+        assert_eq!(cn.borrow().id(), expected.id());
+        cands.remove(&cn.borrow().id().clone());
+    }
+    assert!(cands.next().is_none());
+}
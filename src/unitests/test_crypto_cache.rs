@@ -0,0 +1,255 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    Id,
+    signature,
+    cryptobox::KeyPair,
+};
+use crate::unitests::create_random_bytes;
+use crate::core::{
+    clock::{self, ManualClock},
+    constants,
+    version,
+    msg::msg::Kind,
+    replay_window::ReplayWindow,
+    crypto_cache::{
+        CryptoCache,
+        PACKET_HANDSHAKE,
+        PACKET_SESSION,
+    },
+};
+
+struct Peer {
+    id: Id,
+    keypair: KeyPair,
+    cache: CryptoCache,
+}
+
+fn create_peer() -> Peer {
+    let keypair = signature::KeyPair::random();
+    let id = Id::from(keypair.to_public_key());
+    let keypair = KeyPair::from(&keypair);
+    Peer { id, keypair: keypair.clone(), cache: CryptoCache::new(keypair) }
+}
+
+// Send a packet from one peer to the other, returning its format byte.
+fn send(from: &mut Peer, to: &mut Peer) -> u8 {
+    let plain = create_random_bytes(64);
+    let cipher = from.cache.encrypt_into(&to.id, &plain).unwrap();
+    deliver(from, to, &cipher, &plain);
+    cipher[0]
+}
+
+fn deliver(from: &Peer, to: &mut Peer, cipher: &[u8], plain: &[u8]) {
+    let (decrypted, _, received) = to.cache.decrypt_into(&from.id, cipher).unwrap();
+    assert_eq!(decrypted, plain);
+    to.cache.accept(&from.id, received);
+}
+
+#[test]
+fn test_legacy() {
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    // Nothing is known about the peers yet.
+    let plain = create_random_bytes(64);
+    let cipher1 = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    let cipher2 = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    assert_eq!(cipher1, cipher2);
+    assert_eq!(b.cache.decrypt_into(&a.id, &cipher1).unwrap().0, plain);
+
+    // Peers of older versions stay on the legacy format.
+    b.cache.learn_version(&a.id, version::build("MK", 1));
+    assert_eq!(b.cache.encrypt_into(&a.id, &plain).unwrap().len(), cipher1.len());
+    b.cache.learn_version(&a.id, 0);
+    assert_eq!(b.cache.encrypt_into(&a.id, &plain).unwrap().len(), cipher1.len());
+    send(&mut b, &mut a);
+}
+
+#[test]
+fn test_negotiation() {
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    send(&mut a, &mut b);
+    b.cache.learn_version(&a.id, version::ver());
+    assert_eq!(send(&mut b, &mut a), PACKET_HANDSHAKE);
+    assert_eq!(send(&mut a, &mut b), PACKET_HANDSHAKE);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+    assert_eq!(send(&mut a, &mut b), PACKET_SESSION);
+
+    // Every packet takes a fresh nonce.
+    let plain = create_random_bytes(64);
+    let cipher1 = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    let cipher2 = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    assert_ne!(cipher1, cipher2);
+
+    // Versioned packets tell when they were sent, legacy ones don't.
+    let (_, timestamp, _) = b.cache.decrypt_into(&a.id, &cipher1).unwrap();
+    assert!(clock::elapsed(timestamp.unwrap()) < Duration::from_secs(5));
+    let mut c = create_peer();
+    let legacy = c.cache.encrypt_into(&b.id, &plain).unwrap();
+    assert!(b.cache.decrypt_into(&c.id, &legacy).unwrap().1.is_none());
+
+    // Packets are bound to the sender.
+    assert!(b.cache.decrypt_into(&c.id, &cipher1).is_err());
+    let mut tampered = cipher1.clone();
+    let len = tampered.len();
+    tampered[len - 1] ^= 0xFF;
+    assert!(b.cache.decrypt_into(&a.id, &tampered).is_err());
+}
+
+#[test]
+fn test_restarted_peer() {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    send(&mut a, &mut b);
+    b.cache.learn_version(&a.id, version::ver());
+    send(&mut b, &mut a);
+    send(&mut a, &mut b);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+
+    // The restarted peer can't read the session packets until the
+    // handshake is made again.
+    a.cache = CryptoCache::new(a.keypair.clone());
+    let plain = create_random_bytes(64);
+    let cipher = b.cache.encrypt_into(&a.id, &plain).unwrap();
+    assert!(a.cache.decrypt_into(&b.id, &cipher).is_err());
+
+    // Nor are its legacy packets taken, as they can't be told from the
+    // replayed ones, until the handshake is sent to it as it's not been
+    // heard from for a while.
+    let legacy = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    assert!(b.cache.decrypt_into(&a.id, &legacy).is_err());
+
+    clock.advance(Duration::from_millis(constants::SESSION_ACK_TIMEOUT as u64));
+    assert_eq!(send(&mut b, &mut a), PACKET_HANDSHAKE);
+    assert_eq!(send(&mut a, &mut b), PACKET_HANDSHAKE);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+    assert_eq!(send(&mut a, &mut b), PACKET_SESSION);
+    clock::reset();
+}
+
+#[test]
+fn test_lost_handshake() {
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    send(&mut a, &mut b);
+    b.cache.learn_version(&a.id, version::ver());
+
+    // The handshake in reply never arrives, so the peer keeps sending the
+    // legacy format, which is still taken until it has sent a versioned one.
+    let plain = create_random_bytes(64);
+    let lost = b.cache.encrypt_into(&a.id, &plain).unwrap();
+    assert_eq!(lost[0], PACKET_HANDSHAKE);
+    let legacy = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    deliver(&a, &mut b, &legacy, &plain);
+
+    assert_eq!(send(&mut b, &mut a), PACKET_HANDSHAKE);
+    assert_eq!(send(&mut a, &mut b), PACKET_HANDSHAKE);
+    assert!(b.cache.decrypt_into(&a.id, &legacy).is_err());
+}
+
+#[test]
+fn test_replayed_legacy() {
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    let plain = create_random_bytes(64);
+    let legacy = a.cache.encrypt_into(&b.id, &plain).unwrap();
+    deliver(&a, &mut b, &legacy, &plain);
+
+    b.cache.learn_version(&a.id, version::ver());
+    send(&mut b, &mut a);
+    send(&mut a, &mut b);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+
+    // The captured legacy packet neither gets in, nor takes the peers back
+    // to the handshake or to the legacy format.
+    assert!(b.cache.decrypt_into(&a.id, &legacy).is_err());
+    b.cache.learn_version(&a.id, 0);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+    assert_eq!(send(&mut a, &mut b), PACKET_SESSION);
+}
+
+#[test]
+fn test_replayed_handshake() {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    send(&mut a, &mut b);
+    b.cache.learn_version(&a.id, version::ver());
+    let plain = create_random_bytes(64);
+    let captured = b.cache.encrypt_into(&a.id, &plain).unwrap();
+    assert_eq!(captured[0], PACKET_HANDSHAKE);
+    deliver(&b, &mut a, &captured, &plain);
+    send(&mut a, &mut b);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+
+    // The session keys get rotated, and the old ones dropped.
+    clock.advance(Duration::from_secs(30 * 60));
+    send(&mut a, &mut b);
+    b.cache.expire();
+    assert_eq!(send(&mut b, &mut a), PACKET_HANDSHAKE);
+    assert_eq!(send(&mut a, &mut b), PACKET_SESSION);
+    clock.advance(Duration::from_secs(3 * 60));
+    send(&mut a, &mut b);
+    b.cache.expire();
+
+    // The captured handshake reads fine, but is stale, and nothing of it
+    // is taken before it's been checked.
+    let (_, timestamp, _) = a.cache.decrypt_into(&b.id, &captured).unwrap();
+    let mut window = ReplayWindow::new();
+    assert!(window.check(&b.id, Kind::Request, 1, timestamp).is_err());
+
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+    assert_eq!(send(&mut a, &mut b), PACKET_SESSION);
+    clock::reset();
+}
+
+#[test]
+fn test_key_rotation() {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+
+    let mut a = create_peer();
+    let mut b = create_peer();
+
+    send(&mut a, &mut b);
+    b.cache.learn_version(&a.id, version::ver());
+    send(&mut b, &mut a);
+    send(&mut a, &mut b);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+
+    // A packet sent before the peer learns the new session key.
+    let plain = create_random_bytes(64);
+    let in_flight = b.cache.encrypt_into(&a.id, &plain).unwrap();
+
+    clock.advance(Duration::from_secs(30 * 60));
+    send(&mut b, &mut a);
+    a.cache.expire();
+    assert_eq!(send(&mut a, &mut b), PACKET_HANDSHAKE);
+    assert_eq!(a.cache.decrypt_into(&b.id, &in_flight).unwrap().0, plain);
+    assert_eq!(send(&mut b, &mut a), PACKET_SESSION);
+    assert_eq!(send(&mut a, &mut b), PACKET_SESSION);
+
+    // The previous session key is dropped after the grace period.
+    clock.advance(Duration::from_secs(3 * 60));
+    send(&mut b, &mut a);
+    a.cache.expire();
+    assert!(a.cache.decrypt_into(&b.id, &in_flight).is_err());
+
+    // Back to the handshake when the peer has not been heard from.
+    clock.advance(Duration::from_secs(2 * 60));
+    assert_eq!(send(&mut a, &mut b), PACKET_HANDSHAKE);
+    clock::reset();
+}
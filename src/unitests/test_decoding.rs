@@ -0,0 +1,265 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use ciborium::Value as CVal;
+
+use crate::{
+    Id,
+    NodeInfo,
+    PeerBuilder,
+    ValueBuilder,
+    SignedBuilder,
+};
+use crate::core::{
+    cbor,
+    error::ErrorCode,
+    kbucket_entry::KBucketEntry,
+};
+use crate::core::msg::{
+    Msg,
+    deser,
+    serialize,
+    lookup_req::Msg as LookupReqMsg,
+    lookup_rsp::Msg as LookupRspMsg,
+    ping_req,
+    find_node_req,
+    find_node_rsp,
+    find_peer_rsp,
+    announce_peer_req,
+    store_value_req,
+    find_value_rsp,
+    error_msg,
+    Method,
+};
+
+// A fixed xorshift sequence keeps the mutations reproducible.
+struct Mutator(u64);
+
+impl Mutator {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        (self.next() % len as u64) as usize
+    }
+}
+
+fn encode(mut msg: Box<dyn Msg>) -> Vec<u8> {
+    msg.set_id(&Id::random());
+    serialize(Rc::new(RefCell::new(msg)))
+}
+
+fn encode_cbor(val: &CVal) -> Vec<u8> {
+    let mut buf = Vec::new();
+    ciborium::ser::into_writer(val, cbor::Writer::new(&mut buf)).unwrap();
+    buf
+}
+
+fn valid_messages() -> Vec<Vec<u8>> {
+    let addr = "192.168.1.1:39001".parse::<SocketAddr>().unwrap();
+    let ni = Rc::new(NodeInfo::new(Id::random(), addr));
+    let mut msgs = Vec::new();
+
+    let mut msg = ping_req::Message::new();
+    msg.set_txid(1);
+    msgs.push(encode(Box::new(msg)));
+
+    let mut msg = find_node_req::Message::new();
+    msg.set_txid(2);
+    msg.with_target(Rc::new(Id::random()));
+    msg.with_want4(true);
+    msgs.push(encode(Box::new(msg)));
+
+    let mut msg = find_node_rsp::Message::new();
+    msg.set_txid(3);
+    msg.populate_closest_nodes4(vec![ni.clone()]);
+    msgs.push(encode(Box::new(msg)));
+
+    let peer = PeerBuilder::new(&Id::random()).with_port(8080).build();
+    let mut msg = find_peer_rsp::Message::new();
+    msg.set_txid(4);
+    msg.populate_peers(vec![peer.clone()]);
+    msgs.push(encode(Box::new(msg)));
+
+    let mut msg = announce_peer_req::Message::new();
+    msg.set_txid(5);
+    msg.with_token(1234);
+    msg.with_peer(Rc::new(peer));
+    msgs.push(encode(Box::new(msg)));
+
+    let value = SignedBuilder::new(b"signed value").build().unwrap();
+    let mut msg = store_value_req::Message::new(Some(Rc::new(value.clone())));
+    msg.set_txid(6);
+    msg.with_token(1234);
+    msgs.push(encode(Box::new(msg)));
+
+    let mut msg = find_value_rsp::Message::new();
+    msg.set_txid(7);
+    msg.populate_value(Rc::new(value));
+    msgs.push(encode(Box::new(msg)));
+
+    let mut msg = error_msg::Message::new(Method::Ping, 8);
+    msg.with_code(ErrorCode::Protocol);
+    msg.with_msg("bad request");
+    msgs.push(encode(Box::new(msg)));
+
+    msgs
+}
+
+fn check_deser(buf: &[u8]) {
+    if let Ok(msg) = deser(buf) {
+        msg.borrow_mut().set_id(&Id::random());
+        _ = msg.borrow().to_string();
+        _ = serialize(msg);
+    }
+}
+
+#[test]
+fn test_valid() {
+    for buf in valid_messages() {
+        assert!(deser(&buf).is_ok());
+    }
+}
+
+#[test]
+fn test_truncated() {
+    for buf in valid_messages() {
+        for len in 0..buf.len() {
+            check_deser(&buf[..len]);
+        }
+    }
+}
+
+#[test]
+fn test_mutated() {
+    let mut rng = Mutator(0x2545F4914F6CDD1D);
+    for buf in valid_messages() {
+        for _ in 0..2000 {
+            let mut data = buf.clone();
+            for _ in 0..1 + rng.index(4) {
+                let pos = rng.index(data.len());
+                data[pos] = rng.next() as u8;
+            }
+            check_deser(&data);
+        }
+    }
+}
+
+#[test]
+fn test_garbage() {
+    let mut rng = Mutator(0x9E3779B97F4A7C15);
+    for _ in 0..5000 {
+        let data: Vec<u8> = (0..rng.index(64)).map(|_| rng.next() as u8).collect();
+        check_deser(&data);
+    }
+}
+
+#[test]
+fn test_malformed() {
+    let text = |v: &str| CVal::Text(v.to_string());
+    let int = |v: i64| CVal::Integer(v.into());
+
+    let cases = vec![
+        // not a map, and no message type.
+        CVal::Array(vec![]),
+        CVal::Map(vec![(text("t"), int(1))]),
+        CVal::Map(vec![(int(1), int(1)), (text("y"), int(0x21))]),
+        // invalid type, kind and method.
+        CVal::Map(vec![(text("y"), text("q"))]),
+        CVal::Map(vec![(text("y"), int(i64::MAX))]),
+        CVal::Map(vec![(text("y"), int(0xE1))]),
+        CVal::Map(vec![(text("y"), int(0x3F))]),
+        CVal::Map(vec![(text("y"), int(0x20))]),
+        // out of range transaction id.
+        CVal::Map(vec![(text("y"), int(0x21)), (text("t"), int(i64::MAX))]),
+        // lookup request without target.
+        CVal::Map(vec![
+            (text("y"), int(0x22)), (text("t"), int(1)),
+            (text("q"), CVal::Map(vec![(text("w"), int(1))]))
+        ]),
+        // lookup target of a wrong size.
+        CVal::Map(vec![
+            (text("y"), int(0x22)), (text("t"), int(1)),
+            (text("q"), CVal::Map(vec![(text("t"), CVal::Bytes(vec![1, 2, 3]))]))
+        ]),
+        // store request without value, with empty or unsigned mutable value.
+        CVal::Map(vec![
+            (text("y"), int(0x25)), (text("t"), int(1)),
+            (text("q"), CVal::Map(vec![(text("tok"), int(1))]))
+        ]),
+        CVal::Map(vec![
+            (text("y"), int(0x25)), (text("t"), int(1)),
+            (text("q"), CVal::Map(vec![(text("v"), CVal::Bytes(vec![]))]))
+        ]),
+        CVal::Map(vec![
+            (text("y"), int(0x25)), (text("t"), int(1)),
+            (text("q"), CVal::Map(vec![
+                (text("k"), CVal::Bytes(Id::random().as_bytes().to_vec())),
+                (text("v"), CVal::Bytes(vec![1])),
+            ]))
+        ]),
+        // found value with a wrong nonce.
+        CVal::Map(vec![
+            (text("y"), int(0x46)), (text("t"), int(1)),
+            (text("r"), CVal::Map(vec![
+                (text("n"), CVal::Bytes(vec![1])),
+                (text("v"), CVal::Bytes(vec![1])),
+            ]))
+        ]),
+    ];
+
+    for val in cases {
+        assert!(deser(&encode_cbor(&val)).is_err(), "accepted {:?}", val);
+    }
+}
+
+#[test]
+fn test_from_cbor() {
+    let text = |v: &str| CVal::Text(v.to_string());
+    let int = |v: i64| CVal::Integer(v.into());
+    let id = CVal::Bytes(Id::random().as_bytes().to_vec());
+
+    // node info with a wrong port, address or id.
+    assert!(NodeInfo::from_cbor(&CVal::Array(vec![id.clone(), CVal::Bytes(vec![127, 0, 0, 1]), int(-1)])).is_none());
+    assert!(NodeInfo::from_cbor(&CVal::Array(vec![id.clone(), CVal::Bytes(vec![127, 0, 1]), int(80)])).is_none());
+    assert!(NodeInfo::from_cbor(&CVal::Array(vec![CVal::Bytes(vec![1]), CVal::Bytes(vec![127, 0, 0, 1]), int(80)])).is_none());
+    assert!(NodeInfo::from_cbor(&CVal::Array(vec![id.clone()])).is_none());
+
+    // peer info with a port out of range.
+    assert!(crate::PeerInfo::from_cbor(&CVal::Map(vec![
+        (text("id"), id.clone()),
+        (text("nodeid"), id.clone()),
+        (text("port"), int(1 << 20)),
+        (text("sig"), CVal::Bytes(vec![1])),
+    ])).is_none());
+
+    // routing table entry without id or address, or with a time overflow.
+    assert!(KBucketEntry::from_cbor(&CVal::Map(vec![(text("port"), int(80))])).is_none());
+    assert!(KBucketEntry::from_cbor(&CVal::Map(vec![(text("id"), id.clone())])).is_none());
+    assert!(KBucketEntry::from_cbor(&CVal::Map(vec![
+        (text("id"), id.clone()),
+        (text("port"), int(80)),
+        (text("addr"), CVal::Bytes(vec![127, 0, 0, 1])),
+        (text("created"), CVal::Integer(u64::MAX.into())),
+    ])).is_none());
+    assert!(KBucketEntry::from_cbor(&CVal::Map(vec![
+        (text("id"), id.clone()),
+        (text("port"), int(80)),
+        (text("addr"), CVal::Bytes(vec![127, 0, 0, 1])),
+        (text("created"), int(1700000000)),
+    ])).is_some());
+
+    // an immutable value is still decoded by the usual path.
+    let value = ValueBuilder::new(b"immutable").build().unwrap();
+    let mut msg = find_value_rsp::Message::new();
+    msg.set_txid(1);
+    msg.populate_value(Rc::new(value.clone()));
+    let decoded = deser(&encode(Box::new(msg))).unwrap();
+    let decoded = decoded.borrow();
+    let decoded = decoded.as_any().downcast_ref::<find_value_rsp::Message>().unwrap();
+    assert_eq!(decoded.value().as_deref(), Some(&value));
+}
@@ -0,0 +1,58 @@
+use crate::{Id, Error};
+use crate::core::error::ErrorCode;
+use crate::core::msg::{
+    Msg,
+    Method,
+    error_msg::Message,
+};
+
+#[test]
+fn test_codes() {
+    let codes = [
+        ErrorCode::Generic,
+        ErrorCode::Server,
+        ErrorCode::Protocol,
+        ErrorCode::MethodUnknown,
+    ];
+    for code in codes {
+        assert_eq!(ErrorCode::from(code.code()), code);
+    }
+
+    // Codes unknown to this node are kept as they are.
+    assert_eq!(ErrorCode::from(205), ErrorCode::Unknown(205));
+    assert_eq!(ErrorCode::Unknown(205).code(), 205);
+    assert_eq!(ErrorCode::Protocol.to_string(), "Protocol(203)");
+}
+
+#[test]
+fn test_cbor() {
+    let mut msg = Message::new(Method::StoreValue, 10);
+    msg.with_code(ErrorCode::Protocol);
+    msg.with_msg("Invalid value");
+
+    let cval = msg.ser();
+    let mut decoded = Message::new(Method::Unknown, 0);
+    assert!(decoded.from_cbor(&cval).is_some());
+    assert_eq!(decoded.txid(), 10);
+    assert_eq!(decoded.code(), ErrorCode::Protocol);
+    assert_eq!(decoded.msg(), "Invalid value");
+}
+
+#[test]
+fn test_to_error() {
+    let nodeid = Id::random();
+    let mut msg = Message::new(Method::AnnouncePeer, 10);
+    msg.set_id(&nodeid);
+    msg.with_code(ErrorCode::Protocol);
+    msg.with_msg("Invalid token");
+
+    match msg.to_error() {
+        Error::Remote { code, node, message } => {
+            assert_eq!(code, ErrorCode::Protocol);
+            assert_eq!(node, nodeid);
+            assert_eq!(message, "Invalid token");
+        },
+        err => panic!("Unexpected error {}", err),
+    }
+    assert!(msg.to_error().to_string().contains("Protocol(203)"));
+}
@@ -0,0 +1,93 @@
+use std::rc::Rc;
+use std::sync::Arc;
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+use crate::{
+    Id,
+    Network,
+    NodeStatus,
+};
+
+use crate::core::{
+    event::{EventBus, NodeEvent},
+    kbucket_entry::KBucketEntry,
+    routing_table::RoutingTable,
+};
+
+#[test]
+fn test_publish() {
+    let bus = EventBus::new();
+    bus.publish(NodeEvent::StatusChanged(NodeStatus::Initializing));
+
+    let mut events1 = bus.subscribe();
+    let mut events2 = bus.subscribe();
+    assert!(events1.try_next().is_none());
+
+    bus.publish(NodeEvent::StatusChanged(NodeStatus::Running));
+    bus.publish(NodeEvent::BootstrapCompleted(Network::IPv4));
+
+    match events1.try_next() {
+        Some(NodeEvent::StatusChanged(status)) => assert_eq!(status, NodeStatus::Running),
+        Some(v) => panic!("Unexpected event: {}", v),
+        None => panic!("Missing status event"),
+    }
+    match events1.try_next() {
+        Some(NodeEvent::BootstrapCompleted(network)) => assert_eq!(network, Network::IPv4),
+        Some(v) => panic!("Unexpected event: {}", v),
+        None => panic!("Missing bootstrap event"),
+    }
+    assert!(events1.try_next().is_none());
+
+    // Dropped subscribers should not affect the others.
+    drop(events1);
+    bus.publish(NodeEvent::ReachabilityChanged(true));
+
+    let mut count = 0;
+    while events2.try_next().is_some() {
+        count += 1;
+    }
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn test_routing_table_events() {
+    let bus = Arc::new(EventBus::new());
+    let mut events = bus.subscribe();
+
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    rt.set_events(bus.clone());
+
+    let id = Id::random();
+    let addr = "192.168.1.100:39001".parse::<SocketAddr>().unwrap();
+    let mut entry = KBucketEntry::new(id.clone(), addr);
+    entry.signal_response();
+    entry.merge_request_time(SystemTime::now());
+
+    rt.put(Rc::new(RefCell::new(entry)));
+    match events.try_next() {
+        Some(NodeEvent::EntryAdded(ni)) => {
+            assert_eq!(ni.id(), &id);
+            assert_eq!(ni.socket_addr(), &addr);
+        },
+        Some(v) => panic!("Unexpected event: {}", v),
+        None => panic!("Missing entry added event"),
+    }
+
+    // Putting the same entry again only refreshes the existing one.
+    let mut entry = KBucketEntry::new(id.clone(), addr);
+    entry.signal_response();
+    rt.put(Rc::new(RefCell::new(entry)));
+    assert!(events.try_next().is_none());
+
+    rt.remove(&id);
+    match events.try_next() {
+        Some(NodeEvent::EntryRemoved(ni)) => assert_eq!(ni.id(), &id),
+        Some(v) => panic!("Unexpected event: {}", v),
+        None => panic!("Missing entry removed event"),
+    }
+
+    rt.remove(&id);
+    assert!(events.try_next().is_none());
+}
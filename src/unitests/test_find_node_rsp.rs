@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::cell::RefCell;
 use std::net::SocketAddr;
 use crate::{
     Id,
@@ -7,6 +8,9 @@ use crate::{
 
 use crate::core::msg::{
     Msg,
+    deser,
+    serialize,
+    serialize_within,
     lookup_rsp::Msg as LookupMsg,
     find_node_rsp::Message,
 };
@@ -63,3 +67,39 @@ fn test_cbor_with_ipv6() {
     assert_eq!(nodes6.len(), 1);
     assert_eq!(nodes6[0], ni6);
 }
+
+#[test]
+fn test_trim() {
+    let mut nodes4 = Vec::new();
+    let mut nodes6 = Vec::new();
+    for i in 0..8 {
+        let addr = format!("192.168.1.{}:39001", i + 1).parse::<SocketAddr>().unwrap();
+        nodes4.push(Rc::new(NodeInfo::new(Id::random(), addr)));
+        let addr = format!("[fd00::{}]:39001", i + 1).parse::<SocketAddr>().unwrap();
+        nodes6.push(Rc::new(NodeInfo::new(Id::random(), addr)));
+    }
+
+    let mut msg = Message::new();
+    msg.populate_closest_nodes4(nodes4.clone());
+    msg.populate_closest_nodes6(nodes6.clone());
+    let msg = Rc::new(RefCell::new(Box::new(msg) as Box<dyn Msg>));
+    let full = serialize(msg.clone());
+
+    let limit = full.len() / 2;
+    let buf = serialize_within(msg.clone(), limit).unwrap();
+    assert!(buf.len() <= limit);
+
+    // The farthest nodes are dropped from both lists evenly.
+    let decoded = deser(&buf).unwrap();
+    let decoded = decoded.borrow();
+    let decoded = decoded.as_any().downcast_ref::<Message>().unwrap();
+    let len4 = decoded.nodes4().unwrap().len();
+    let len6 = decoded.nodes6().unwrap().len();
+    assert!(len4 < 8);
+    assert!(len4.abs_diff(len6) <= 1);
+    assert_eq!(decoded.nodes4().unwrap(), &nodes4[..len4]);
+    assert_eq!(decoded.nodes6().unwrap(), &nodes6[..len6]);
+
+    // Nothing left to drop for such a small limit.
+    assert!(serialize_within(msg.clone(), 8).is_none());
+}
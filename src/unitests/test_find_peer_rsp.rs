@@ -1,11 +1,16 @@
+use std::rc::Rc;
+use std::net::SocketAddr;
+use ciborium::Value as CVal;
 use crate::{
     signature,
     Id,
+    NodeInfo,
     PeerBuilder,
 };
 
 use crate::core::msg::{
     Msg,
+    lookup_rsp::Msg as LookupMsg,
     find_peer_rsp::Message,
 };
 
@@ -99,3 +104,63 @@ fn test_cbor_with_more_peers() {
     assert_eq!(msg.peers().len(), 2);
     assert_eq!(decoded_msg.peers().len(), 2);
 }
+
+#[test]
+fn test_cbor_without_peers() {
+    let msg = Message::new();
+    let cval = msg.ser();
+    let mut decoded_msg = Message::new();
+    let result = decoded_msg.from_cbor(&cval);
+    assert_eq!(result.is_some(), true);
+    assert_eq!(decoded_msg.peers().len(), 0);
+}
+
+#[test]
+fn test_trim() {
+    let nodeid = Id::random();
+    let mut peers = Vec::new();
+    for _ in 0..4 {
+        peers.push(PeerBuilder::new(&nodeid)
+            .with_port(65534)
+            .build());
+    }
+    let addr = "192.168.1.1:39001".parse::<SocketAddr>().unwrap();
+    let ni = Rc::new(NodeInfo::new(Id::random(), addr));
+
+    let mut msg = Message::new();
+    msg.populate_closest_nodes4(vec![ni.clone(), ni.clone()]);
+    msg.populate_peers(peers);
+
+    // The closest nodes are dropped ahead of the peers.
+    assert!(msg.trim());
+    assert!(msg.trim());
+    assert_eq!(msg.nodes4().unwrap().len(), 0);
+    assert_eq!(msg.peers().len(), 4);
+
+    for i in (0..4).rev() {
+        assert!(msg.trim());
+        assert_eq!(msg.peers().len(), i);
+    }
+    assert!(!msg.trim());
+}
+
+// The nodes before used to put an empty "p" into the responses without peers.
+#[test]
+fn test_cbor_with_empty_peers() {
+    let msg = Message::new();
+    let mut cval = msg.ser();
+    let rsp = cval.as_map_mut().unwrap().iter_mut()
+        .find(|(k, _)| k.as_text() == Some("r"))
+        .map(|(_, v)| v)
+        .unwrap();
+    assert_eq!(rsp.as_map().unwrap().iter().any(|(k, _)| k.as_text() == Some("p")), false);
+    rsp.as_map_mut().unwrap().push((
+        CVal::Text(String::from("p")),
+        CVal::Array(Vec::new())
+    ));
+
+    let mut decoded_msg = Message::new();
+    let result = decoded_msg.from_cbor(&cval);
+    assert_eq!(result.is_some(), true);
+    assert_eq!(decoded_msg.peers().len(), 0);
+}
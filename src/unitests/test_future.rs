@@ -0,0 +1,83 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::{
+    Id,
+    PeerBuilder,
+    Network,
+    LookupOptions,
+};
+
+use crate::core::future::{
+    Cmd,
+    Command,
+    CmdFuture,
+    FindNodeCmd,
+    FindPeerCmd,
+    PeerStream,
+};
+
+fn find_node_cmd() -> Arc<Mutex<FindNodeCmd>> {
+    let cmd = FindNodeCmd::new(&Id::random(), &LookupOptions::default());
+    Arc::new(Mutex::new(cmd))
+}
+
+#[test]
+fn test_drop_before_completion() {
+    let arc = find_node_cmd();
+    arc.lock().unwrap().add_task(Network::IPv4, 3);
+    arc.lock().unwrap().add_task(Network::IPv6, 4);
+
+    let canceled = Arc::new(Mutex::new(None));
+    let cloned = canceled.clone();
+    let future = CmdFuture::new(Command::FindNode(arc.clone()))
+        .with_cancel_fn(move |cmd| {
+            *cloned.lock().unwrap() = Some(cmd.tasks());
+        });
+
+    drop(future);
+    let tasks = canceled.lock().unwrap().take();
+    match tasks {
+        Some(tasks) => assert_eq!(tasks, vec![(Network::IPv4, 3), (Network::IPv6, 4)]),
+        None => panic!("Cancel function was not invoked"),
+    }
+}
+
+#[test]
+fn test_drop_after_completion() {
+    let arc = find_node_cmd();
+    arc.lock().unwrap().add_task(Network::IPv4, 5);
+
+    let canceled = Arc::new(Mutex::new(false));
+    let cloned = canceled.clone();
+    let future = CmdFuture::new(Command::FindNode(arc.clone()))
+        .with_cancel_fn(move |_| {
+            *cloned.lock().unwrap() = true;
+        });
+
+    arc.lock().unwrap().complete(Err(crate::Error::State("canceled".to_string())));
+    drop(future);
+    assert!(!*canceled.lock().unwrap());
+}
+
+#[tokio::test]
+async fn test_peer_stream() {
+    let nodeid = Id::random();
+    let peer1 = PeerBuilder::new(&nodeid).with_port(65531).build();
+    let peer2 = PeerBuilder::new(&nodeid).with_port(65530).build();
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let cmd = FindPeerCmd::new(peer1.id(), 0, &LookupOptions::default()).with_stream(tx);
+    let arc = Arc::new(Mutex::new(cmd));
+    let mut stream = PeerStream::new(rx, CmdFuture::new(Command::FindPeer(arc.clone())));
+
+    arc.lock().unwrap().stream_peer(&peer1);
+    assert_eq!(stream.next().await, Some(peer1.clone()));
+
+    // The peers streamed before completion are still delivered.
+    arc.lock().unwrap().stream_peer(&peer2);
+    arc.lock().unwrap().complete(Ok(vec![peer1, peer2.clone()]));
+    assert_eq!(stream.next().await, Some(peer2));
+    assert_eq!(stream.next().await, None);
+    assert_eq!(stream.next().await, None);
+}
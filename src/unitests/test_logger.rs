@@ -1,21 +1,60 @@
-use log::{info, debug, error};
+use std::{fs, thread};
+use log::{info, debug, error, LevelFilter};
 use crate::core::logger;
 
 #[test]
 fn test_logger() {
-    logger::setup(log::LevelFilter::Info, None);
+    let logger = logger::setup(LevelFilter::Info, None);
+    assert!(logger.is_some());
+    logger::enter(logger);
     info!("info: testing....");
     error!("debug: testing...");
-    assert!(true);
-    logger::teardown();
+    logger::enter(None);
 }
 
 #[test]
 fn test_logger_disable() {
-    logger::setup(log::LevelFilter::Info, None);
-    logger::revert_console_output();
+    let logger = logger::setup(LevelFilter::Info, None);
+    logger.as_ref().unwrap().revert_console_output();
+    logger::enter(logger);
     info!("info: testing....");
     debug!("debug: testing...");
-    assert!(true);
-    logger::teardown();
+    logger::enter(None);
+}
+
+#[test]
+fn test_logger_off() {
+    assert!(logger::setup(LevelFilter::Off, None).is_none());
+}
+
+#[test]
+fn test_logger_per_thread() {
+    let path1 = "logger1.log";
+    let path2 = "logger2.log";
+    _ = fs::remove_file(path1);
+    _ = fs::remove_file(path2);
+
+    let logger1 = logger::setup(LevelFilter::Info, Some(path1));
+    let logger2 = logger::setup(LevelFilter::Info, Some(path2));
+
+    thread::spawn(move || {
+        logger::enter(logger1);
+        info!("testing logger1");
+    }).join().unwrap();
+
+    // The first logger is gone, which must not affect the second one.
+    thread::spawn(move || {
+        logger::enter(logger2);
+        info!("testing logger2");
+    }).join().unwrap();
+
+    let log1 = fs::read_to_string(path1).unwrap();
+    let log2 = fs::read_to_string(path2).unwrap();
+    assert!(log1.contains("testing logger1"));
+    assert!(!log1.contains("testing logger2"));
+    assert!(log2.contains("testing logger2"));
+    assert!(!log2.contains("testing logger1"));
+
+    _ = fs::remove_file(path1);
+    _ = fs::remove_file(path2);
 }
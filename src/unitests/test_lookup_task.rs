@@ -0,0 +1,55 @@
+use std::rc::Rc;
+use std::net::SocketAddr;
+use std::collections::HashMap;
+
+use crate::{
+    Id,
+    NodeInfo,
+    LookupOptions,
+};
+use crate::core::task::lookup_task::LookupTaskData;
+
+fn nodes(count: usize, port: &mut u16) -> Vec<Rc<NodeInfo>> {
+    (0..count).map(|_| {
+        *port += 1;
+        let addr = format!("1.2.3.4:{}", port).parse::<SocketAddr>().unwrap();
+        Rc::new(NodeInfo::new(Id::random(), addr))
+    }).collect()
+}
+
+// Every response hands out the same nodes along with a few of its own, as
+// the adversarial nodes would do to draw all the paths to them. Each of the
+// nodes is still known to one path only.
+#[test]
+fn test_disjoint_paths() {
+    let mut data = LookupTaskData::new(Rc::new(Id::random()));
+    data.set_options(&LookupOptions::default().with_disjoint_paths(4));
+
+    let mut port = 39000;
+    let mut offered = nodes(16, &mut port);
+    data.add_candidates(offered.clone(), HashMap::new());
+
+    let shared = nodes(8, &mut port);
+    offered.extend(shared.iter().cloned());
+    let mut responses = 0;
+    while let Some(cn) = data.next_candidate() {
+        let id = cn.borrow().id().clone();
+        let (path, _) = data.take_responsed(&id).unwrap();
+        assert!(path < 4);
+
+        let mut carried = nodes(4, &mut port);
+        offered.extend(carried.iter().cloned());
+        carried.extend(shared.iter().cloned());
+        data.add_candidates(carried, HashMap::new());
+
+        responses += 1;
+        if responses == 64 {
+            break;
+        }
+    }
+
+    assert!(responses > 16);
+    for node in offered.iter() {
+        assert_eq!(data.paths_knowing(node), 1);
+    }
+}
@@ -22,6 +22,7 @@ use crate::{
     NodeInfo,
     CryptoBox,
     LookupOption,
+    LookupOptions,
     configuration as config,
     JointResult
 };
@@ -149,7 +150,7 @@ fn test_encrypt_into() {
         let result = node2.borrow_mut().decrypt_into(&node1.borrow().id(), &cipher);
         assert_eq!(result.is_ok(), true);
 
-        let decrypted = result.ok().unwrap();
+        let (decrypted, _, _) = result.ok().unwrap();
         assert_eq!(cipher.len() - CryptoBox::MAC_BYTES, decrypted.len());
         assert_eq!(plain.len(), decrypted.len());
         assert_eq!(plain, decrypted);
@@ -174,7 +175,7 @@ fn test_find_node() {
         );
         channel.lock().unwrap().push(&ni2);
 
-        let opt = LookupOption::Conservative;
+        let opt = LookupOptions::new(LookupOption::Conservative);
         let arc = Arc::new(Mutex::new(FindNodeCmd::new(&node2id, &opt)));
         let cmd = Command::FindNode(arc.clone());
         let channel = COMMAND_CHANNEL.as_ref().unwrap();
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Id;
+use crate::core::{
+    clock::{self, Clock, ManualClock},
+    msg::msg::Kind,
+    replay_window::ReplayWindow,
+};
+
+#[test]
+fn test_duplicate() {
+    let mut window = ReplayWindow::new();
+    let a = Id::random();
+    let b = Id::random();
+    let now = Some(clock::now());
+
+    assert!(window.check(&a, Kind::Request, 1, now).is_ok());
+    assert!(window.check(&a, Kind::Request, 1, now).is_err());
+    assert!(window.check(&a, Kind::Request, 1, None).is_err());
+
+    // Transaction ids are tracked per kind and per node.
+    assert!(window.check(&a, Kind::Response, 1, now).is_ok());
+    assert!(window.check(&b, Kind::Request, 1, now).is_ok());
+    assert!(window.check(&a, Kind::Request, 2, None).is_ok());
+    assert!(window.check(&a, Kind::Request, 2, now).is_err());
+
+    // Errors without transaction ids are not tracked.
+    assert!(window.check(&a, Kind::Error, 0, now).is_ok());
+    assert!(window.check(&a, Kind::Error, 0, now).is_ok());
+}
+
+#[test]
+fn test_stale() {
+    let mut window = ReplayWindow::new();
+    let id = Id::random();
+    let now = clock::now();
+
+    assert!(window.check(&id, Kind::Request, 1, Some(now - Duration::from_secs(6 * 60))).is_err());
+    assert!(window.check(&id, Kind::Request, 2, Some(now + Duration::from_secs(6 * 60))).is_err());
+    assert!(window.check(&id, Kind::Request, 3, Some(now - Duration::from_secs(4 * 60))).is_ok());
+    assert!(window.check(&id, Kind::Request, 4, Some(now + Duration::from_secs(4 * 60))).is_ok());
+}
+
+#[test]
+fn test_eviction() {
+    let mut window = ReplayWindow::new();
+    let id = Id::random();
+    let start = clock::now() - Duration::from_secs(60);
+
+    for i in 0..1024 {
+        let time = start + Duration::from_millis(i as u64);
+        assert!(window.check(&id, Kind::Request, i + 1, Some(time)).is_ok());
+    }
+
+    // The oldest transaction id is evicted, with packets sent no later
+    // than it rejected since.
+    assert!(window.check(&id, Kind::Request, 2000, Some(start + Duration::from_secs(1))).is_ok());
+    assert!(window.check(&id, Kind::Request, 1, Some(start)).is_err());
+    assert!(window.check(&id, Kind::Request, 3000, Some(start)).is_err());
+    assert!(window.check(&id, Kind::Request, 3001, Some(start + Duration::from_millis(1))).is_ok());
+    assert!(window.check(&id, Kind::Request, 2, Some(start + Duration::from_millis(1))).is_err());
+    assert!(window.check(&id, Kind::Request, 3002, Some(start + Duration::from_millis(2))).is_ok());
+}
+
+#[test]
+fn test_expire() {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+
+    let mut window = ReplayWindow::new();
+    let a = Id::random();
+    let b = Id::random();
+
+    assert!(window.check(&a, Kind::Request, 1, None).is_ok());
+    clock.advance(Duration::from_secs(4 * 60));
+    assert!(window.check(&b, Kind::Request, 1, Some(clock.now())).is_ok());
+    window.expire();
+    assert_eq!(window.size(), 2);
+
+    // The seen transaction ids are dropped along with idle nodes.
+    clock.advance(Duration::from_secs(2 * 60));
+    window.expire();
+    assert_eq!(window.size(), 1);
+    assert!(window.check(&a, Kind::Request, 1, None).is_ok());
+    assert!(window.check(&b, Kind::Request, 1, Some(clock.now())).is_err());
+    clock::reset();
+}
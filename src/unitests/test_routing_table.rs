@@ -1,12 +1,26 @@
 use std::rc::Rc;
+use std::sync::Arc;
 use std::cell::RefCell;
-use std::net::SocketAddr;
-use std::time::SystemTime;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime};
 
+use crate::unitests::{
+    working_path,
+    remove_working_path,
+};
 use crate::core::{
-    id::Id,
+    clock::{self, ManualClock},
+    constants,
+    subnet_of,
+    id::{Id, MIN_ID},
+    prefix::Prefix,
+    network::Network,
+    node_info::NodeInfo,
+    stats::Stats,
+    kclosest_nodes::KClosestNodes,
     kbucket_entry::KBucketEntry,
     routing_table::RoutingTable,
+    routing_snapshot::RoutingTableSnapshot,
 };
 
 #[test]
@@ -62,3 +76,520 @@ fn test_split() {
 
     assert_eq!(true, true);
 }
+
+#[test]
+fn test_snapshot() {
+    let id = Rc::new(Id::random());
+    let mut rt = RoutingTable::new(id.clone());
+
+    for i in 0..100 {
+        let id = Id::random();
+        let addr = format!("192.168.1.100:{}", i+1);
+        let addr = addr.parse::<SocketAddr>().unwrap();
+        let mut input = KBucketEntry::new(id.clone(), addr);
+        input.signal_response();
+        input.merge_request_time(SystemTime::now());
+
+        rt.put(Rc::new(RefCell::new(input)));
+    }
+
+    let snapshot = RoutingTableSnapshot::from(Network::IPv4, &rt);
+    assert_eq!(snapshot.network(), Network::IPv4);
+    assert_eq!(snapshot.buckets().len(), rt.size());
+    assert_eq!(snapshot.size_of_entries(), rt.size_of_entries());
+
+    for bucket in snapshot.buckets() {
+        for entry in bucket.entries() {
+            assert!(bucket.prefix().is_prefix_of(entry.node().id()));
+            assert!(entry.is_reachable());
+            assert_eq!(entry.failed_requests(), 0);
+            assert!(entry.last_seen() >= entry.created());
+        }
+    }
+
+    let json = serde_json::to_value(&snapshot).unwrap();
+    assert_eq!(json["network"], "v4");
+    assert_eq!(json["buckets"].as_array().unwrap().len(), rt.size());
+
+    // Formats encoding the length of the structs up front can read it back.
+    let mut buf = Vec::new();
+    ciborium::ser::into_writer(&snapshot, &mut buf).unwrap();
+    let cbor: ciborium::Value = ciborium::de::from_reader(buf.as_slice()).unwrap();
+    let fields = cbor.as_map().unwrap();
+    assert_eq!(fields.len(), 2);
+}
+
+fn fill(rt: &mut RoutingTable, count: usize) -> Vec<Rc<RefCell<KBucketEntry>>> {
+    let mut entries = Vec::new();
+    for i in 0..count {
+        let addr = format!("192.168.1.100:{}", i+1);
+        let addr = addr.parse::<SocketAddr>().unwrap();
+        let mut input = KBucketEntry::new(Id::random(), addr);
+        input.signal_response();
+        input.merge_request_time(clock::now());
+
+        rt.put(Rc::new(RefCell::new(input)));
+    }
+
+    for bucket in rt.buckets().values() {
+        entries.append(&mut bucket.borrow().entries());
+    }
+    entries
+}
+
+fn check_buckets(rt: &RoutingTable) {
+    let buckets: Vec<_> = rt.buckets().iter().collect();
+    for (prefix, bucket) in buckets.iter() {
+        for entry in bucket.borrow().entries() {
+            assert!(prefix.is_prefix_of(entry.borrow().id()));
+        }
+    }
+
+    // No siblings left that would fit in one bucket.
+    for pair in buckets.windows(2) {
+        let (p1, b1) = pair[0];
+        let (p2, b2) = pair[1];
+        if p1.is_sibling_of(p2) {
+            assert!(b1.borrow().size() + b2.borrow().size() > constants::MAX_ENTRIES_PER_BUCKET);
+        }
+    }
+}
+
+#[test]
+fn test_merge() {
+    let id = Rc::new(Id::random());
+    let mut rt = RoutingTable::new(id.clone());
+
+    let entries = fill(&mut rt, 1000);
+    assert!(rt.size() > 1);
+
+    // No entries are lost while the buckets are populated, though a full
+    // bucket might have been split off from an empty sibling.
+    rt.merge_buckets();
+    let buckets = rt.size();
+    assert!(buckets > 1);
+    assert_eq!(rt.size_of_entries(), entries.len());
+    check_buckets(&rt);
+    rt.merge_buckets();
+    assert_eq!(rt.size(), buckets);
+
+    // Part of the nodes have gone.
+    for entry in entries.iter().skip(entries.len() / 4) {
+        rt.remove(entry.borrow().id());
+    }
+    let remaining = rt.size_of_entries();
+    rt.merge_buckets();
+    assert!(rt.size() < buckets);
+    assert_eq!(rt.size_of_entries(), remaining);
+    check_buckets(&rt);
+
+    // Back to the single bucket when the entries left fit in it, the
+    // candidates promoted in place of the removed ones going as well.
+    let keep: Vec<_> = entries.iter().take(5).map(|v| v.borrow().id().clone()).collect();
+    loop {
+        let gone: Vec<_> = rt.buckets().values()
+            .flat_map(|v| v.borrow().entries())
+            .map(|v| v.borrow().id().clone())
+            .filter(|v| !keep.contains(v))
+            .collect();
+        if gone.is_empty() {
+            break;
+        }
+        gone.iter().for_each(|v| rt.remove(v));
+    }
+    rt.merge_buckets();
+    assert_eq!(rt.size(), 1);
+    assert_eq!(rt.size_of_entries(), 5);
+    for entry in entries.iter().take(5) {
+        assert!(rt.bucket_entry(entry.borrow().id()).is_some());
+    }
+
+    // And gets split again as it fills up.
+    fill(&mut rt, 1000);
+    rt.merge_buckets();
+    assert!(rt.size() > 1);
+    check_buckets(&rt);
+}
+
+#[test]
+fn test_merge_stale() {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+
+    let id = Rc::new(Id::random());
+    let mut rt = RoutingTable::new(id.clone());
+    let entries = fill(&mut rt, 1000);
+    assert!(rt.size() > 1);
+
+    // Entries needing replacement don't keep the buckets apart, but they
+    // are kept as long as there is room left.
+    clock.advance(Duration::from_secs(20 * 60));
+    for entry in entries.iter().skip(3) {
+        for _ in 0..constants::KBUCKET_MAX_TIMEOUTS + 1 {
+            entry.borrow_mut().signal_request_timeout();
+        }
+        assert!(entry.borrow().needs_replacement());
+    }
+
+    rt.merge_buckets();
+    assert_eq!(rt.size(), 1);
+    assert_eq!(rt.size_of_entries(), constants::MAX_ENTRIES_PER_BUCKET);
+    for entry in entries.iter().take(3) {
+        assert!(rt.bucket_entry(entry.borrow().id()).is_some());
+    }
+    clock::reset();
+}
+
+fn create_entry(id: Id, port: u16) -> Rc<RefCell<KBucketEntry>> {
+    create_entry_at(id, &format!("192.168.1.100:{}", port))
+}
+
+fn create_entry_at(id: Id, addr: &str) -> Rc<RefCell<KBucketEntry>> {
+    let mut entry = KBucketEntry::new(id, addr.parse::<SocketAddr>().unwrap());
+    entry.signal_response();
+    entry.merge_request_time(SystemTime::now());
+    Rc::new(RefCell::new(entry))
+}
+
+#[test]
+fn test_replacement_cache() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+
+    // The ids out of the branch to split into are turned away once the
+    // bucket is full, and kept as candidates.
+    let low = Prefix::new().split_branch(false);
+    let entries: Vec<_> = (0..12).map(|i| create_entry(low.random_id(), i + 1)).collect();
+    for entry in entries.iter() {
+        rt.put(entry.clone());
+    }
+    let bucket = rt.bucket(entries[0].borrow().id());
+    assert_eq!(rt.size(), 1);
+    assert_eq!(rt.size_of_entries(), constants::MAX_ENTRIES_PER_BUCKET);
+    assert_eq!(bucket.borrow().cache_entries().len(), 4);
+
+    // Seen again, the candidate is moved to the end of the cache.
+    let id = entries[8].borrow().id().clone();
+    rt.put(create_entry(id, 9));
+    let cache = bucket.borrow().cache_entries();
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache[3].borrow().id(), entries[8].borrow().id());
+
+    // The cache is bounded, dropping the least recently seen ones.
+    for i in 0..constants::MAX_CACHE_ENTRIES_PER_BUCKET {
+        rt.put(create_entry(low.random_id(), 100 + i as u16));
+    }
+    let cache = bucket.borrow().cache_entries();
+    assert_eq!(cache.len(), constants::MAX_CACHE_ENTRIES_PER_BUCKET);
+    assert!(cache.iter().all(|v| v.borrow().id() != entries[9].borrow().id()));
+
+    // The candidates go along with the split.
+    let high = Prefix::new().split_branch(true);
+    rt.put(create_entry(high.random_id(), 200));
+    assert_eq!(rt.size(), 2);
+    let bucket = rt.bucket(entries[0].borrow().id());
+    assert_eq!(bucket.borrow().cache_entries().len(), constants::MAX_CACHE_ENTRIES_PER_BUCKET);
+    assert!(rt.bucket(&high.random_id()).borrow().cache_entries().is_empty());
+}
+
+#[test]
+fn test_promotion() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let low = Prefix::new().split_branch(false);
+    let entries: Vec<_> = (0..11).map(|i| create_entry(low.random_id(), i + 1)).collect();
+    for entry in entries.iter() {
+        rt.put(entry.clone());
+    }
+    let bucket = rt.bucket(entries[0].borrow().id());
+    assert_eq!(bucket.borrow().cache_entries().len(), 3);
+
+    // The unresponsive entry is replaced with the latest candidate.
+    let id = entries[0].borrow().id().clone();
+    for _ in 0..constants::KBUCKET_MAX_TIMEOUTS {
+        rt.on_timeout(&id);
+        assert!(rt.bucket_entry(&id).is_some());
+    }
+    rt.on_timeout(&id);
+    assert!(rt.bucket_entry(&id).is_none());
+    assert!(rt.bucket_entry(entries[10].borrow().id()).is_some());
+    assert_eq!(rt.size_of_entries(), constants::MAX_ENTRIES_PER_BUCKET);
+    assert_eq!(bucket.borrow().cache_entries().len(), 2);
+
+    // And so is the removed one.
+    rt.remove(entries[1].borrow().id());
+    assert!(rt.bucket_entry(entries[9].borrow().id()).is_some());
+    assert_eq!(rt.size_of_entries(), constants::MAX_ENTRIES_PER_BUCKET);
+    assert_eq!(bucket.borrow().cache_entries().len(), 1);
+
+    // Candidates timing out are dropped from the cache.
+    let candidate = entries[8].borrow().id().clone();
+    candidate_timeouts(&mut rt, &candidate);
+    assert!(bucket.borrow().cache_entries().is_empty());
+
+    // No candidate left, the entry stays until it needs replacement.
+    let id = entries[2].borrow().id().clone();
+    for _ in 0..constants::KBUCKET_MAX_TIMEOUTS + 1 {
+        rt.on_timeout(&id);
+    }
+    assert!(rt.bucket_entry(&id).is_some());
+}
+
+#[test]
+fn test_timeout_backoff() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let entries: Vec<_> = (0..2).map(|i| create_entry(Id::random(), i + 1)).collect();
+    for entry in entries.iter() {
+        entry.borrow_mut().signal_round_trip(Duration::from_millis(50));
+        rt.put(entry.clone());
+    }
+
+    // Only the node timing out waits longer for the next calls.
+    let timeout = entries[1].borrow().rtt().timeout();
+    let id = entries[0].borrow().id().clone();
+    rt.on_timeout(&id);
+    assert_eq!(entries[0].borrow().rtt().timeout(), timeout * 2);
+    assert_eq!(entries[1].borrow().rtt().timeout(), timeout);
+}
+
+// The nodes the table has no room for, not even as candidates, are not
+// worth verifying.
+#[test]
+fn test_accepts() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let low = Prefix::new().split_branch(false);
+    let high = Prefix::new().split_branch(true);
+    assert!(rt.accepts(&low.random_id()));
+
+    let entries: Vec<_> = (0..8).map(|i| create_entry(low.random_id(), i + 1)).collect();
+    for entry in entries.iter() {
+        rt.put(entry.clone());
+    }
+    assert_eq!(rt.size(), 1);
+    assert_eq!(rt.size_of_entries(), constants::MAX_ENTRIES_PER_BUCKET);
+
+    // The full bucket takes the ones it would split for, and keeps the
+    // others as candidates as long as there is room in the cache.
+    assert!(rt.accepts(&low.random_id()));
+    for i in 0..constants::MAX_CACHE_ENTRIES_PER_BUCKET {
+        rt.put(create_entry(low.random_id(), 100 + i as u16));
+    }
+    assert!(!rt.accepts(&low.random_id()));
+    assert!(rt.accepts(&high.random_id()));
+
+    // Or the ones to replace its stale entries with.
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+    clock.advance(Duration::from_secs(20 * 60));
+    for _ in 0..constants::KBUCKET_MAX_TIMEOUTS + 1 {
+        entries[0].borrow_mut().signal_request_timeout();
+    }
+    assert!(entries[0].borrow().needs_replacement());
+    assert!(rt.accepts(&low.random_id()));
+    clock::reset();
+}
+
+// A contact only heard from through its requests is verified before being
+// promoted in place of an entry timing out.
+#[test]
+fn test_promote_incoming() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let low = Prefix::new().split_branch(false);
+    let entries: Vec<_> = (0..8).map(|i| create_entry(low.random_id(), i + 1)).collect();
+    for entry in entries.iter() {
+        rt.put(entry.clone());
+    }
+
+    // Put unverified as DHT::received does, which pings it as accepted.
+    let id = low.random_id();
+    let addr = "192.168.1.100:200".parse::<SocketAddr>().unwrap();
+    assert!(rt.accepts(&id));
+    rt.put(Rc::new(RefCell::new(KBucketEntry::new(id.clone(), addr))));
+    let bucket = rt.bucket(&id);
+    assert_eq!(bucket.borrow().cache_entries().len(), 1);
+    assert!(rt.accepts(&id));
+
+    // Not promoted before answering the ping.
+    let failing = entries[0].borrow().id().clone();
+    for _ in 0..constants::KBUCKET_MAX_TIMEOUTS + 1 {
+        rt.on_timeout(&failing);
+    }
+    assert!(rt.bucket_entry(&failing).is_some());
+    assert!(rt.bucket_entry(&id).is_none());
+
+    rt.put(create_entry_at(id.clone(), "192.168.1.100:200"));
+    rt.on_timeout(&failing);
+    assert!(rt.bucket_entry(&failing).is_none());
+    assert!(rt.bucket_entry(&id).is_some());
+    assert!(bucket.borrow().cache_entries().is_empty());
+}
+
+// The unverified candidates make room for the verified ones.
+#[test]
+fn test_cache_eviction() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let low = Prefix::new().split_branch(false);
+    for i in 0..8 {
+        rt.put(create_entry(low.random_id(), i + 1));
+    }
+
+    let unverified = low.random_id();
+    let addr = "192.168.1.100:100".parse::<SocketAddr>().unwrap();
+    rt.put(Rc::new(RefCell::new(KBucketEntry::new(unverified.clone(), addr))));
+    for i in 0..constants::MAX_CACHE_ENTRIES_PER_BUCKET {
+        rt.put(create_entry(low.random_id(), 200 + i as u16));
+    }
+
+    let cache = rt.bucket(&unverified).borrow().cache_entries();
+    assert_eq!(cache.len(), constants::MAX_CACHE_ENTRIES_PER_BUCKET);
+    assert!(cache.iter().all(|v| v.borrow().id() != &unverified));
+    assert!(!rt.accepts(&low.random_id()));
+}
+
+fn candidate_timeouts(rt: &mut RoutingTable, id: &Id) {
+    let clock = Arc::new(ManualClock::new());
+    clock::set(clock.clone());
+    clock.advance(Duration::from_secs(20 * 60));
+    for _ in 0..constants::KBUCKET_MAX_TIMEOUTS + 1 {
+        rt.on_timeout(id);
+    }
+    clock::reset();
+}
+
+#[test]
+fn test_persist_cache() {
+    let path = working_path("routing_table/");
+    let file = format!("{}dht.cache", path);
+
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let low = Prefix::new().split_branch(false);
+    let entries: Vec<_> = (0..11).map(|i| create_entry(low.random_id(), i + 1)).collect();
+    for entry in entries.iter() {
+        rt.put(entry.clone());
+    }
+    rt.save(&file);
+
+    let mut loaded = RoutingTable::new(Rc::new(Id::random()));
+    loaded.load(&file);
+    assert_eq!(loaded.size_of_entries(), constants::MAX_ENTRIES_PER_BUCKET);
+    let cache = loaded.bucket(entries[0].borrow().id()).borrow().cache_entries();
+    assert_eq!(cache.len(), 3);
+    for (i, entry) in cache.iter().enumerate() {
+        assert_eq!(entry.borrow().id(), entries[8 + i].borrow().id());
+    }
+
+    remove_working_path(&path);
+}
+
+fn subnet_entries(rt: &RoutingTable, subnet: &IpAddr) -> Vec<usize> {
+    let nobody = Id::random();
+    rt.buckets().values()
+        .map(|v| v.borrow().subnet_entries(subnet, &nobody))
+        .collect()
+}
+
+#[test]
+fn test_subnet_limits() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let stats = Rc::new(RefCell::new(Stats::new()));
+    rt.set_stats(stats.clone());
+
+    // Only two of the nodes from the same /24 network are taken.
+    for i in 1..=3 {
+        rt.put(create_entry_at(Id::random(), &format!("203.0.113.{}:39001", i)));
+    }
+    assert_eq!(rt.size_of_entries(), 2);
+    assert_eq!(stats.borrow().rejected_entries(), 1);
+
+    // Nor is the IPv6 /64 network told apart by the interface id.
+    for i in 1..=3 {
+        rt.put(create_entry_at(Id::random(), &format!("[2001:db8:1:2::{}]:39001", i)));
+    }
+    assert_eq!(rt.size_of_entries(), 4);
+    assert_eq!(stats.borrow().rejected_entries(), 2);
+
+    // Being seen again, an entry already taken is not limited.
+    let entry = rt.buckets().values().next().unwrap().borrow().entries()[0].clone();
+    let id = entry.borrow().id().clone();
+    let addr = entry.borrow().ni().socket_addr().to_string();
+    rt.put(create_entry_at(id, &addr));
+    assert_eq!(rt.size_of_entries(), 4);
+    assert_eq!(stats.borrow().rejected_entries(), 2);
+
+    // The other networks and the local ones are left alone.
+    rt.put(create_entry_at(Id::random(), "198.51.100.1:39001"));
+    for i in 1..=3 {
+        rt.put(create_entry_at(Id::random(), &format!("192.168.1.{}:39001", i)));
+    }
+    assert_eq!(rt.size_of_entries(), 8);
+    assert_eq!(stats.borrow().rejected_entries(), 2);
+}
+
+#[test]
+fn test_merge_subnet_limit() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let stats = Rc::new(RefCell::new(Stats::new()));
+    rt.set_stats(stats.clone());
+
+    fill(&mut rt, 500);
+    for i in 0..100 {
+        rt.put(create_entry_at(Id::random(), &format!("203.0.113.{}:39001", i + 1)));
+    }
+    let subnet = subnet_of(&"203.0.113.1:39001".parse().unwrap()).unwrap();
+
+    // Only the nodes from the subnet are left, two for each bucket.
+    loop {
+        let gone: Vec<_> = rt.buckets().values()
+            .flat_map(|v| v.borrow().entries())
+            .filter(|v| subnet_of(v.borrow().ni().socket_addr()).as_ref() != Some(&subnet))
+            .map(|v| v.borrow().id().clone())
+            .collect();
+        if gone.is_empty() {
+            break;
+        }
+        gone.iter().for_each(|v| rt.remove(v));
+    }
+    let remaining = rt.size_of_entries();
+    assert!(remaining > constants::BUCKET_SUBNET_LIMIT);
+
+    // Those turned away by the merged bucket are counted as rejected.
+    let rejected = stats.borrow().rejected_entries();
+    rt.merge_buckets();
+    assert_eq!(rt.size(), 1);
+    assert_eq!(rt.size_of_entries(), constants::BUCKET_SUBNET_LIMIT);
+    assert_eq!(stats.borrow().rejected_entries(), rejected + remaining - constants::BUCKET_SUBNET_LIMIT);
+}
+
+#[test]
+fn test_table_subnet_limit() {
+    let mut rt = RoutingTable::new(Rc::new(Id::random()));
+    let stats = Rc::new(RefCell::new(Stats::new()));
+    rt.set_stats(stats.clone());
+    rt.set_subnet_limits(2, 5);
+
+    fill(&mut rt, 500);
+    assert!(rt.size() > 1);
+    assert_eq!(stats.borrow().rejected_entries(), 0);
+
+    // The replacement candidates are counted as well.
+    for i in 0..100 {
+        rt.put(create_entry_at(Id::random(), &format!("203.0.113.{}:39001", i + 1)));
+    }
+    let subnet = subnet_of(&"203.0.113.1:39001".parse().unwrap()).unwrap();
+    let counts = subnet_entries(&rt, &subnet);
+    assert!(counts.iter().all(|v| *v <= 2));
+    assert!(counts.iter().sum::<usize>() <= 5);
+    assert!(stats.borrow().rejected_entries() >= 95);
+
+    // Nor do the closest nodes handed out take more than a bucket does. The
+    // target falls into the first bucket, from which the nodes are collected
+    // over the following buckets as well.
+    let rt = Rc::new(RefCell::new(rt));
+    let ni = Rc::new(NodeInfo::new(Id::random(), "192.168.1.1:39001".parse().unwrap()));
+    let mut kns = KClosestNodes::new(Rc::new(MIN_ID), ni, rt.clone(), 1000);
+    kns.fill(false);
+    let nodes = kns.as_nodes();
+    assert!(nodes.len() > constants::MAX_ENTRIES_PER_BUCKET);
+    let total = nodes.iter()
+        .filter(|v| subnet_of(v.socket_addr()).as_ref() == Some(&subnet))
+        .count();
+    assert!(total <= 2);
+}
@@ -0,0 +1,108 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::Duration;
+use std::net::SocketAddr;
+
+use crate::Id;
+use crate::core::{
+    constants,
+    kbucket_entry::KBucketEntry,
+    rtt_estimator::RttEstimator,
+};
+
+#[test]
+fn test_no_samples() {
+    let rtt = RttEstimator::new();
+    assert_eq!(rtt.samples(), 0);
+    assert_eq!(rtt.mean(), None);
+    assert_eq!(rtt.timeout(), Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX));
+}
+
+#[test]
+fn test_steady() {
+    let mut rtt = RttEstimator::new();
+    for _ in 0..20 {
+        rtt.update(Duration::from_millis(50));
+    }
+    assert_eq!(rtt.samples(), 20);
+    assert_eq!(rtt.mean(), Some(Duration::from_millis(50)));
+    assert_eq!(rtt.timeout(), Duration::from_millis(constants::RPC_CALL_TIMEOUT_MIN));
+
+    let mut rtt = RttEstimator::new();
+    rtt.update(Duration::from_millis(2000));
+    assert_eq!(rtt.timeout(), Duration::from_millis(6000));
+    for _ in 0..100 {
+        rtt.update(Duration::from_millis(2000));
+    }
+    assert!(rtt.timeout() < Duration::from_millis(2100));
+    assert!(rtt.timeout() >= Duration::from_millis(2000));
+}
+
+#[test]
+fn test_jitter() {
+    let mut steady = RttEstimator::new();
+    let mut jittery = RttEstimator::new();
+    for i in 0..50 {
+        steady.update(Duration::from_millis(500));
+        jittery.update(Duration::from_millis(if i % 2 == 0 { 200 } else { 800 }));
+    }
+
+    // Same mean, but the deviation makes the timeout longer.
+    let mean = jittery.mean().unwrap();
+    assert!(mean > Duration::from_millis(400) && mean < Duration::from_millis(600));
+    assert!(jittery.timeout() > steady.timeout());
+    assert!(jittery.timeout() > Duration::from_millis(1200));
+
+    // Never beyond the maximum.
+    let mut slow = RttEstimator::new();
+    slow.update(Duration::from_secs(30));
+    assert_eq!(slow.timeout(), Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX));
+}
+
+#[test]
+fn test_backoff() {
+    let mut rtt = RttEstimator::new();
+    rtt.backoff();
+    assert_eq!(rtt.timeout(), Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX));
+
+    rtt.update(Duration::from_millis(50));
+    let timeout = rtt.timeout();
+    rtt.backoff();
+    assert_eq!(rtt.timeout(), timeout * 2);
+    rtt.backoff();
+    assert_eq!(rtt.timeout(), timeout * 4);
+    for _ in 0..32 {
+        rtt.backoff();
+    }
+    assert_eq!(rtt.timeout(), Duration::from_millis(constants::RPC_CALL_TIMEOUT_MAX));
+
+    // Back to the measured one once answered again.
+    rtt.update(Duration::from_millis(50));
+    assert_eq!(rtt.timeout(), timeout);
+}
+
+#[test]
+fn test_merge_entry() {
+    let id = Id::random();
+    let addr = "192.168.1.2:39001".parse::<SocketAddr>().unwrap();
+
+    let mut entry = KBucketEntry::new(id.clone(), addr);
+    let mut other = KBucketEntry::new(id.clone(), addr);
+    other.signal_round_trip(Duration::from_millis(100));
+
+    entry.merge(Rc::new(RefCell::new(other)));
+    assert_eq!(entry.rtt().samples(), 1);
+    assert_eq!(entry.rtt().mean(), Some(Duration::from_millis(100)));
+
+    let mut other = KBucketEntry::new(id.clone(), addr);
+    other.signal_round_trip(Duration::from_millis(900));
+    entry.merge(Rc::new(RefCell::new(other)));
+    assert_eq!(entry.rtt().samples(), 2);
+    assert_eq!(entry.rtt().mean(), Some(Duration::from_millis(200)));
+
+    // The entry of another node is not merged.
+    let mut stranger = KBucketEntry::new(Id::random(), addr);
+    stranger.signal_round_trip(Duration::from_millis(10));
+    entry.merge(Rc::new(RefCell::new(stranger)));
+    assert_eq!(entry.rtt().samples(), 2);
+}
@@ -329,3 +329,54 @@ fn test_peers() {
 
     remove_storage(&path);
 }
+
+// Storing again what has been stored already, as the re-announcements do,
+// updates it rather than failing.
+#[test]
+#[serial]
+fn test_put_again() {
+    let (mut db, path) = get_storage();
+
+    let data = create_random_bytes(32);
+    let value = ValueBuilder::new(&data)
+        .build()
+        .expect("Failed to build value");
+    let result = db.put_value(&value, Some(0), Some(true), None);
+    assert_eq!(result.is_ok(), true);
+    let result = db.put_value(&value, Some(0), Some(true), None);
+    assert_eq!(result.is_ok(), true);
+    assert_eq!(db.value(&value.id()).ok().unwrap(), Some(value));
+
+    // The mutable value takes the newer sequence number.
+    let keypair = KeyPair::random();
+    let data1 = create_random_bytes(32);
+    let value1 = SignedBuilder::new(&data1)
+        .with_keypair(&keypair)
+        .with_sequence_number(1)
+        .build()
+        .expect("Failed to build value");
+    let data2 = create_random_bytes(32);
+    let value2 = SignedBuilder::new(&data2)
+        .with_keypair(&keypair)
+        .with_sequence_number(2)
+        .build()
+        .expect("Failed to build value");
+    assert_eq!(value1.id(), value2.id());
+    let result = db.put_value(&value1, None, Some(true), None);
+    assert_eq!(result.is_ok(), true);
+    let result = db.put_value(&value2, Some(1), Some(true), None);
+    assert_eq!(result.is_ok(), true);
+    assert_eq!(db.value(&value2.id()).ok().unwrap(), Some(value2));
+
+    let nodeid = Id::random();
+    let peer = PeerBuilder::new(&nodeid)
+        .with_port(39001)
+        .build();
+    let result = db.put_peer(&peer, Some(true), None);
+    assert_eq!(result.is_ok(), true);
+    let result = db.put_peer(&peer, Some(true), None);
+    assert_eq!(result.is_ok(), true);
+    assert_eq!(db.peer(&peer.id(), &nodeid).ok().unwrap(), Some(peer));
+
+    remove_storage(&path);
+}
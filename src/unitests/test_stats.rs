@@ -0,0 +1,74 @@
+use crate::core::{
+    stats::{Stats, Method, Kind},
+    msg::{
+        Msg,
+        ping_req,
+        ping_rsp,
+        find_node_req,
+        error_msg,
+    },
+};
+
+#[test]
+fn test_bytes() {
+    let mut stats = Stats::new();
+    assert_eq!(stats.received_bytes(), 0);
+    assert_eq!(stats.sent_bytes(), 0);
+
+    stats.on_received_bytes(100);
+    stats.on_received_bytes(20);
+    stats.on_sent_bytes(64);
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.received_bytes(), 120);
+    assert_eq!(snapshot.sent_bytes(), 64);
+    assert_eq!(snapshot.dropped_packets(), 0);
+    assert_eq!(snapshot.dropped_bytes(), 0);
+}
+
+#[test]
+fn test_msgs() {
+    let mut stats = Stats::new();
+
+    let ping = Box::new(ping_req::Message::new()) as Box<dyn Msg>;
+    let pong = Box::new(ping_rsp::Message::new()) as Box<dyn Msg>;
+    let find = Box::new(find_node_req::Message::new()) as Box<dyn Msg>;
+    let err  = Box::new(error_msg::Message::new(Method::FindNode, 1)) as Box<dyn Msg>;
+
+    stats.on_received_msg(ping.as_ref());
+    stats.on_received_msg(ping.as_ref());
+    stats.on_received_msg(err.as_ref());
+    stats.on_sent_msg(pong.as_ref());
+    stats.on_sent_msg(find.as_ref());
+    stats.on_timeout_msg(find.as_ref());
+
+    assert_eq!(stats.received_msgs(Method::Ping, Kind::Request), 2);
+    assert_eq!(stats.received_msgs(Method::Ping, Kind::Response), 0);
+    assert_eq!(stats.received_msgs(Method::FindNode, Kind::Error), 1);
+    assert_eq!(stats.total_received_msgs(), 3);
+
+    assert_eq!(stats.sent_msgs(Method::Ping, Kind::Response), 1);
+    assert_eq!(stats.sent_msgs(Method::FindNode, Kind::Request), 1);
+    assert_eq!(stats.total_sent_msgs(), 2);
+
+    assert_eq!(stats.timeout_msgs(Method::FindNode), 1);
+    assert_eq!(stats.timeout_msgs(Method::Ping), 0);
+    assert_eq!(stats.total_timeout_msgs(), 1);
+}
+
+#[test]
+fn test_dropped() {
+    let mut stats = Stats::new();
+    stats.on_dropped_packet(10);
+    stats.on_dropped_packet(32);
+
+    assert_eq!(stats.dropped_packets(), 2);
+    assert_eq!(stats.dropped_bytes(), 42);
+    assert_eq!(stats.replayed_packets(), 0);
+
+    stats.on_replayed_packet(8);
+    assert_eq!(stats.dropped_packets(), 3);
+    assert_eq!(stats.dropped_bytes(), 50);
+    assert_eq!(stats.replayed_packets(), 1);
+    assert!(!stats.to_string().is_empty());
+}
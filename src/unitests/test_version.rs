@@ -4,7 +4,7 @@ use crate::core::version;
 fn test_def_version() {
     let ver = version::ver();
     let ver_str = version::canonical_version(ver);
-    assert_eq!(ver_str, "Meerkat/1");
+    assert_eq!(ver_str, "Meerkat/2");
 }
 
 #[test]
@@ -26,3 +26,12 @@ fn test_na_version() {
     let ver_str = version::canonical_version(0);
     assert_eq!(ver_str, "N/A");
 }
+
+#[test]
+fn test_versioned_packet() {
+    assert!(version::supports_versioned_packet(version::ver()));
+    assert!(version::supports_versioned_packet(version::build("MK", 5)));
+    assert!(!version::supports_versioned_packet(version::build("MK", 1)));
+    assert!(!version::supports_versioned_packet(version::build("OR", 8)));
+    assert!(!version::supports_versioned_packet(0));
+}
@@ -13,6 +13,8 @@ use boson::{
  - with_ipv6
  - with_listening_port
  - with_storage_path
+ - with_max_datagram_size
+ - with_subnet_limits
  - add_bootstrap_node
  - add_bootstrap_nodes
  - load
@@ -23,6 +25,9 @@ use boson::{
  - addr6
  - listening_port
  - storage_path
+ - max_datagram_size
+ - bucket_subnet_limit
+ - table_subnet_limit
  - bootstra_nodes
  */
 #[test]
@@ -96,3 +101,59 @@ fn test_load_cfg() {
     assert_eq!(n4.ip().to_string(), "66.42.74.13");
     assert_eq!(n4.port(), 39001);
 }
+
+#[test]
+fn test_max_datagram_size() {
+    let cfg = configuration::Builder::new()
+        .with_ipv4("192.168.1.102")
+        .with_storage_path("tests")
+        .build()
+        .unwrap();
+    assert_eq!(cfg.max_datagram_size(), 65507);
+
+    let cfg = configuration::Builder::new()
+        .with_ipv4("192.168.1.102")
+        .with_storage_path("tests")
+        .with_max_datagram_size(1500)
+        .build()
+        .unwrap();
+    assert_eq!(cfg.max_datagram_size(), 1500);
+
+    for size in [1024, 65536] {
+        let result = configuration::Builder::new()
+            .with_ipv4("192.168.1.102")
+            .with_storage_path("tests")
+            .with_max_datagram_size(size)
+            .build();
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn test_subnet_limits() {
+    let cfg = configuration::Builder::new()
+        .with_ipv4("192.168.1.102")
+        .with_storage_path("tests")
+        .build()
+        .unwrap();
+    assert_eq!(cfg.bucket_subnet_limit(), 2);
+    assert_eq!(cfg.table_subnet_limit(), 10);
+
+    let cfg = configuration::Builder::new()
+        .with_ipv4("192.168.1.102")
+        .with_storage_path("tests")
+        .with_subnet_limits(4, 16)
+        .build()
+        .unwrap();
+    assert_eq!(cfg.bucket_subnet_limit(), 4);
+    assert_eq!(cfg.table_subnet_limit(), 16);
+
+    for (bucket, table) in [(0, 10), (4, 2)] {
+        let result = configuration::Builder::new()
+            .with_ipv4("192.168.1.102")
+            .with_storage_path("tests")
+            .with_subnet_limits(bucket, table)
+            .build();
+        assert!(result.is_err());
+    }
+}
@@ -7,6 +7,11 @@
 #[cfg(test)] mod signature;
 #[cfg(test)] mod cryptobox;
 #[cfg(test)] mod node;
+#[cfg(test)] mod node6;
+#[cfg(test)] mod transport;
+#[cfg(test)] mod simulation;
+#[cfg(test)] mod multi_node;
+#[cfg(test)] mod runtime;
 
 use std::env;
 use std::fs;
@@ -0,0 +1,97 @@
+use std::fs;
+use std::sync::Arc;
+use std::net::SocketAddr;
+use tokio::time::{Duration, sleep};
+use serial_test::serial;
+use log::LevelFilter;
+
+use boson::{
+    configuration as cfg,
+    Id,
+    Node,
+    NodeInfo,
+    Network,
+};
+use crate::{
+    working_path,
+    remove_working_path,
+    simulation::SimNetwork,
+};
+
+fn create_node(network: &Arc<SimNetwork>, addr: &SocketAddr, path: &str) -> Node {
+    let logfile = format!("{}/node.log", path);
+    let cfg = cfg::Builder::new()
+        .with_listening_port(addr.port())
+        .with_ipv4(&addr.ip().to_string())
+        .with_storage_path(path)
+        .with_log_level(LevelFilter::Info)
+        .with_log_file(&logfile)
+        .build()
+        .unwrap();
+
+    let node = Node::new(&cfg).unwrap();
+    let cloned = network.clone();
+    node.set_transport(move |addr| cloned.bind(addr));
+    node
+}
+
+#[tokio::test]
+#[serial]
+async fn test_multiple_nodes() {
+    let network = SimNetwork::new();
+    let addr1: SocketAddr = "10.0.2.1:39001".parse().unwrap();
+    let addr2: SocketAddr = "10.0.2.2:39001".parse().unwrap();
+
+    let path1 = working_path("multi1");
+    let path2 = working_path("multi2");
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+    let path1 = working_path("multi1");
+    let path2 = working_path("multi2");
+
+    let node1 = create_node(&network, &addr1, &path1);
+    let node2 = create_node(&network, &addr2, &path2);
+    node1.start().unwrap();
+    node2.start().unwrap();
+    node2.bootstrap(&NodeInfo::new(node1.id().clone(), addr1));
+
+    let mut found = false;
+    for _ in 0..50 {
+        let snapshot = node2.routing_table(Network::IPv4).await.unwrap();
+        found = snapshot.buckets().iter()
+            .flat_map(|v| v.entries().iter())
+            .any(|v| v.node().id() == node1.id());
+        if found {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(found);
+
+    match node2.find_node(node1.id(), None).await {
+        Ok(found) => assert_eq!(found.v4().unwrap().id(), node1.id()),
+        Err(e) => panic!("find_node failed: {}", e),
+    }
+
+    // Shutting down one node must leave the other one intact.
+    node1.stop();
+    drop(node1);
+
+    assert!(node2.find_node(&Id::random(), None).await.is_ok());
+    node2.stop();
+
+    let log1 = fs::read_to_string(format!("{}/node.log", path1)).unwrap();
+    let log2 = fs::read_to_string(format!("{}/node.log", path2)).unwrap();
+
+    assert!(log1.contains(&format!("Started DHT node on ipv4 address: {}", addr1)));
+    assert!(log1.contains(&format!("Stopped DHT node on ipv4 address: {}", addr1)));
+    assert!(!log1.contains(&format!("Started DHT node on ipv4 address: {}", addr2)));
+
+    assert!(log2.contains(&format!("Started DHT node on ipv4 address: {}", addr2)));
+    assert!(log2.contains(&format!("Stopped DHT node on ipv4 address: {}", addr2)));
+    assert!(!log2.contains(&format!("Started DHT node on ipv4 address: {}", addr1)));
+
+    drop(node2);
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+}
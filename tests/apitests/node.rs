@@ -1,17 +1,29 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::time::Duration;
+use tokio::time::Instant;
 use tokio::time::sleep;
 use serial_test::serial;
 
 use boson::{
     configuration as cfg,
     Id,
+    Error,
+    ErrorCode,
     NodeInfo,
     Node,
+    NodeEvent,
+    LookupOption,
+    LookupOptions,
+    NodeStatus,
+    EventStream,
+    Network,
     ValueBuilder,
     PeerBuilder,
     cryptobox::CryptoBox,
-    signature::Signature,
+    signature::{self, Signature},
+    stats::{Method, Kind},
+    ManualClock,
 };
 use crate::{
     create_random_bytes,
@@ -43,9 +55,11 @@ fn setup() {
         remove_working_path(PATH2.as_ref().unwrap().as_str());
         remove_working_path(PATH3.as_ref().unwrap().as_str());
 
+        // All the nodes run on the same host, so from the same subnet.
         let ipstr = ip.to_string();
         let cfg1 = cfg::Builder::new()
             .with_listening_port(32222)
+            .with_subnet_limits(8, 32)
             .with_ipv4(&ipstr)
             .with_storage_path(&PATH1.as_ref().unwrap())
             .build()
@@ -53,6 +67,7 @@ fn setup() {
 
         let cfg2 = cfg::Builder::new()
             .with_listening_port(32224)
+            .with_subnet_limits(8, 32)
             .with_ipv4(&ipstr)
             .with_storage_path(&PATH2.as_ref().unwrap())
             .build()
@@ -60,6 +75,7 @@ fn setup() {
 
         let cfg3 = cfg::Builder::new()
             .with_listening_port(32226)
+            .with_subnet_limits(8, 32)
             .with_ipv4(&ipstr)
             .with_storage_path(PATH3.as_ref().unwrap())
             .build()
@@ -69,9 +85,9 @@ fn setup() {
         NODE2 = Some(Node::new(&cfg2).unwrap());
         NODE3 = Some(Node::new(&cfg3).unwrap());
 
-        NODE1.as_mut().unwrap().start();
-        NODE2.as_mut().unwrap().start();
-        NODE3.as_mut().unwrap().start();
+        NODE1.as_mut().unwrap().start().unwrap();
+        NODE2.as_mut().unwrap().start().unwrap();
+        NODE3.as_mut().unwrap().start().unwrap();
 
         let id = NODE1.as_ref().unwrap().id().clone();
         let p  = NODE1.as_ref().unwrap().port();
@@ -284,6 +300,123 @@ async fn test_find_node() {
     teardown()
 }
 
+#[tokio::test]
+#[serial]
+async fn test_find_node_canceled() {
+    setup();
+    sleep(Duration::from_millis(3*1000)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+
+        // Give up the lookups long before they could be completed.
+        for _ in 0..8 {
+            let target = Id::random();
+            _ = tokio::time::timeout(
+                Duration::ZERO,
+                node1.find_node(&target, None)
+            ).await;
+        }
+        sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(node1.is_running(), true);
+        match node1.find_node(node2.id(), None).await {
+            Ok(found) => {
+                assert!(found.v4().is_some());
+                assert_eq!(found.v4().unwrap().id(), node2.id());
+            }
+            Err(e) => panic!("find_node failed after canceled lookups: {}", e),
+        }
+    }
+    teardown()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_lookup_options() {
+    setup();
+    sleep(Duration::from_millis(3*1000)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+
+        let options = LookupOptions::new(LookupOption::Conservative)
+            .with_alpha(1)
+            .with_width(2)
+            .with_max_hops(1);
+        assert_eq!(options.alpha(), 1);
+        assert_eq!(options.width(), 2);
+        assert_eq!(options.max_hops(), Some(1));
+        assert_eq!(options.disjoint_paths(), 1);
+        assert_eq!(options.clone().with_disjoint_paths(0).disjoint_paths(), 1);
+        assert_eq!(options.rpc_timeout(), None);
+        assert_eq!(options.clone().with_rpc_timeout(Duration::from_secs(1)).rpc_timeout(),
+            Some(Duration::from_secs(1)));
+
+        match node1.find_node_with(node2.id(), &options).await {
+            Ok(found) => assert_eq!(found.v4().unwrap().id(), node2.id()),
+            Err(e) => panic!("find_node_with failed: {}", e),
+        }
+
+        // Make node1 aware of a node which then goes away.
+        let ip = local_addr(true).unwrap();
+        let path = working_path("node7");
+        remove_working_path(&path);
+        let cfg = cfg::Builder::new()
+            .with_listening_port(32232)
+            .with_ipv4(&ip.to_string())
+            .with_storage_path(&path)
+            .build()
+            .unwrap();
+
+        let node7 = Node::new(&cfg).unwrap();
+        node7.start().unwrap();
+        node7.bootstrap(&NodeInfo::new(node1.id().clone(), SocketAddr::new(ip, node1.port())));
+
+        let mut found = false;
+        for _ in 0..50 {
+            let snapshot = node1.routing_table(Network::IPv4).await.unwrap();
+            found = snapshot.buckets().iter()
+                .flat_map(|v| v.entries().iter())
+                .any(|v| v.node().id() == node7.id());
+            if found {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert!(found);
+        node7.stop();
+        remove_working_path(&path);
+
+        // The lookups would wait for the full RPC timeout on the dead node.
+        let options = LookupOptions::new(LookupOption::Conservative)
+            .with_deadline(Duration::from_millis(500));
+        let started = Instant::now();
+        let result = node1.find_value_with(&Id::random(), &options).await;
+        assert!(result.is_ok());
+        assert!(result.unwrap().is_none());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let options = LookupOptions::new(LookupOption::Conservative)
+            .with_rpc_timeout(Duration::from_millis(1000));
+        let started = Instant::now();
+        let result = node1.find_peer_with(&Id::random(), None, &options).await;
+        assert!(result.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(4));
+
+        let options = LookupOptions::new(LookupOption::Conservative)
+            .with_deadline(Duration::from_millis(500));
+        let value = ValueBuilder::new(b"test lookup options").build().unwrap();
+        let started = Instant::now();
+        let result = node1.store_value_with(&value, None, &options).await;
+        assert!(result.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+    teardown()
+}
+
 #[tokio::test]
 #[serial]
 async fn test_store_value() {
@@ -317,8 +450,7 @@ async fn test_announce_peer() {
         let node = NODE1.as_mut().unwrap();
         assert_eq!(node.is_running(), true);
 
-        let nodeid = Id::random();
-        let peer = PeerBuilder::new(&nodeid)
+        let peer = PeerBuilder::new(node.id())
             .with_port(65534)
             .with_alternative_url(Some("http://announce.com"))
             .build();
@@ -327,6 +459,18 @@ async fn test_announce_peer() {
             Ok(_) => assert!(true),
             Err(_) => panic!("testcase failed")
         }
+
+        // A peer of another node is rejected by the remote nodes.
+        let nodeid = Id::random();
+        let peer = PeerBuilder::new(&nodeid)
+            .with_port(65534)
+            .build();
+
+        match node.announce_peer(&peer, None).await {
+            Err(Error::Remote { code, .. }) => assert_eq!(code, ErrorCode::Protocol),
+            Err(e) => panic!("Unexpected error: {}", e),
+            Ok(_) => panic!("Announced a peer of another node"),
+        }
     }
     teardown()
 }
@@ -443,6 +587,61 @@ async fn test_find_peer() {
     teardown()
 }
 
+#[tokio::test]
+#[serial]
+async fn test_find_peer_stream() {
+    setup();
+    sleep(Duration::from_secs(2)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+        let node3 = NODE3.as_mut().unwrap();
+
+        // The same peer served by two nodes.
+        let keypair = signature::KeyPair::random();
+        let peer1 = PeerBuilder::new(node1.id())
+            .with_keypair(Some(&keypair))
+            .with_port(65533)
+            .build();
+        let peer3 = PeerBuilder::new(node3.id())
+            .with_keypair(Some(&keypair))
+            .with_port(65532)
+            .build();
+        assert_eq!(peer1.id(), peer3.id());
+
+        assert!(node1.announce_peer(&peer1, None).await.is_ok());
+        assert!(node3.announce_peer(&peer3, None).await.is_ok());
+
+        let options = LookupOptions::new(LookupOption::Conservative);
+        let mut stream = match node2.find_peer_stream(peer1.id(), &options) {
+            Ok(v) => v,
+            Err(e) => panic!("Find peer stream error: {}", e),
+        };
+
+        let mut found = Vec::new();
+        while let Some(peer) = tokio::time::timeout(Duration::from_secs(30), stream.next())
+            .await
+            .expect("Peer stream did not end in time") {
+            assert_eq!(peer.id(), peer1.id());
+            assert!(peer.is_valid());
+            assert!(!found.contains(&peer), "Duplicated peer {}", peer);
+            found.push(peer);
+        }
+
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().any(|v| v.nodeid() == node1.id()));
+        assert!(found.iter().any(|v| v.nodeid() == node3.id()));
+
+        // Ended streams stay ended.
+        assert!(stream.next().await.is_none());
+
+        let result = node2.find_peer_stream(&Id::default(), &options);
+        assert!(result.is_err());
+    }
+    teardown()
+}
+
 #[tokio::test]
 #[serial]
 async fn test_get_value() {
@@ -687,3 +886,441 @@ async fn test_get_peer_ids() {
     }
     teardown()
 }
+
+#[tokio::test]
+#[serial]
+async fn test_benchmark_roundtrip() {
+    setup();
+    sleep(Duration::from_secs(2)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+
+        // Round-trip of a local command through the Node -> NodeRunner channel.
+        let rounds = 100;
+        let started = Instant::now();
+        for _ in 0..rounds {
+            assert!(node1.value_ids().await.is_ok());
+        }
+        let local = started.elapsed() / rounds;
+
+        // Round-trip of a lookup to a neighbor over the network.
+        let rounds = 10;
+        let started = Instant::now();
+        for _ in 0..rounds {
+            assert!(node1.find_node(node2.id(), None).await.is_ok());
+        }
+        let lookup = started.elapsed() / rounds;
+
+        println!("round-trip: local command {:?}, lookup {:?}", local, lookup);
+        assert!(local < Duration::from_millis(20));
+        assert!(lookup < Duration::from_millis(500));
+    }
+    teardown()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_stats() {
+    setup();
+    sleep(Duration::from_secs(2)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+
+        let result = node1.find_node(node2.id(), None).await;
+        assert!(result.is_ok());
+
+        let stats = match node1.stats().await {
+            Ok(v) => v,
+            Err(e) => panic!("Get stats error: {}", e),
+        };
+        println!("{}", stats);
+
+        assert!(stats.received_bytes() > 0);
+        assert!(stats.sent_bytes() > 0);
+        assert!(stats.received_msgs(Method::FindNode, Kind::Request) > 0);
+        assert!(stats.sent_msgs(Method::FindNode, Kind::Response) > 0);
+        assert!(stats.total_received_msgs() > 0);
+        assert!(stats.total_sent_msgs() > 0);
+        assert_eq!(stats.dropped_packets(), 0);
+        assert_eq!(stats.replayed_packets(), 0);
+    }
+    teardown()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_routing_table() {
+    setup();
+    sleep(Duration::from_secs(2)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+
+        let result = node1.find_node(node2.id(), None).await;
+        assert!(result.is_ok());
+
+        let snapshot = match node1.routing_table(Network::IPv4).await {
+            Ok(v) => v,
+            Err(e) => panic!("Get routing table error: {}", e),
+        };
+        println!("{}", snapshot);
+
+        assert_eq!(snapshot.network(), Network::IPv4);
+        assert!(snapshot.size_of_entries() > 0);
+
+        let found = snapshot.buckets().iter()
+            .flat_map(|v| v.entries().iter())
+            .any(|v| v.node().id() == node2.id());
+        assert!(found);
+
+        let json = serde_json::to_string(&snapshot);
+        assert!(json.is_ok());
+
+        let result = node1.routing_table(Network::IPv6).await;
+        assert!(result.is_err());
+    }
+    teardown()
+}
+
+async fn wait_event<F>(events: &mut EventStream, secs: u64, predicate: F) -> Option<NodeEvent>
+where F: Fn(&NodeEvent) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(secs);
+    loop {
+        match tokio::time::timeout_at(deadline, events.next()).await {
+            Ok(Some(event)) => if predicate(&event) {
+                return Some(event);
+            },
+            _ => return None,
+        }
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_subscribe() {
+    setup();
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+
+        let ip = local_addr(true).unwrap();
+        let path = working_path("node4");
+        remove_working_path(&path);
+
+        let cfg = cfg::Builder::new()
+            .with_listening_port(32228)
+            .with_ipv4(&ip.to_string())
+            .with_storage_path(&path)
+            .build()
+            .unwrap();
+
+        let node4 = Node::new(&cfg).unwrap();
+        let mut events4 = node4.subscribe();
+        let mut events1 = node1.subscribe();
+
+        node4.start().unwrap();
+        let event = wait_event(&mut events4, 1, |v| matches!(v, NodeEvent::StatusChanged(_))).await;
+        assert!(matches!(event, Some(NodeEvent::StatusChanged(NodeStatus::Initializing))));
+        let event = wait_event(&mut events4, 1, |v| matches!(v, NodeEvent::StatusChanged(_))).await;
+        assert!(matches!(event, Some(NodeEvent::StatusChanged(NodeStatus::Running))));
+
+        node4.bootstrap(&NodeInfo::new(node1.id().clone(), SocketAddr::new(ip, node1.port())));
+        let event = wait_event(&mut events4, 5, |v| matches!(v, NodeEvent::BootstrapCompleted(_))).await;
+        assert!(matches!(event, Some(NodeEvent::BootstrapCompleted(Network::IPv4))));
+
+        let event = wait_event(&mut events4, 5, |v| {
+            matches!(v, NodeEvent::EntryAdded(ni) if ni.id() == node1.id())
+        }).await;
+        assert!(event.is_some());
+
+        let data = create_random_bytes(32);
+        let value = ValueBuilder::new(&data)
+            .build()
+            .expect("Failed to build immutable value");
+
+        let result = node2.store_value(&value, None).await;
+        assert!(result.is_ok());
+
+        let event = wait_event(&mut events1, 5, |v| matches!(v, NodeEvent::ValueStored(_))).await;
+        match event {
+            Some(NodeEvent::ValueStored(v)) => assert_eq!(v.id(), value.id()),
+            _ => panic!("Missing value stored event"),
+        }
+
+        node4.stop();
+        let event = wait_event(&mut events4, 1, |v| matches!(v, NodeEvent::StatusChanged(_))).await;
+        assert!(matches!(event, Some(NodeEvent::StatusChanged(NodeStatus::Stopped))));
+
+        drop(node4);
+        remove_working_path(&path);
+    }
+    teardown()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_wait_ready() {
+    setup();
+
+    unsafe {
+        let node2 = NODE2.as_mut().unwrap();
+        let node3 = NODE3.as_mut().unwrap();
+
+        let result = node2.wait_ready(Duration::from_secs(5)).await;
+        assert!(result.is_ok());
+        let result = node3.wait_ready(Duration::from_secs(5)).await;
+        assert!(result.is_ok());
+
+        // Already ready, should resolve right away.
+        let started = Instant::now();
+        let result = node2.wait_ready(Duration::from_secs(5)).await;
+        assert!(result.is_ok());
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        let result = node2.find_node(node3.id(), None).await;
+        assert!(result.is_ok());
+    }
+    teardown()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_wait_ready_unreachable() {
+    let ip = local_addr(true).unwrap();
+    let path = working_path("node5");
+    remove_working_path(&path);
+
+    let cfg = cfg::Builder::new()
+        .with_listening_port(32230)
+        .with_ipv4(&ip.to_string())
+        .with_storage_path(&path)
+        .build()
+        .unwrap();
+
+    let node = Node::new(&cfg).unwrap();
+    let result = node.wait_ready(Duration::from_secs(1)).await;
+    assert!(result.is_err());   // not running yet.
+
+    node.start().unwrap();
+    match node.wait_ready(Duration::from_secs(1)).await {
+        Ok(_) => panic!("Should time out without bootstrap nodes"),
+        Err(e) => assert!(e.to_string().contains("bootstrap v4 pending")),
+    }
+
+    // Nobody is listening on the bootstrap node port.
+    let id = Id::from(signature::KeyPair::random().to_public_key());
+    node.bootstrap(&NodeInfo::new(id, SocketAddr::new(ip, 32238)));
+    match node.wait_ready(Duration::from_secs(20)).await {
+        Ok(_) => panic!("Should fail with unreachable bootstrap nodes"),
+        Err(e) => {
+            println!("{}", e);
+            assert!(e.to_string().contains("unreachable"));
+        }
+    }
+
+    node.stop();
+    remove_working_path(&path);
+}
+
+// The failed bootstrap on one network is told by the timeout error while the
+// other network is yet to be bootstrapped.
+#[tokio::test]
+#[serial]
+async fn test_wait_ready_partially_failed() {
+    let (ip4, ip6) = match (local_addr(true), local_addr(false)) {
+        (Some(ip4), Some(ip6)) => (ip4, ip6),
+        _ => return,    // not a dual-stack host.
+    };
+    let path = working_path("node6");
+    remove_working_path(&path);
+
+    let cfg = cfg::Builder::new()
+        .with_listening_port(32240)
+        .with_ipv4(&ip4.to_string())
+        .with_ipv6(&ip6.to_string())
+        .with_storage_path(&path)
+        .build()
+        .unwrap();
+
+    let node = Node::new(&cfg).unwrap();
+    node.start().unwrap();
+
+    // Nobody is listening on the bootstrap node port.
+    let id = Id::from(signature::KeyPair::random().to_public_key());
+    node.bootstrap(&NodeInfo::new(id, SocketAddr::new(ip6, 32248)));
+    match node.wait_ready(Duration::from_secs(20)).await {
+        Ok(_) => panic!("Should not be ready without any bootstrap completed"),
+        Err(e) => {
+            println!("{}", e);
+            assert!(e.to_string().contains("bootstrap v4 pending, v6 failed"));
+        }
+    }
+
+    node.stop();
+    remove_working_path(&path);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_start_failures() {
+    setup();
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let ip = local_addr(true).unwrap();
+        let path = working_path("node6");
+        remove_working_path(&path);
+
+        // Listening port already in use by node1.
+        let cfg = cfg::Builder::new()
+            .with_listening_port(node1.port())
+            .with_ipv4(&ip.to_string())
+            .with_storage_path(&path)
+            .build()
+            .unwrap();
+
+        let node = Node::new(&cfg).unwrap();
+        match node.start() {
+            Ok(_) => panic!("Should fail with the address in use"),
+            Err(e) => assert!(e.to_string().contains("Binding UDP socket")),
+        }
+        assert!(!node.is_running());
+        drop(node);
+
+        // Corrupted database.
+        let cfg = cfg::Builder::new()
+            .with_listening_port(32240)
+            .with_ipv4(&ip.to_string())
+            .with_storage_path(&path)
+            .build()
+            .unwrap();
+
+        let db_path = format!("{}/node.db", path);
+        std::fs::write(&db_path, create_random_bytes(4096)).unwrap();
+
+        let node = Node::new(&cfg).unwrap();
+        match node.start() {
+            Ok(_) => panic!("Should fail with the corrupted database"),
+            Err(e) => assert!(e.to_string().contains("node.db")),
+        }
+        assert!(!node.is_running());
+
+        // Unwritable database path.
+        std::fs::remove_file(&db_path).unwrap();
+        std::fs::create_dir(&db_path).unwrap();
+        assert!(node.start().is_err());
+        assert!(!node.is_running());
+
+        // Should be able to start once the problem is fixed.
+        std::fs::remove_dir(&db_path).unwrap();
+        assert!(node.start().is_ok());
+        assert!(node.is_running());
+        assert!(node.start().is_err());
+        node.stop();
+        drop(node);
+
+        // Unwritable data directory.
+        let file_path = format!("{}/file", path);
+        std::fs::write(&file_path, b"file").unwrap();
+        let cfg = cfg::Builder::new()
+            .with_listening_port(32240)
+            .with_ipv4(&ip.to_string())
+            .with_storage_path(&format!("{}/data", file_path))
+            .build()
+            .unwrap();
+        assert!(Node::new(&cfg).is_err());
+
+        remove_working_path(&path);
+    }
+    teardown()
+}
+
+// The bootstrapping is run by the regular update of the node, and given
+// up on the timeout of the call, both only falling due once the clock is
+// moved on. The node has nothing else to wake it up before the timeout.
+#[tokio::test]
+#[serial]
+async fn test_manual_clock() {
+    let ip = local_addr(true).unwrap();
+    let path = working_path("node7");
+    remove_working_path(&path);
+
+    let cfg = cfg::Builder::new()
+        .with_listening_port(32242)
+        .with_ipv4(&ip.to_string())
+        .with_storage_path(&path)
+        .build()
+        .unwrap();
+
+    let clock = Arc::new(ManualClock::new());
+    let node = Node::new(&cfg).unwrap();
+    node.set_clock(clock.clone());
+    let mut events = node.subscribe();
+
+    // No one is listening on the bootstrap address.
+    node.start().unwrap();
+    let id = Id::from(signature::KeyPair::random().to_public_key());
+    node.bootstrap(&NodeInfo::new(id, SocketAddr::new(ip, 32244)));
+    let event = wait_event(&mut events, 1, |v| matches!(v, NodeEvent::BootstrapFailed(_))).await;
+    assert!(event.is_none());
+
+    // Send the bootstrap request, then get past the first check of its
+    // timeout, leaving the next jobs due several seconds later.
+    clock.advance(Duration::from_secs(2));
+    sleep(Duration::from_secs(3)).await;
+    clock.advance(Duration::from_secs(2));
+    let event = wait_event(&mut events, 3, |v| matches!(v, NodeEvent::BootstrapFailed(_))).await;
+    assert!(event.is_none());
+
+    clock.advance(Duration::from_secs(10));
+    let event = wait_event(&mut events, 1, |v| matches!(v, NodeEvent::BootstrapFailed(_))).await;
+    assert!(matches!(event, Some(NodeEvent::BootstrapFailed(Network::IPv4))));
+
+    node.stop();
+    drop(node);
+    remove_working_path(&path);
+}
+
+// The persistent values and peers are announced again at the regular
+// interval, which must leave the node running.
+#[tokio::test]
+#[serial]
+async fn test_persistent_reannounce() {
+    let ip = local_addr(true).unwrap();
+    let path = working_path("node8");
+    remove_working_path(&path);
+
+    let cfg = cfg::Builder::new()
+        .with_listening_port(32250)
+        .with_ipv4(&ip.to_string())
+        .with_storage_path(&path)
+        .build()
+        .unwrap();
+
+    let clock = Arc::new(ManualClock::new());
+    let node = Node::new(&cfg).unwrap();
+    node.set_clock(clock.clone());
+    node.start().unwrap();
+
+    // Kept locally, with no other node to take them.
+    let value = ValueBuilder::new(b"persistent value").build().unwrap();
+    let peer = PeerBuilder::new(node.id()).with_port(65533).build();
+    _ = tokio::time::timeout(Duration::from_secs(5), node.store_value(&value, Some(true))).await;
+    _ = tokio::time::timeout(Duration::from_secs(5), node.announce_peer(&peer, Some(true))).await;
+
+    clock.advance(Duration::from_secs(5 * 60 + 1));
+    sleep(Duration::from_secs(1)).await;
+
+    let stats = tokio::time::timeout(Duration::from_secs(5), node.stats()).await;
+    assert!(stats.is_ok_and(|v| v.is_ok()));
+
+    node.stop();
+    drop(node);
+    remove_working_path(&path);
+}
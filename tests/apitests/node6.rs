@@ -0,0 +1,169 @@
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use tokio::time::Duration;
+use tokio::time::sleep;
+use serial_test::serial;
+
+use boson::{
+    configuration as cfg,
+    NodeInfo,
+    Node,
+    ValueBuilder,
+};
+use crate::{
+    create_random_bytes,
+    working_path,
+    remove_working_path,
+};
+
+static mut PATH1: Option<String> = None;
+static mut PATH2: Option<String> = None;
+static mut PATH3: Option<String> = None;
+
+static mut NODE1: Option<Node> = None;
+static mut NODE2: Option<Node> = None;
+static mut NODE3: Option<Node> = None;
+
+fn setup() {
+    unsafe {
+        let ip = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+        PATH1 = Some(working_path("node6_1"));
+        PATH2 = Some(working_path("node6_2"));
+        PATH3 = Some(working_path("node6_3"));
+
+        remove_working_path(PATH1.as_ref().unwrap().as_str());
+        remove_working_path(PATH2.as_ref().unwrap().as_str());
+        remove_working_path(PATH3.as_ref().unwrap().as_str());
+
+        // The nodes all listen on ::1, which is only taken on request.
+        let ipstr = ip.to_string();
+        let cfg1 = cfg::Builder::new()
+            .with_listening_port(32232)
+            .with_ipv6(&ipstr)
+            .with_loopback_allowed()
+            .with_storage_path(PATH1.as_ref().unwrap())
+            .build()
+            .unwrap();
+
+        let cfg2 = cfg::Builder::new()
+            .with_listening_port(32234)
+            .with_ipv6(&ipstr)
+            .with_loopback_allowed()
+            .with_storage_path(PATH2.as_ref().unwrap())
+            .build()
+            .unwrap();
+
+        let cfg3 = cfg::Builder::new()
+            .with_listening_port(32236)
+            .with_ipv6(&ipstr)
+            .with_loopback_allowed()
+            .with_storage_path(PATH3.as_ref().unwrap())
+            .build()
+            .unwrap();
+
+        NODE1 = Some(Node::new(&cfg1).unwrap());
+        NODE2 = Some(Node::new(&cfg2).unwrap());
+        NODE3 = Some(Node::new(&cfg3).unwrap());
+
+        NODE1.as_mut().unwrap().start().unwrap();
+        NODE2.as_mut().unwrap().start().unwrap();
+        NODE3.as_mut().unwrap().start().unwrap();
+
+        let id = NODE1.as_ref().unwrap().id().clone();
+        let p  = NODE1.as_ref().unwrap().port();
+        let ni = NodeInfo::new(id, SocketAddr::new(ip, p));
+
+        NODE2.as_mut().unwrap().bootstrap(&ni);
+        NODE3.as_mut().unwrap().bootstrap(&ni);
+    }
+}
+
+fn teardown() {
+    unsafe {
+        NODE1.take().unwrap().stop();
+        NODE2.take().unwrap().stop();
+        NODE3.take().unwrap().stop();
+
+        remove_working_path(PATH1.take().unwrap().as_str());
+        remove_working_path(PATH2.take().unwrap().as_str());
+        remove_working_path(PATH3.take().unwrap().as_str());
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_find_node_v6() {
+    setup();
+    sleep(Duration::from_millis(3*1000)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node2 = NODE2.as_mut().unwrap();
+        let node3 = NODE3.as_mut().unwrap();
+
+        assert!(node1.is_running());
+        assert!(node2.is_running());
+        assert!(node3.is_running());
+
+        let result = tokio::join!(
+            node1.find_node(node2.id(), None),
+            node2.find_node(node3.id(), None)
+        );
+
+        match result.0 {
+            Ok(found) => {
+                assert!(found.v4().is_none());
+                assert!(found.v6().is_some());
+
+                let ni = found.v6().unwrap();
+                assert!(ni.id() == node2.id());
+                assert!(ni.socket_addr().is_ipv6());
+            }
+            Err(e) => panic!("Find node error: {}", e),
+        }
+
+        match result.1 {
+            Ok(found) => {
+                assert!(found.v4().is_none());
+                assert!(found.v6().is_some());
+
+                let ni = found.v6().unwrap();
+                assert!(ni.id() == node3.id());
+                assert!(ni.socket_addr().is_ipv6());
+            }
+            Err(e) => panic!("Find node error: {}", e),
+        }
+    }
+    teardown()
+}
+
+#[tokio::test]
+#[serial]
+async fn test_find_value_v6() {
+    setup();
+    sleep(Duration::from_secs(2)).await;
+
+    unsafe {
+        let node1 = NODE1.as_mut().unwrap();
+        let node3 = NODE3.as_mut().unwrap();
+
+        let data = create_random_bytes(32);
+        let value = ValueBuilder::new(&data)
+            .build()
+            .expect("Failed to build immutable value");
+
+        if let Err(e) = node1.store_value(&value, None).await {
+            panic!("Store value error: {}", e);
+        }
+
+        match node3.find_value(&value.id(), None).await {
+            Ok(Some(v)) => {
+                assert_eq!(value.id(), v.id());
+                assert_eq!(value.data(), v.data());
+            },
+            Ok(None) => panic!("Should have found the value"),
+            Err(e) => panic!("Find value error: {}", e),
+        }
+    }
+    teardown()
+}
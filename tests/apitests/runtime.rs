@@ -0,0 +1,186 @@
+use std::sync::Arc;
+use std::net::SocketAddr;
+use tokio::runtime::Handle;
+use tokio::task::{self, LocalSet};
+use tokio::time::{Duration, sleep};
+use serial_test::serial;
+
+use boson::{
+    configuration as cfg,
+    Error,
+    Node,
+    NodeInfo,
+    Network,
+};
+use crate::{
+    working_path,
+    remove_working_path,
+    simulation::SimNetwork,
+};
+
+fn create_node(network: &Arc<SimNetwork>, addr: &SocketAddr, path: &str) -> Node {
+    let cfg = cfg::Builder::new()
+        .with_listening_port(addr.port())
+        .with_ipv4(&addr.ip().to_string())
+        .with_storage_path(path)
+        .build()
+        .unwrap();
+
+    let node = Node::new(&cfg).unwrap();
+    let cloned = network.clone();
+    node.set_transport(move |addr| cloned.bind(addr));
+    node
+}
+
+async fn wait_for_entry(node: &Node, other: &Node) {
+    for _ in 0..50 {
+        let snapshot = node.routing_table(Network::IPv4).await.unwrap();
+        let found = snapshot.buckets().iter()
+            .flat_map(|v| v.entries().iter())
+            .any(|v| v.node().id() == other.id());
+        if found {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Node {} is not in the routing table of {}", other.id(), node.id());
+}
+
+#[test]
+fn test_node_handle() {
+    fn assert_handle<T: Clone + Send + Sync + 'static>() {}
+    assert_handle::<Node>();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn test_start_on() {
+    let network = SimNetwork::new();
+    let addr1: SocketAddr = "10.0.3.1:39001".parse().unwrap();
+    let addr2: SocketAddr = "10.0.3.2:39001".parse().unwrap();
+    let path1 = working_path("runtime1");
+    let path2 = working_path("runtime2");
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+
+    let node1 = create_node(&network, &addr1, &path1);
+    let node2 = create_node(&network, &addr2, &path2);
+    node1.start_on(&Handle::current()).unwrap();
+    node2.start_on(&Handle::current()).unwrap();
+    assert!(node1.is_running());
+    assert!(node1.start_on(&Handle::current()).is_err());
+
+    node2.bootstrap(&NodeInfo::new(node1.id().clone(), addr1));
+    wait_for_entry(&node2, &node1).await;
+
+    // The handle is shared with another task as it is.
+    let cloned = node2.clone();
+    let target = node1.id().clone();
+    let found = tokio::spawn(async move {
+        cloned.find_node(&target, None).await
+    }).await.unwrap();
+    assert_eq!(found.unwrap().v4().unwrap().id(), node1.id());
+
+    node1.stop();
+    node2.stop();
+    assert!(!node1.is_running());
+    assert!(!node2.is_running());
+
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+}
+
+// The runtime of the test is a current_thread one, only the async pair
+// gets along with it.
+#[tokio::test]
+#[serial]
+async fn test_start_async() {
+    let network = SimNetwork::new();
+    let addr1: SocketAddr = "10.0.3.1:39001".parse().unwrap();
+    let addr2: SocketAddr = "10.0.3.2:39001".parse().unwrap();
+    let path1 = working_path("runtime1");
+    let path2 = working_path("runtime2");
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+
+    let node1 = create_node(&network, &addr1, &path1);
+    let node2 = create_node(&network, &addr2, &path2);
+    assert!(matches!(node1.start_on(&Handle::current()), Err(Error::Argument(_))));
+    assert!(!node1.is_running());
+
+    node1.start_async().await.unwrap();
+    node2.start_async().await.unwrap();
+    assert!(node1.is_running());
+    assert!(node1.start_async().await.is_err());
+
+    node2.bootstrap(&NodeInfo::new(node1.id().clone(), addr1));
+    wait_for_entry(&node2, &node1).await;
+
+    let found = node2.find_node(node1.id(), None).await.unwrap();
+    assert_eq!(found.v4().unwrap().id(), node1.id());
+
+    node1.stop_async().await;
+    node2.stop_async().await;
+    assert!(!node1.is_running());
+    assert!(!node2.is_running());
+
+    // The address is taken by node1 again.
+    node1.start_async().await.unwrap();
+    let node3 = create_node(&network, &addr1, &path2);
+    assert!(node3.start_async().await.is_err());
+    assert!(!node3.is_running());
+
+    node1.stop_async().await;
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_run() {
+    let network = SimNetwork::new();
+    let addr1: SocketAddr = "10.0.3.1:39001".parse().unwrap();
+    let addr2: SocketAddr = "10.0.3.2:39001".parse().unwrap();
+    let path1 = working_path("runtime1");
+    let path2 = working_path("runtime2");
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+
+    let node1 = create_node(&network, &addr1, &path1);
+    let node2 = create_node(&network, &addr2, &path2);
+    node1.start().unwrap();
+
+    let local = LocalSet::new();
+    local.run_until(async {
+        let cloned = node2.clone();
+        let running = task::spawn_local(async move {
+            cloned.run().await
+        });
+
+        for _ in 0..50 {
+            if node2.is_running() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert!(node2.is_running());
+
+        node2.bootstrap(&NodeInfo::new(node1.id().clone(), addr1));
+        wait_for_entry(&node2, &node1).await;
+
+        let found = node2.find_node(node1.id(), None).await.unwrap();
+        assert_eq!(found.v4().unwrap().id(), node1.id());
+
+        node2.stop();
+        assert!(running.await.unwrap().is_ok());
+    }).await;
+
+    // The address is taken by node1.
+    let node3 = create_node(&network, &addr1, &path2);
+    assert!(node3.run().await.is_err());
+    assert!(!node3.is_running());
+
+    node1.stop();
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+}
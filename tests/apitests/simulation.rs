@@ -0,0 +1,990 @@
+use std::io;
+use std::fs;
+use std::ops::Range;
+use std::future::Future;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration, Instant, MissedTickBehavior, sleep};
+use serial_test::serial;
+use ciborium::value::Value as CVal;
+
+use boson::{
+    configuration as cfg,
+    core::{signature, cryptobox},
+    id::ID_BYTES,
+    Id,
+    Node,
+    NodeInfo,
+    PeerInfo,
+    PeerBuilder,
+    Value,
+    ValueBuilder,
+    LookupOption,
+    LookupOptions,
+    Transport,
+    TransportFuture,
+    Clock,
+    ManualClock,
+};
+use crate::{
+    working_path,
+    remove_working_path,
+};
+
+type Datagram = (Vec<u8>, SocketAddr);
+// A datagram taken off the wire, as (from, to, data).
+type Captured = (SocketAddr, SocketAddr, Vec<u8>);
+
+struct Conditions {
+    latency: (Duration, Duration),
+    loss: f64,
+    // How long send_to stays pending before the datagram goes out.
+    send_stall: Duration,
+    partitions: HashMap<SocketAddr, usize>,
+    seed: u64,
+}
+
+impl Conditions {
+    // xorshift64, enough to make the node keys, the loss and the jitter
+    // reproducible.
+    fn next(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+
+    fn dropped(&mut self) -> bool {
+        self.loss > 0.0 && (self.next() % 10000) < (self.loss * 10000.0) as u64
+    }
+
+    fn delay(&mut self) -> Duration {
+        let (min, max) = self.latency;
+        if max <= min {
+            return min;
+        }
+        let range = (max - min).as_micros() as u64;
+        min + Duration::from_micros(self.next() % range)
+    }
+
+    fn reachable(&self, from: &SocketAddr, to: &SocketAddr) -> bool {
+        self.partitions.get(from) == self.partitions.get(to)
+    }
+}
+
+// In-memory network delivering datagrams between the transports bound on
+// it. The datagrams to unknown addresses, or across partitions, are
+// silently dropped as the real network would do.
+pub(crate) struct SimNetwork {
+    endpoints: Mutex<HashMap<SocketAddr, UnboundedSender<Datagram>>>,
+    conditions: Mutex<Conditions>,
+    // Datagrams sent since the capture started.
+    captured: Mutex<Option<Vec<Captured>>>,
+}
+
+impl SimNetwork {
+    pub(crate) fn new() -> Arc<Self> {
+        Self::with_seed(0x5eed_d47a_0b1e_c7ed)
+    }
+
+    pub(crate) fn with_seed(seed: u64) -> Arc<Self> {
+        Arc::new(Self {
+            endpoints: Mutex::new(HashMap::new()),
+            conditions: Mutex::new(Conditions {
+                latency: (Duration::ZERO, Duration::ZERO),
+                loss: 0.0,
+                send_stall: Duration::ZERO,
+                partitions: HashMap::new(),
+                seed: seed.max(1),
+            }),
+            captured: Mutex::new(None),
+        })
+    }
+
+    // Key pair drawn from the seeded source, for the simulated nodes to get
+    // the same ids from run to run.
+    pub(crate) fn keypair(&self) -> signature::KeyPair {
+        let mut conditions = self.conditions.lock().unwrap();
+        let seed = (0..signature::KeyPair::SEED_BYTES / 8)
+            .flat_map(|_| conditions.next().to_le_bytes())
+            .collect::<Vec<_>>();
+        signature::KeyPair::try_from_seed(&seed).unwrap()
+    }
+
+    pub(crate) fn set_latency(&self, min: Duration, max: Duration) {
+        self.conditions.lock().unwrap().latency = (min, max);
+    }
+
+    pub(crate) fn set_loss(&self, rate: f64) {
+        self.conditions.lock().unwrap().loss = rate.clamp(0.0, 1.0);
+    }
+
+    pub(crate) fn set_send_stall(&self, stall: Duration) {
+        self.conditions.lock().unwrap().send_stall = stall;
+    }
+
+    // Split the network into the groups given, the addresses not listed
+    // stay together in a group of their own.
+    pub(crate) fn partition(&self, groups: &[&[SocketAddr]]) {
+        let mut conditions = self.conditions.lock().unwrap();
+        conditions.partitions.clear();
+        groups.iter().enumerate().for_each(|(idx, group)| {
+            group.iter().for_each(|addr| {
+                conditions.partitions.insert(*addr, idx + 1);
+            });
+        });
+    }
+
+    pub(crate) fn heal(&self) {
+        self.conditions.lock().unwrap().partitions.clear();
+    }
+
+    pub(crate) fn start_capture(&self) {
+        *self.captured.lock().unwrap() = Some(Vec::new());
+    }
+
+    pub(crate) fn take_captured(&self) -> Vec<Captured> {
+        self.captured.lock().unwrap().take().unwrap_or_default()
+    }
+
+    pub(crate) fn bind(self: &Arc<Self>, addr: &SocketAddr) -> io::Result<Box<dyn Transport>> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if endpoints.get(addr).is_some_and(|v| !v.is_closed()) {
+            return Err(io::Error::from(io::ErrorKind::AddrInUse));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        endpoints.insert(*addr, tx);
+        Ok(Box::new(SimTransport {
+            addr: *addr,
+            network: self.clone(),
+            rx: tokio::sync::Mutex::new(rx),
+        }))
+    }
+
+    // Take the datagrams sent to the address without a transport, for the
+    // nodes played by the test itself.
+    pub(crate) fn attach(&self, addr: &SocketAddr) -> UnboundedReceiver<Datagram> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.endpoints.lock().unwrap().insert(*addr, tx);
+        rx
+    }
+
+    fn deliver(&self, from: &SocketAddr, to: &SocketAddr, data: &[u8]) {
+        if let Some(captured) = self.captured.lock().unwrap().as_mut() {
+            captured.push((*from, *to, data.to_vec()));
+        }
+
+        let delay = {
+            let mut conditions = self.conditions.lock().unwrap();
+            if !conditions.reachable(from, to) || conditions.dropped() {
+                return;
+            }
+            conditions.delay()
+        };
+
+        let tx = match self.endpoints.lock().unwrap().get(to) {
+            Some(v) => v.clone(),
+            None => return,
+        };
+
+        let datagram = (data.to_vec(), *from);
+        if delay.is_zero() {
+            _ = tx.send(datagram);
+            return;
+        }
+        tokio::spawn(async move {
+            sleep(delay).await;
+            _ = tx.send(datagram);
+        });
+    }
+}
+
+pub(crate) struct SimTransport {
+    addr: SocketAddr,
+    network: Arc<SimNetwork>,
+    rx: tokio::sync::Mutex<UnboundedReceiver<Datagram>>,
+}
+
+impl Transport for SimTransport {
+    fn send_to<'a>(&'a self, buf: &'a [u8], target: &'a SocketAddr) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            let stall = self.network.conditions.lock().unwrap().send_stall;
+            if !stall.is_zero() {
+                sleep(stall).await;
+            }
+            self.network.deliver(&self.addr, target, buf);
+            Ok(buf.len())
+        })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(async move {
+            let (data, from) = match self.rx.lock().await.recv().await {
+                Some(v) => v,
+                None => return Err(io::Error::from(io::ErrorKind::ConnectionAborted)),
+            };
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok((len, from))
+        })
+    }
+}
+
+// Step by which the virtual time of the simulated nodes is moved.
+const CLOCK_TICK: Duration = Duration::from_millis(10);
+
+// A group of nodes running in this process over the same SimNetwork, all
+// of them bootstrapping from the first one. The nodes share a ManualClock
+// moved forward by the harness at the real pace, as long as it gets to run,
+// so a loaded machine slows their time down rather than timing their calls
+// out.
+pub(crate) struct Simulation {
+    network: Arc<SimNetwork>,
+    nodes: Vec<Node>,
+    addrs: Vec<SocketAddr>,
+    paths: Vec<String>,
+    options: LookupOptions,
+    clock: Arc<ManualClock>,
+    ticker: JoinHandle<()>,
+}
+
+impl Simulation {
+    pub(crate) fn new(name: &str, network: Arc<SimNetwork>, count: usize) -> Self {
+        let clock = Arc::new(ManualClock::new());
+        let ticker = {
+            let clock = clock.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(CLOCK_TICK);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    clock.advance(CLOCK_TICK);
+                }
+            })
+        };
+
+        let mut sim = Self {
+            network,
+            nodes: Vec::with_capacity(count),
+            addrs: Vec::with_capacity(count),
+            paths: Vec::with_capacity(count),
+            options: LookupOptions::default(),
+            clock: clock.clone(),
+            ticker,
+        };
+
+        for idx in 0..count {
+            let ip = Ipv4Addr::new(10, 1, (idx / 250) as u8, (idx % 250 + 1) as u8);
+            let addr = SocketAddr::new(IpAddr::V4(ip), 39001);
+            let path = working_path(&format!("{}{}", name, idx));
+            remove_working_path(&path);
+
+            // Taken by the node as its persisted key.
+            let keypair = sim.network.keypair();
+            fs::create_dir_all(&path).unwrap();
+            fs::write(format!("{}/key", path), keypair.private_key().as_bytes()).unwrap();
+
+            let cfg = cfg::Builder::new()
+                .with_listening_port(addr.port())
+                .with_ipv4(&ip.to_string())
+                .with_storage_path(&path)
+                .build()
+                .unwrap();
+
+            let node = Node::new(&cfg).unwrap();
+            let network = sim.network.clone();
+            node.set_transport(move |addr| network.bind(addr));
+            node.set_clock(clock.clone());
+            node.start().unwrap();
+
+            sim.nodes.push(node);
+            sim.addrs.push(addr);
+            sim.paths.push(path);
+        }
+
+        let seed = NodeInfo::new(sim.nodes[0].id().clone(), sim.addrs[0]);
+        sim.nodes.iter().skip(1).for_each(|node| node.bootstrap(&seed));
+        sim
+    }
+
+    pub(crate) fn network(&self) -> &SimNetwork {
+        &self.network
+    }
+
+    // Options of the lookups issued while checking the convergence.
+    pub(crate) fn set_lookup_options(&mut self, options: LookupOptions) {
+        self.options = options;
+    }
+
+    // Current time on the clock of the nodes.
+    pub(crate) fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn node(&self, idx: usize) -> &Node {
+        &self.nodes[idx]
+    }
+
+    pub(crate) fn node_info(&self, idx: usize) -> NodeInfo {
+        NodeInfo::new(self.nodes[idx].id().clone(), self.addrs[idx])
+    }
+
+    pub(crate) fn addrs(&self, range: Range<usize>) -> Vec<SocketAddr> {
+        self.addrs[range].to_vec()
+    }
+
+    pub(crate) async fn wait_ready(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        for node in self.nodes.iter().skip(1) {
+            // The failed bootstraps are retried by the nodes themselves on
+            // a lossy network, keep waiting until the deadline.
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                match node.wait_ready(left).await {
+                    Ok(_) => break,
+                    Err(_) if Instant::now() + Duration::from_millis(200) < deadline => {
+                        sleep(Duration::from_millis(200)).await;
+                    },
+                    Err(e) => panic!("Simulated node is not ready: {}", e),
+                }
+            }
+        }
+    }
+
+    // Run the check on each of the source nodes, retrying the failed ones
+    // until all of them succeed or the timeout is reached.
+    pub(crate) async fn converge<'a, F, Fut>(&'a self,
+        sources: Range<usize>,
+        timeout: Duration,
+        mut check: F
+    ) -> bool
+    where F: FnMut(&'a Node) -> Fut, Fut: Future<Output = bool> + 'a
+    {
+        let deadline = Instant::now() + timeout;
+        let mut pending = sources.collect::<Vec<_>>();
+
+        while !pending.is_empty() && Instant::now() < deadline {
+            let mut left = Vec::new();
+            for idx in pending {
+                if !check(&self.nodes[idx]).await {
+                    left.push(idx);
+                }
+            }
+            pending = left;
+            if !pending.is_empty() {
+                sleep(Duration::from_millis(200)).await;
+            }
+        }
+        pending.is_empty()
+    }
+
+    pub(crate) async fn converge_on_node(&self,
+        target: usize,
+        sources: Range<usize>,
+        timeout: Duration
+    ) -> bool {
+        let id = self.nodes[target].id().clone();
+        let addr = self.addrs[target];
+        let options = &self.options;
+        self.converge(sources, timeout, |node| {
+            let id = id.clone();
+            async move {
+                match node.find_node_with(&id, options).await {
+                    Ok(v) => v.v4().is_some_and(|v| v.socket_addr() == &addr),
+                    Err(_) => false,
+                }
+            }
+        }).await
+    }
+
+    pub(crate) async fn converge_on_value(&self,
+        value: &Value,
+        sources: Range<usize>,
+        timeout: Duration
+    ) -> bool {
+        let options = &self.options;
+        self.converge(sources, timeout, |node| {
+            let value = value.clone();
+            async move {
+                match node.find_value_with(&value.id(), options).await {
+                    Ok(v) => v.is_some_and(|v| v == value),
+                    Err(_) => false,
+                }
+            }
+        }).await
+    }
+
+    pub(crate) async fn converge_on_peer(&self,
+        peer: &PeerInfo,
+        sources: Range<usize>,
+        timeout: Duration
+    ) -> bool {
+        let options = &self.options;
+        self.converge(sources, timeout, |node| {
+            let peer = peer.clone();
+            async move {
+                match node.find_peer_with(peer.id(), None, options).await {
+                    Ok(v) => v.iter().any(|v| v.id() == peer.id() && v.nodeid() == peer.nodeid()),
+                    Err(_) => false,
+                }
+            }
+        }).await
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.nodes.drain(..).for_each(|node| node.stop());
+        self.ticker.abort();
+        self.paths.iter().for_each(|path| remove_working_path(path));
+    }
+}
+
+// The key pair of an id sharing at least the prefix bits with the target.
+fn keypair_near(target: &Id, bits: usize) -> signature::KeyPair {
+    loop {
+        let keypair = signature::KeyPair::random();
+        let distance = Id::from(keypair.to_public_key()).distance(target);
+        let zeros = distance.as_bytes().iter()
+            .position(|v| *v != 0)
+            .map_or(ID_BYTES * 8, |idx| idx * 8 + distance.as_bytes()[idx].leading_zeros() as usize);
+        if zeros >= bits {
+            break keypair;
+        }
+    }
+}
+
+// Adversaries close to the target, answering the lookups with the same
+// bogus nodes, even closer to the target but never reachable.
+struct Adversaries {
+    bogus: Vec<NodeInfo>,
+    // Lookup requests received, by the adversary, the requesting node and
+    // the method.
+    queries: Mutex<HashMap<(SocketAddr, SocketAddr, i64), usize>>,
+}
+
+impl Adversaries {
+    fn spawn(network: &Arc<SimNetwork>, target: &Id, count: usize, peers: &[NodeInfo]) -> Arc<Self> {
+        let adversaries = Arc::new(Self {
+            bogus: (0..8).map(|idx| {
+                let id = Id::from(keypair_near(target, 12).to_public_key());
+                let ip = Ipv4Addr::new(10, 3, 0, idx + 1);
+                NodeInfo::new(id, SocketAddr::new(IpAddr::V4(ip), 39001))
+            }).collect(),
+            queries: Mutex::new(HashMap::new()),
+        });
+
+        (0..count).for_each(|idx| {
+            let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 2, 0, idx as u8 + 1)), 39001);
+            Adversary::new(network, &keypair_near(target, 8), addr, adversaries.clone())
+                .join(peers)
+                .run();
+        });
+        adversaries
+    }
+
+    fn on_query(&self, adversary: &SocketAddr, from: &SocketAddr, method: i64) {
+        *self.queries.lock().unwrap().entry((*adversary, *from, method)).or_default() += 1;
+    }
+
+    fn take_queries(&self) -> HashMap<(SocketAddr, SocketAddr, i64), usize> {
+        std::mem::take(&mut self.queries.lock().unwrap())
+    }
+}
+
+const PING: i64 = 0x01;
+const FIND_NODE: i64 = 0x02;
+const FIND_PEER: i64 = 0x04;
+const FIND_VALUE: i64 = 0x06;
+
+// A node played by the test itself on the SimNetwork. It only speaks the
+// legacy packet format, which the nodes keep to as it claims no version.
+struct LegacyEndpoint {
+    id: Id,
+    keypair: cryptobox::KeyPair,
+    addr: SocketAddr,
+    network: Arc<SimNetwork>,
+    boxes: HashMap<Id, (cryptobox::CryptoBox, cryptobox::Nonce)>,
+}
+
+impl LegacyEndpoint {
+    fn new(network: &Arc<SimNetwork>, keypair: &signature::KeyPair, addr: SocketAddr) -> Self {
+        Self {
+            id: Id::from(keypair.to_public_key()),
+            keypair: cryptobox::KeyPair::from(keypair),
+            addr,
+            network: network.clone(),
+            boxes: HashMap::new(),
+        }
+    }
+
+    // The sender and the message carried by the datagram.
+    fn open(&mut self, data: &[u8]) -> Option<(Id, CVal)> {
+        if data.len() <= ID_BYTES {
+            return None;
+        }
+        let sender = Id::try_from(&data[..ID_BYTES]).ok()?;
+        let plain = {
+            let (cbox, nonce) = self.crypto(&sender);
+            cbox.decrypt_into(&data[ID_BYTES..], nonce).ok()?
+        };
+        let msg = ciborium::de::from_reader::<CVal, _>(plain.as_slice()).ok()?;
+        Some((sender, msg))
+    }
+
+    fn send(&mut self, peer: &Id, to: &SocketAddr, plain: &[u8]) {
+        let mut data = self.id.as_bytes().to_vec();
+        let (cbox, nonce) = self.crypto(peer);
+        data.extend_from_slice(&cbox.encrypt_into(plain, nonce).unwrap());
+        self.network.deliver(&self.addr, to, &data);
+    }
+
+    // The legacy format boxes the packets with the static keys, under the
+    // nonce derived from the distance between both public keys.
+    fn crypto(&mut self, peer: &Id) -> &(cryptobox::CryptoBox, cryptobox::Nonce) {
+        let keypair = &self.keypair;
+        self.boxes.entry(peer.clone()).or_insert_with(|| {
+            let pk = peer.to_encryption_key();
+            let ours = Id::try_from(keypair.public_key().as_bytes()).unwrap();
+            let theirs = Id::try_from(pk.as_bytes()).unwrap();
+            let distance = Id::distance(&ours, &theirs);
+            (
+                cryptobox::CryptoBox::try_from((&pk, keypair.private_key())).unwrap(),
+                cryptobox::Nonce::try_from(&distance.as_bytes()[..cryptobox::Nonce::BYTES]).unwrap(),
+            )
+        })
+    }
+
+    fn message(_type: i64, txid: i64, key: &str, body: Option<CVal>) -> Vec<u8> {
+        let mut map = vec![
+            (CVal::Text("y".to_string()), CVal::Integer(_type.into())),
+            (CVal::Text("t".to_string()), CVal::Integer(txid.into())),
+            (CVal::Text("v".to_string()), CVal::Integer(0.into())),
+        ];
+        if let Some(body) = body {
+            map.push((CVal::Text(key.to_string()), body));
+        }
+
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&CVal::Map(map), &mut buf).unwrap();
+        buf
+    }
+}
+
+fn field<'a>(map: &'a CVal, key: &str) -> Option<&'a CVal> {
+    map.as_map()?.iter().find(|(k, _)| k.as_text() == Some(key)).map(|(_, v)| v)
+}
+
+// A malicious node answering all the lookups with the bogus nodes.
+struct Adversary {
+    endpoint: LegacyEndpoint,
+    adversaries: Arc<Adversaries>,
+    rx: Option<UnboundedReceiver<Datagram>>,
+    txid: i32,
+}
+
+impl Adversary {
+    fn new(network: &Arc<SimNetwork>,
+        keypair: &signature::KeyPair,
+        addr: SocketAddr,
+        adversaries: Arc<Adversaries>
+    ) -> Self {
+        Self {
+            endpoint: LegacyEndpoint::new(network, keypair, addr),
+            adversaries,
+            rx: Some(network.attach(&addr)),
+            txid: 0,
+        }
+    }
+
+    // Joins by pinging the honest nodes, which then ping it back to verify
+    // and take it into their routing tables.
+    fn join(mut self, peers: &[NodeInfo]) -> Self {
+        peers.iter().for_each(|peer| {
+            self.txid += 1;
+            let ping = LegacyEndpoint::message(0x20 | PING, self.txid as i64, "q", None);
+            self.endpoint.send(peer.id(), peer.socket_addr(), &ping);
+        });
+        self
+    }
+
+    fn run(mut self) {
+        let mut rx = self.rx.take().unwrap();
+        tokio::spawn(async move {
+            while let Some((data, from)) = rx.recv().await {
+                self.on_datagram(&data, &from);
+            }
+        });
+    }
+
+    fn on_datagram(&mut self, data: &[u8], from: &SocketAddr) {
+        let Some((sender, msg)) = self.endpoint.open(data) else {
+            return;
+        };
+
+        let field = |key: &str| -> Option<i64> {
+            i64::try_from(field(&msg, key)?.as_integer()?).ok()
+        };
+        let (Some(_type), Some(txid)) = (field("y"), field("t")) else {
+            return;
+        };
+        // Only the requests are answered, the responses to the pings sent
+        // while joining need nothing more.
+        if _type & 0x60 != 0x20 {
+            return;
+        }
+
+        let method = _type & 0x1F;
+        let result = match method {
+            PING => None,
+            FIND_NODE | FIND_PEER | FIND_VALUE => {
+                self.adversaries.on_query(&self.endpoint.addr, from, method);
+                let nodes = self.adversaries.bogus.iter().map(|node| {
+                    let ip = match node.ip() {
+                        IpAddr::V4(v) => v.octets().to_vec(),
+                        IpAddr::V6(v) => v.octets().to_vec(),
+                    };
+                    CVal::Array(vec![
+                        CVal::Bytes(node.id().as_bytes().to_vec()),
+                        CVal::Bytes(ip),
+                        CVal::Integer(node.port().into()),
+                    ])
+                }).collect();
+                Some(CVal::Map(vec![(CVal::Text("n4".to_string()), CVal::Array(nodes))]))
+            },
+            _ => return,
+        };
+
+        let reply = LegacyEndpoint::message(0x40 | method, txid, "r", result);
+        self.endpoint.send(&sender, from, &reply);
+    }
+}
+
+// Sends the requests to the nodes one at a time and takes their responses
+// as they are, to check what is actually put on the wire.
+struct Probe {
+    endpoint: LegacyEndpoint,
+    rx: UnboundedReceiver<Datagram>,
+    txid: i64,
+}
+
+impl Probe {
+    fn new(network: &Arc<SimNetwork>, addr: SocketAddr) -> Self {
+        Self {
+            endpoint: LegacyEndpoint::new(network, &signature::KeyPair::random(), addr),
+            rx: network.attach(&addr),
+            txid: 0,
+        }
+    }
+
+    // The result of the response to the request, the other messages
+    // received meanwhile are ignored.
+    async fn request(&mut self, node: &NodeInfo, method: i64, args: CVal) -> Option<CVal> {
+        self.txid += 1;
+        let req = LegacyEndpoint::message(0x20 | method, self.txid, "q", Some(args));
+        self.endpoint.send(node.id(), node.socket_addr(), &req);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let (data, _) = tokio::time::timeout(left, self.rx.recv()).await.ok()??;
+            let Some((_, msg)) = self.endpoint.open(&data) else {
+                continue;
+            };
+            let _type = field(&msg, "y").and_then(|v| v.as_integer()).and_then(|v| i64::try_from(v).ok());
+            let txid = field(&msg, "t").and_then(|v| v.as_integer()).and_then(|v| i64::try_from(v).ok());
+            if _type == Some(0x40 | method) && txid == Some(self.txid) {
+                return field(&msg, "r").cloned();
+            }
+        }
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sim_find_node() {
+    let sim = Simulation::new("sim_find_node", SimNetwork::new(), 64);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    for target in [0, sim.len() / 2, sim.len() - 1] {
+        assert!(sim.converge_on_node(target, 0..sim.len(), Duration::from_secs(30)).await);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sim_store_find_value() {
+    let sim = Simulation::new("sim_value", SimNetwork::new(), 32);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    let value = ValueBuilder::new(b"simulated value").build().unwrap();
+    match sim.node(7).store_value(&value, None).await {
+        Ok(_) => {},
+        Err(e) => panic!("Store value error: {}", e),
+    }
+    assert!(sim.converge_on_value(&value, 0..sim.len(), Duration::from_secs(30)).await);
+}
+
+// The value is far larger than the receive buffer used to be.
+#[tokio::test]
+#[serial]
+async fn test_sim_large_value() {
+    let sim = Simulation::new("sim_large", SimNetwork::new(), 16);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    let data = vec![0x5a; 16 * 1024];
+    let value = ValueBuilder::new(&data).build().unwrap();
+    match sim.node(3).store_value(&value, None).await {
+        Ok(_) => {},
+        Err(e) => panic!("Store value error: {}", e),
+    }
+    assert!(sim.converge_on_value(&value, 0..sim.len(), Duration::from_secs(30)).await);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sim_announce_find_peer() {
+    let sim = Simulation::new("sim_peer", SimNetwork::new(), 32);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    let peer = PeerBuilder::new(sim.node(11).id())
+        .with_port(65534)
+        .build();
+    match sim.node(11).announce_peer(&peer, None).await {
+        Ok(_) => {},
+        Err(e) => panic!("Announce peer error: {}", e),
+    }
+    assert!(sim.converge_on_peer(&peer, 0..sim.len(), Duration::from_secs(30)).await);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sim_lossy_network() {
+    let network = SimNetwork::new();
+    network.set_latency(Duration::from_millis(5), Duration::from_millis(30));
+    network.set_loss(0.05);
+
+    let options = LookupOptions::default()
+        .with_rpc_timeout(Duration::from_secs(1));
+    let mut sim = Simulation::new("sim_lossy", network, 32);
+    sim.set_lookup_options(options.clone());
+    sim.wait_ready(Duration::from_secs(60)).await;
+
+    assert!(sim.converge_on_node(sim.len() - 1, 0..sim.len(), Duration::from_secs(60)).await);
+
+    // The store itself may get lost on the way, retried as a user would do.
+    let value = ValueBuilder::new(b"lossy value").build().unwrap();
+    assert!(sim.converge(3..4, Duration::from_secs(60), |node| {
+        let value = value.clone();
+        let options = &options;
+        async move {
+            node.store_value_with(&value, None, options).await.is_ok()
+        }
+    }).await);
+    assert!(sim.converge_on_value(&value, 0..sim.len(), Duration::from_secs(60)).await);
+}
+
+// The deadline bounds the announcement to the closest nodes as well, not
+// only the lookup of them.
+#[tokio::test]
+#[serial]
+async fn test_sim_deadline() {
+    let sim = Simulation::new("sim_deadline", SimNetwork::new(), 16);
+    sim.wait_ready(Duration::from_secs(30)).await;
+    assert!(sim.converge_on_node(sim.len() - 1, 0..sim.len(), Duration::from_secs(30)).await);
+
+    sim.network().set_latency(Duration::from_millis(200), Duration::from_millis(200));
+    let options = LookupOptions::new(LookupOption::Conservative)
+        .with_deadline(Duration::from_millis(600));
+
+    let value = ValueBuilder::new(b"value in a hurry").build().unwrap();
+    let started = sim.now();
+    assert!(sim.node(3).store_value_with(&value, None, &options).await.is_ok());
+    assert!(sim.now().duration_since(started).unwrap() < Duration::from_millis(800));
+
+    let peer = PeerBuilder::new(sim.node(3).id()).with_port(8080).build();
+    let started = sim.now();
+    assert!(sim.node(3).announce_peer_with(&peer, None, &options).await.is_ok());
+    assert!(sim.now().duration_since(started).unwrap() < Duration::from_millis(800));
+}
+
+// The sends stay pending long enough to be canceled by the incoming
+// traffic, none of the datagrams may get lost that way.
+#[tokio::test]
+#[serial]
+async fn test_sim_stalled_sends() {
+    let network = SimNetwork::new();
+    network.set_send_stall(Duration::from_millis(2));
+
+    let sim = Simulation::new("sim_stalled", network, 16);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    assert!(sim.converge_on_node(sim.len() - 1, 0..sim.len(), Duration::from_secs(30)).await);
+    for idx in 0..sim.len() {
+        let stats = sim.node(idx).stats().await.unwrap();
+        assert!(stats.total_sent_msgs() > 0);
+        assert_eq!(stats.total_timeout_msgs(), 0);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sim_partition() {
+    let sim = Simulation::new("sim_partition", SimNetwork::new(), 32);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    // The nodes of each side have to know each other before being cut
+    // apart, rather than through the seed node left on the other side.
+    let half = sim.len() / 2;
+    assert!(sim.converge_on_node(1, 0..half, Duration::from_secs(60)).await);
+    assert!(sim.converge_on_node(half + 1, half..sim.len(), Duration::from_secs(60)).await);
+
+    let group1 = sim.addrs(0..half);
+    let group2 = sim.addrs(half..sim.len());
+    sim.network().partition(&[&group1, &group2]);
+
+    // Each side keeps working on its own.
+    assert!(sim.converge_on_node(1, 0..half, Duration::from_secs(60)).await);
+    assert!(sim.converge_on_node(half + 1, half..sim.len(), Duration::from_secs(60)).await);
+
+    let value = ValueBuilder::new(b"partitioned value").build().unwrap();
+    _ = sim.node(half + 2).store_value(&value, None).await;
+
+    // The value becomes visible to the other side once being healed.
+    sim.network().heal();
+    assert!(sim.converge_on_value(&value, 0..sim.len(), Duration::from_secs(60)).await);
+    assert!(sim.converge_on_node(half + 1, 0..half, Duration::from_secs(60)).await);
+}
+
+// A node holding no peers of the target answers with its closest nodes
+// instead, and only the nodes holding them answer with the peers.
+#[tokio::test]
+#[serial]
+async fn test_sim_find_peer_response() {
+    let network = SimNetwork::new();
+    let sim = Simulation::new("sim_peer_rsp", network.clone(), 16);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    let peer = PeerBuilder::new(sim.node(11).id())
+        .with_port(65534)
+        .build();
+    match sim.node(11).announce_peer(&peer, None).await {
+        Ok(_) => {},
+        Err(e) => panic!("Announce peer error: {}", e),
+    }
+
+    let mut probe = Probe::new(&network, SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 4, 0, 1)), 39001));
+    let args = |target: &Id| CVal::Map(vec![
+        (CVal::Text("t".to_string()), CVal::Bytes(target.as_bytes().to_vec())),
+        (CVal::Text("w".to_string()), CVal::Integer(0x01.into())),
+    ]);
+    let count = |rsp: &CVal, key: &str| field(rsp, key).and_then(|v| v.as_array()).map(|v| v.len());
+
+    let mut holders = 0;
+    for idx in 0..sim.len() {
+        let node = sim.node_info(idx);
+        let rsp = probe.request(&node, FIND_PEER, args(&Id::random())).await.unwrap();
+        assert!(count(&rsp, "n4").is_some_and(|v| v > 0));
+        assert_eq!(count(&rsp, "p"), None);
+
+        let rsp = probe.request(&node, FIND_PEER, args(peer.id())).await.unwrap();
+        match count(&rsp, "p") {
+            Some(v) => {
+                assert!(v > 0);
+                assert_eq!(count(&rsp, "n4"), None);
+                holders += 1;
+            },
+            None => assert!(count(&rsp, "n4").is_some_and(|v| v > 0)),
+        }
+    }
+    assert!(holders > 0);
+}
+
+// The adversaries answer the lookups with the bogus nodes, which keep any
+// path running into one of them busy until timed out. The other paths never
+// take the nodes known to that path, and keep going.
+#[tokio::test]
+#[serial]
+async fn test_sim_disjoint_paths() {
+    let network = SimNetwork::new();
+    let sim = Simulation::new("sim_disjoint", network.clone(), 32);
+    sim.wait_ready(Duration::from_secs(30)).await;
+
+    let peer = PeerBuilder::new(sim.node(11).id())
+        .with_port(65534)
+        .build();
+    match sim.node(11).announce_peer(&peer, None).await {
+        Ok(_) => {},
+        Err(e) => panic!("Announce peer error: {}", e),
+    }
+
+    let peers = (0..sim.len()).map(|idx| sim.node_info(idx)).collect::<Vec<_>>();
+    let adversaries = Adversaries::spawn(&network, peer.id(), 4, &peers);
+    sleep(Duration::from_secs(2)).await;
+
+    let options = LookupOptions::default()
+        .with_alpha(3)
+        .with_rpc_timeout(Duration::from_millis(200))
+        .with_disjoint_paths(4);
+    for idx in 0..sim.len() {
+        match sim.node(idx).find_peer_with(peer.id(), None, &options).await {
+            Ok(v) => assert!(v.iter().any(|v| v.id() == peer.id() && v.nodeid() == peer.nodeid())),
+            Err(e) => panic!("Find peer error: {}", e),
+        }
+    }
+
+    // None of the adversaries is queried twice by the same lookup, the
+    // nodes may run other lookups in the background.
+    let queries = adversaries.take_queries().into_iter()
+        .filter(|((_, _, method), _)| *method == FIND_PEER)
+        .collect::<Vec<_>>();
+    assert!(!queries.is_empty());
+    assert!(queries.iter().all(|(_, v)| *v == 1));
+}
+
+// The packets captured off the wire and sent again, handshakes included,
+// are all turned away without breaking the sessions between the nodes.
+#[tokio::test]
+#[serial]
+async fn test_sim_replayed_packets() {
+    let network = SimNetwork::new();
+    network.start_capture();
+    let sim = Simulation::new("sim_replay", network.clone(), 4);
+    sim.wait_ready(Duration::from_secs(30)).await;
+    assert!(sim.converge_on_node(0, 1..sim.len(), Duration::from_secs(30)).await);
+
+    let target = sim.addrs(0..1)[0];
+    // The replayed packets are counted as dropped ones as well.
+    let rejected = |stats: boson::Stats| stats.dropped_packets();
+    let before = rejected(sim.node(0).stats().await.unwrap());
+
+    let captured = network.take_captured().into_iter()
+        .filter(|(_, to, _)| to == &target)
+        .collect::<Vec<_>>();
+    assert!(!captured.is_empty());
+    for (from, to, data) in captured.iter() {
+        network.deliver(from, to, data);
+    }
+    sleep(Duration::from_secs(1)).await;
+
+    let after = rejected(sim.node(0).stats().await.unwrap());
+    assert_eq!(after - before, captured.len());
+
+    let options = LookupOptions::default().with_rpc_timeout(Duration::from_secs(1));
+    for idx in 1..sim.len() {
+        let id = sim.node(idx).id().clone();
+        match sim.node(0).find_node_with(&id, &options).await {
+            Ok(v) => assert_eq!(v.v4().unwrap().id(), &id),
+            Err(e) => panic!("find_node_with failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::net::SocketAddr;
+use tokio::time::{Duration, sleep};
+use serial_test::serial;
+
+use boson::{
+    configuration as cfg,
+    Node,
+    NodeInfo,
+    Network,
+};
+use crate::{
+    working_path,
+    remove_working_path,
+    simulation::SimNetwork,
+};
+
+#[tokio::test]
+#[serial]
+async fn test_memory_transport() {
+    let network = SimNetwork::new();
+    let addr1: SocketAddr = "10.0.0.1:39001".parse().unwrap();
+    let addr2: SocketAddr = "10.0.0.2:39002".parse().unwrap();
+
+    let path1 = working_path("transport1");
+    let path2 = working_path("transport2");
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+
+    let cfg1 = cfg::Builder::new()
+        .with_listening_port(addr1.port())
+        .with_ipv4(&addr1.ip().to_string())
+        .with_storage_path(&path1)
+        .build()
+        .unwrap();
+    let cfg2 = cfg::Builder::new()
+        .with_listening_port(addr2.port())
+        .with_ipv4(&addr2.ip().to_string())
+        .with_storage_path(&path2)
+        .build()
+        .unwrap();
+
+    let node1 = Node::new(&cfg1).unwrap();
+    let node2 = Node::new(&cfg2).unwrap();
+
+    let cloned = network.clone();
+    node1.set_transport(move |addr| cloned.bind(addr));
+    let cloned = network.clone();
+    node2.set_transport(move |addr| cloned.bind(addr));
+
+    node1.start().unwrap();
+    node2.start().unwrap();
+    node2.bootstrap(&NodeInfo::new(node1.id().clone(), addr1));
+
+    let mut found = false;
+    for _ in 0..50 {
+        let snapshot = node1.routing_table(Network::IPv4).await.unwrap();
+        found = snapshot.buckets().iter()
+            .flat_map(|v| v.entries().iter())
+            .any(|v| v.node().id() == node2.id());
+        if found {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(found);
+
+    match node2.find_node(node1.id(), None).await {
+        Ok(found) => {
+            let ni = found.v4().unwrap();
+            assert_eq!(ni.id(), node1.id());
+            assert_eq!(ni.socket_addr(), &addr1);
+        },
+        Err(e) => panic!("find_node failed: {}", e),
+    }
+
+    // The address is taken by node1 on the same network.
+    let node3 = Node::new(&cfg1).unwrap();
+    let cloned = network.clone();
+    node3.set_transport(move |addr| cloned.bind(addr));
+    assert!(node3.start().is_err());
+    drop(node3);
+
+    node1.stop();
+    node2.stop();
+    remove_working_path(&path1);
+    remove_working_path(&path2);
+}